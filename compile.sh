@@ -1,17 +1,20 @@
-# $1 height, $2 width
+# La taille de la carte et ses paramètres viennent de la carte choisie dans
+# `main`, qui écrit la dernière ligne de chaque circuit et le manifeste. Avec
+# WESNOTH_ZKPSI_ARTIFACTS, les circuits sont compilés dans le jeu de cette carte
+# sous ce dossier, sinon dans wesnoth-zkpsi.
 
-function change_last_line () {
+function compile () {
   pushd $1;
-    sed -i '$ d' $2;
-    echo "$4" >> $2;
-    circom -l ~/Téléchargements/zkpsi/circomlib/circuits/ --c --r1cs --O2 --prime bn128 $2;
+    circom -l ~/Téléchargements/zkpsi/circomlib/circuits/ --c --r1cs --sym --O2 --prime bn128 $2;
     make -j12 -C $3;
   popd;
 }
 
-pushd wesnoth-zkpsi
-  change_last_line hash hash_state.circom hash_state_cpp $"component main = Main(($1 "'*'" $2) "'*'" 4 + 3);"
-  change_last_line phase1 circuit.circom circuit_cpp $"component main {public [degats, captures]} = Final($1 "'*'" $2, $1, $2 , 10, 0, 1, 64, 8, [50,90,5,45,54,94,9,49], 9, [0,58,32,26,33,38,42,32,18], [0,5,5,6,7,5,4,8,5], [0,-1,14,17,14,12,13,17,9], 2, [0, 99], 6, [[0,1], [0,10], [0,20], [1,89], [1,98], [1,79]]);"
-  change_last_line phase2nova circuit.circom circuit_cpp $"component main {public [step_in]} = Final($1 "'*'" $2, $1, $2, 10, 0, 1, 8, [50,90,5,45,54,94,9,49], 9, [0,58,32,26,33,38,42,32,18], [0,5,5,6,7,5,4,8,5], [0,-1,14,17,14,12,13,17,9], 2, [0, 99], 6, [[0,1], [0,10], [0,20], [1,89], [1,98], [1,79]]);"
-  change_last_line phase3 circuit.circom circuit_cpp $"component main = Final($1 "'*'" $2,0,1);"
-popd
\ No newline at end of file
+artifacts=$(cargo run -q -- circuits) || exit 1
+
+pushd "$artifacts"
+  compile hash hash_state.circom hash_state_cpp
+  compile phase1 circuit.circom circuit_cpp
+  compile phase2nova circuit.circom circuit_cpp
+  compile phase3 circuit.circom circuit_cpp
+popd
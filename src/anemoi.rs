@@ -0,0 +1,219 @@
+//! Éponge Anemoi des circuits, recalculée hors des circuits.
+//!
+//! Transcription de `AnemoiSponge127` (`phase2nova/sponge.circom`) : un rate et
+//! une capacité de 1, 19 tours, une Flystel ouverte avec `beta` le générateur
+//! 5, `gamma` son inverse et `delta` nul. Sert à recalculer la chaîne des
+//! échanges qu'une preuve Nova donne en sortie.
+//!
+//! L'exposant passé au circuit comme l'inverse de 11 vaut en fait 5/11 modulo
+//! `p - 1`. Il reste inversible, et on le reprend tel quel pour obtenir les
+//! mêmes hachages que le circuit.
+
+use halo2curves::bn256::Fr;
+use halo2curves::ff::Field;
+use num_bigint::BigUint;
+
+use crate::convert::decimal_to_fr;
+
+/// Constantes de tour ajoutées à `X`, dans l'ordre des tours.
+const C: [&str; 19] = [
+    "37",
+    "13352247125433170118601974521234241686699252132838635793584252509352796067497",
+    "8959866518978803666083663798535154543742217570455117599799616562379347639707",
+    "3222831896788299315979047232033900743869692917288857580060845801753443388885",
+    "11437915391085696126542499325791687418764799800375359697173212755436799377493",
+    "14725846076402186085242174266911981167870784841637418717042290211288365715997",
+    "3625896738440557179745980526949999799504652863693655156640745358188128872126",
+    "463291105983501380924034618222275689104775247665779333141206049632645736639",
+    "17443852951621246980363565040958781632244400021738903729528591709655537559937",
+    "10761214205488034344706216213805155745482379858424137060372633423069634639664",
+    "1555059412520168878870894914371762771431462665764010129192912372490340449901",
+    "7985258549919592662769781896447490440621354347569971700598437766156081995625",
+    "9570976950823929161626934660575939683401710897903342799921775980893943353035",
+    "17962366505931708682321542383646032762931774796150042922562707170594807376009",
+    "12386136552538719544323156650508108618627836659179619225468319506857645902649",
+    "21184636178578575123799189548464293431630680704815247777768147599366857217074",
+    "3021529450787050964585040537124323203563336821758666690160233275817988779052",
+    "7005374570978576078843482270548485551486006385990713926354381743200520456088",
+    "3870834761329466217812893622834770840278912371521351591476987639109753753261",
+];
+
+/// Constantes de tour ajoutées à `Y`, dans l'ordre des tours.
+const D: [&str; 19] = [
+    "8755297148735710088898562298102910035419345760166413737479281674630323398284",
+    "5240474505904316858775051800099222288270827863409873986701694203345984265770",
+    "9012679925958717565787111885188464538194947839997341443807348023221726055342",
+    "21855834035835287540286238525800162342051591799629360593177152465113152235615",
+    "11227229470941648605622822052481187204980748641142847464327016901091886692935",
+    "8277823808153992786803029269162651355418392229624501612473854822154276610437",
+    "20904607884889140694334069064199005451741168419308859136555043894134683701950",
+    "1902748146936068574869616392736208205391158973416079524055965306829204527070",
+    "14452570815461138929654743535323908350592751448372202277464697056225242868484",
+    "10548134661912479705005015677785100436776982856523954428067830720054853946467",
+    "17068729307795998980462158858164249718900656779672000551618940554342475266265",
+    "16199718037005378969178070485166950928725365516399196926532630556982133691321",
+    "19148564379197615165212957504107910110246052442686857059768087896511716255278",
+    "5497141763311860520411283868772341077137612389285480008601414949457218086902",
+    "18379046272821041930426853913114663808750865563081998867954732461233335541378",
+    "7696001730141875853127759241422464241772355903155684178131833937483164915734",
+    "963844642109550260189938374814031216012862679737123536423540607519656220143",
+    "12412434690468911461310698766576920805270445399824272791985598210955534611003",
+    "6971318955459107915662273112161635903624047034354567202210253298398705502050",
+];
+
+/// Racine primitive du corps, `beta` de la Flystel.
+const GENERATOR: u64 = 5;
+
+/// Exposant de la Flystel ouverte, tel que le circuit le reçoit.
+const INVERSE_EXPONENT: &str =
+    "7959361044305190989907783907366281850381223418333103397708437886027566725679";
+
+/// Permutation `Anemoi(1, 19, 11, INVERSE_EXPONENT)` avec ses constantes.
+struct Permutation {
+    c: Vec<Fr>,
+    d: Vec<Fr>,
+    beta: Fr,
+    gamma: Fr,
+    inverse_exponent: Vec<u64>,
+}
+
+impl Permutation {
+    fn new() -> Permutation {
+        let constants = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| decimal_to_fr(value).expect("Constante d'Anemoi hors du corps"))
+                .collect()
+        };
+        let beta = Fr::from(GENERATOR);
+        Permutation {
+            c: constants(&C),
+            d: constants(&D),
+            beta,
+            gamma: beta.invert().unwrap(),
+            inverse_exponent: INVERSE_EXPONENT.parse::<BigUint>().unwrap().to_u64_digits(),
+        }
+    }
+
+    fn apply(&self, (mut x, mut y): (Fr, Fr)) -> (Fr, Fr) {
+        for (c, d) in self.c.iter().zip(&self.d) {
+            x += c;
+            y += d;
+            // Sur un seul mot, la couche linéaire est l'identité.
+            (x, y) = pht(x, y);
+            (x, y) = self.flystel(x, y);
+        }
+        pht(x, y)
+    }
+
+    /// Flystel ouverte : `(x, y)` donne `(u, v)`.
+    fn flystel(&self, x: Fr, y: Fr) -> (Fr, Fr) {
+        let t = x - self.beta * y.square() - self.gamma;
+        let v = y - t.pow_vartime(&self.inverse_exponent);
+        let u = t + self.beta * v.square();
+        (u, v)
+    }
+}
+
+/// Transformation pseudo-Hadamard : `Y += X` puis `X += Y`.
+fn pht(x: Fr, y: Fr) -> (Fr, Fr) {
+    let y = y + x;
+    (x + y, y)
+}
+
+/// Sortie de `AnemoiSponge127(inputs.len())`.
+pub fn sponge(inputs: &[Fr]) -> Fr {
+    let permutation = Permutation::new();
+    let mut state = (Fr::ZERO, Fr::ZERO);
+    for input in inputs {
+        state.0 += input;
+        state = permutation.apply(state);
+    }
+    state.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponent_is_the_circuit_one() {
+        // Puissance 11 de l'exposant : 5, et non 1.
+        let permutation = Permutation::new();
+        for value in [2u64, 37, 123_456_789] {
+            let value = Fr::from(value);
+            assert_eq!(
+                value
+                    .pow_vartime(&permutation.inverse_exponent)
+                    .pow_vartime([11]),
+                value.pow_vartime([5])
+            );
+        }
+    }
+
+    #[test]
+    fn flystel_matches_its_closed_form() {
+        // Avec t = x - beta y² - gamma : (y - v)^11 = t^5 et u = t + beta v².
+        let permutation = Permutation::new();
+        let (x, y) = (Fr::from(1234), Fr::from(5678));
+        let (u, v) = permutation.flystel(x, y);
+        let t = x - permutation.beta * y.square() - permutation.gamma;
+        assert_eq!((y - v).pow_vartime([11]), t.pow_vartime([5]));
+        assert_eq!(u, t + permutation.beta * v.square());
+    }
+
+    /// Constantes de la table `var name[19][1]` de `anemoi.circom`.
+    fn circuit_constants(name: &str) -> Vec<String> {
+        const CIRCUIT: &str = include_str!("../wesnoth-zkpsi/phase2nova/anemoi.circom");
+        let start = CIRCUIT
+            .find(&format!("var {}[19][1] =", name))
+            .expect("Table absente du circuit");
+        let table = &CIRCUIT[start..];
+        table[..table.find("]];").unwrap()]
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|value| !value.is_empty())
+            .skip(2) // Dimensions de la table.
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn constants_are_the_circuit_ones() {
+        assert_eq!(circuit_constants("c"), C);
+        assert_eq!(circuit_constants("d"), D);
+    }
+
+    /// Valeurs obtenues hors de ce module, par une transcription littérale des
+    /// templates `Anemoi` et `AnemoiSponge127` en Python, `fast_exp` compris,
+    /// faute de compilateur circom sous la main pour calculer un témoin.
+    #[test]
+    fn sponge_known_answers() {
+        let minus_one = -Fr::ONE;
+        for (inputs, expected) in [
+            (
+                vec![Fr::ZERO],
+                "443009428321136551303457357093856787656784655987721854532550467190662205584",
+            ),
+            (
+                vec![Fr::from(1), Fr::from(2)],
+                "19892894212676506162476137464973368497693837225154999373404474962677442540190",
+            ),
+            (
+                vec![Fr::from(5), Fr::from(6), Fr::from(7), minus_one],
+                "16154823888249063037129913549055230777537897789764888608242806012977444120379",
+            ),
+        ] {
+            assert_eq!(sponge(&inputs), decimal_to_fr(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn sponge_absorbs_every_input_in_order() {
+        let (one, two) = (Fr::from(1), Fr::from(2));
+        let hash = sponge(&[one, two]);
+        assert_eq!(hash, sponge(&[one, two]));
+        assert_ne!(hash, sponge(&[two, one]));
+        assert_ne!(hash, sponge(&[one]));
+        assert_ne!(sponge(&[one]), sponge(&[one, Fr::ZERO]));
+    }
+}
@@ -0,0 +1,773 @@
+//! Génération des `component main` des circuits à partir de la carte et des
+//! unités décrites en Rust.
+//!
+//! Les paramètres des templates `Final` (villages, points de vie, portées, prix,
+//! donjons, châteaux, économie) sont écrits à la dernière ligne de chaque
+//! circuit, et un manifeste les reprend à côté des circuits. Au démarrage, le
+//! manifeste doit correspondre à la carte choisie : sinon les circuits ont été
+//! générés pour autre chose et il faut les régénérer puis les recompiler. Les
+//! fichiers compilés sont aussi relus pour vérifier qu'ils ont été compilés
+//! depuis ces circuits-là.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::rules::Economy;
+use crate::unit::Unit;
+use crate::{InitialState, MAX_ACTION_COUNT};
+
+/// Circuits dont la dernière ligne est générée, avec leur fichier source.
+pub const CIRCUITS: [(&str, &str); 4] = [
+    ("hash", "hash_state.circom"),
+    ("phase1", "circuit.circom"),
+    ("phase2nova", "circuit.circom"),
+    ("phase3", "circuit.circom"),
+];
+
+pub const MANIFEST: &str = "manifest.json";
+
+/// Valeurs des signaux `enum_tag` et `enum_data` des actions.
+const ENUM_TAG: u64 = 0;
+const ENUM_DATA: u64 = 1;
+/// Taille des hashs MiMC de la phase 1.
+const MIMC_HASH_SIZE: u64 = 64;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct UnitParameters {
+    pub name: String,
+    pub health_points: u64,
+    pub movement: u64,
+    /// `-1` pour le commandant, qui ne se recrute pas.
+    pub price: i64,
+}
+
+/// Tout ce que les circuits savent de la partie.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Manifest {
+    pub map: String,
+    pub state_height: u64,
+    pub state_width: u64,
+    pub actions_size: usize,
+    pub villages: Vec<usize>,
+    /// Dans l'ordre des types d'unités, `None` compris.
+    pub units: Vec<UnitParameters>,
+    pub keeps: Vec<usize>,
+    pub castles: Vec<(usize, usize)>,
+    pub economy: Economy,
+    /// Dernière ligne de chaque circuit de `CIRCUITS`.
+    pub mains: Vec<(String, String)>,
+}
+
+fn list<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    let values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+impl Manifest {
+    pub fn new(map: &InitialState) -> Manifest {
+        let grid = map.grid();
+        let units = Unit::ALL
+            .iter()
+            .map(|unit| UnitParameters {
+                name: format!("{:?}", unit),
+                health_points: unit.max_health(),
+                movement: unit.movement(),
+                price: match unit {
+                    Unit::None => 0,
+                    _ => unit.cost().map_or(-1, |cost| cost as i64),
+                },
+            })
+            .collect();
+        let mut manifest = Manifest {
+            map: map.name().to_string(),
+            state_height: grid.height,
+            state_width: grid.width,
+            actions_size: MAX_ACTION_COUNT,
+            villages: map.village_positions().to_vec(),
+            units,
+            keeps: map.keeps().to_vec(),
+            castles: map.castles().to_vec(),
+            economy: map.economy(),
+            mains: vec![],
+        };
+        manifest.mains = CIRCUITS
+            .iter()
+            .map(|&(circuit, _)| (circuit.to_string(), manifest.main_component(circuit)))
+            .collect();
+        manifest
+    }
+
+    /// Paramètres communs aux `Final` des phases 1 et 2, après les tailles.
+    fn rules_parameters(&self) -> String {
+        let economy = &self.economy;
+        format!(
+            "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+            self.villages.len(),
+            list(&self.villages),
+            self.units.len(),
+            list(self.units.iter().map(|unit| unit.health_points)),
+            list(self.units.iter().map(|unit| unit.movement)),
+            list(self.units.iter().map(|unit| unit.price)),
+            self.keeps.len(),
+            list(&self.keeps),
+            self.castles.len(),
+            list(
+                self.castles
+                    .iter()
+                    .map(|(keep, castle)| format!("[{},{}]", keep, castle))
+            ),
+            economy.base_income,
+            economy.village_gold,
+            economy.village_support,
+            economy.village_heal,
+            economy.rest_heal,
+        )
+    }
+
+    /// Dernière ligne du circuit `circuit`.
+    pub fn main_component(&self, circuit: &str) -> String {
+        let state_size = format!("{} * {}", self.state_height, self.state_width);
+        match circuit {
+            "hash" => format!("component main = Main(({}) * 4 + 3);", state_size),
+            "phase1" => format!(
+                "component main {{public [degats, captures]}} = Final({}, {}, {}, {}, {}, {}, {}, {});",
+                state_size,
+                self.state_height,
+                self.state_width,
+                self.actions_size,
+                ENUM_TAG,
+                ENUM_DATA,
+                MIMC_HASH_SIZE,
+                self.rules_parameters()
+            ),
+            "phase2nova" => format!(
+                "component main {{public [step_in]}} = Final({}, {}, {}, {}, {}, {}, {});",
+                state_size,
+                self.state_height,
+                self.state_width,
+                self.actions_size,
+                ENUM_TAG,
+                ENUM_DATA,
+                self.rules_parameters()
+            ),
+            "phase3" => format!(
+                "component main = Final({}, {}, {});",
+                state_size, ENUM_TAG, ENUM_DATA
+            ),
+            _ => panic!("Circuit inconnu : {}", circuit),
+        }
+    }
+
+    fn path(root: &Path) -> PathBuf {
+        root.join(MANIFEST)
+    }
+
+    pub fn read(root: &Path) -> io::Result<Manifest> {
+        let manifest = fs::read_to_string(Manifest::path(root))?;
+        serde_json::from_str(&manifest).map_err(io::Error::from)
+    }
+
+    /// Écrit la dernière ligne de chaque circuit sous `root`, puis le manifeste.
+    pub fn write(&self, root: &Path) -> io::Result<()> {
+        for (&(circuit, file), (_, main)) in CIRCUITS.iter().zip(&self.mains) {
+            let path = root.join(circuit).join(file);
+            let source = fs::read_to_string(&path)?;
+            let (body, last) = source.trim_end().rsplit_once('\n').unwrap_or(("", &source));
+            if !last.starts_with("component main") {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{:?} ne finit pas par son `component main`", path),
+                ));
+            }
+            fs::write(&path, format!("{}\n{}\n", body, main))?;
+        }
+        let manifest = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        fs::write(Manifest::path(root), manifest + "\n")
+    }
+}
+
+/// Copie les sources des circuits de `CIRCUITS` dans un nouveau jeu de
+/// circuits, sans toucher à ceux qui existent déjà.
+pub fn copy_sources(sources: &Path, root: &Path) -> io::Result<()> {
+    for &(circuit, _) in &CIRCUITS {
+        let target = root.join(circuit);
+        if target.exists() {
+            continue;
+        }
+        fs::create_dir_all(&target)?;
+        for entry in fs::read_dir(sources.join(circuit))? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "circom")
+            {
+                fs::copy(&path, target.join(path.file_name().unwrap()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Raison de refuser de démarrer avec les circuits compilés.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtifactError {
+    MissingManifest(PathBuf, String),
+    /// Le manifeste ne décrit pas la carte choisie.
+    StaleManifest(PathBuf, String),
+    Missing(PathBuf),
+    Unreadable(PathBuf, String),
+    /// Nombres d'entrées et de sorties publiques différents de l'attendu.
+    PublicSignals {
+        circuit: &'static str,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// Dimensions d'une entrée de `main` différentes de l'attendu, `None` si
+    /// l'entrée n'existe pas.
+    InputShape {
+        circuit: &'static str,
+        signal: &'static str,
+        expected: Vec<usize>,
+        found: Option<Vec<usize>>,
+    },
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactError::MissingManifest(path, error) => write!(
+                f,
+                "impossible de lire {:?} ({}), lancer `cargo run -- circuits`",
+                path, error
+            ),
+            ArtifactError::StaleManifest(path, map) => write!(
+                f,
+                "les circuits de {:?} ont été générés pour d'autres paramètres que la carte {}, \
+                 relancer `cargo run -- circuits` puis les recompiler",
+                path, map
+            ),
+            ArtifactError::Missing(path) => {
+                write!(f, "{:?} n'existe pas, compiler les circuits", path)
+            }
+            ArtifactError::Unreadable(path, error) => {
+                write!(f, "impossible de lire {:?} : {}", path, error)
+            }
+            ArtifactError::PublicSignals {
+                circuit,
+                expected,
+                found,
+            } => write!(
+                f,
+                "le circuit {} compilé a {} entrées et {} sorties publiques au lieu de {} et {}",
+                circuit, found.0, found.1, expected.0, expected.1
+            ),
+            ArtifactError::InputShape {
+                circuit,
+                signal,
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "l'entrée {} du circuit {} compilé est de taille {:?} au lieu de {:?}",
+                signal, circuit, found, expected
+            ),
+            ArtifactError::InputShape {
+                circuit,
+                signal,
+                found: None,
+                ..
+            } => write!(
+                f,
+                "le circuit {} compilé n'a pas d'entrée {}",
+                circuit, signal
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+/// Nom d'une entrée de `main` et ses dimensions.
+type InputShape = (&'static str, Vec<usize>);
+
+/// Entrées de `main` et nombres d'entrées et de sorties publiques attendus pour
+/// un circuit, d'après le manifeste.
+fn expected_signals(circuit: &str, manifest: &Manifest) -> (Vec<InputShape>, (u32, u32)) {
+    let size = (manifest.state_height * manifest.state_width) as usize;
+    let villages = manifest.villages.len();
+    match circuit {
+        "hash" => (vec![("to_hash", vec![size * 4 + 3])], (0, 0)),
+        "phase1" => (
+            vec![
+                ("prev_state", vec![size, 4]),
+                ("prev_misc_state", vec![3]),
+                ("degats", vec![size]),
+                ("captures", vec![villages]),
+                ("phase1_exponents", vec![size, 254]),
+            ],
+            ((size + villages) as u32, 2 * size as u32),
+        ),
+        "phase2nova" => (
+            vec![
+                ("step_in", vec![2]),
+                ("prev_state", vec![size, 4]),
+                ("prev_misc_state", vec![3]),
+                ("actions", vec![manifest.actions_size, 8]),
+                ("phase1_exponents", vec![size, 254]),
+                ("phase2_exponent", vec![254]),
+                ("degats", vec![size]),
+                ("captures", vec![villages]),
+                ("phase1_received", vec![size, 2]),
+                ("actions_captures", vec![villages]),
+                ("roll_commitments", vec![4]),
+            ],
+            (2, 2),
+        ),
+        "phase3" => (
+            vec![
+                ("hashed_idents", vec![size, 2]),
+                ("inv_phase1_exponents", vec![size, 254]),
+                ("phase2_dh_output", vec![size, 2]),
+                ("phase2_hidden_tags", vec![size, 2]),
+                ("phase2_hidden_data", vec![size, 3, 64]),
+            ],
+            (0, 0),
+        ),
+        _ => panic!("Circuit inconnu : {}", circuit),
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Entrées et sorties publiques lues dans l'en-tête d'un fichier `.r1cs`, sans
+/// charger les contraintes.
+fn read_public_signals(path: &Path) -> io::Result<(u32, u32)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"r1cs" {
+        return Err(invalid("ce n'est pas un fichier R1CS"));
+    }
+    let _version = read_u32(&mut reader)?;
+    let sections = read_u32(&mut reader)?;
+    for _ in 0..sections {
+        let kind = read_u32(&mut reader)?;
+        let mut size = [0; 8];
+        reader.read_exact(&mut size)?;
+        let size = u64::from_le_bytes(size);
+        if kind == 1 {
+            let field_size = read_u32(&mut reader)?;
+            reader.seek_relative(field_size as i64)?;
+            let _wires = read_u32(&mut reader)?;
+            let public_outputs = read_u32(&mut reader)?;
+            let public_inputs = read_u32(&mut reader)?;
+            return Ok((public_inputs, public_outputs));
+        }
+        reader.seek_relative(size as i64)?;
+    }
+    Err(invalid("pas d'en-tête dans le fichier R1CS"))
+}
+
+/// Dimensions des signaux de `main` listés dans un fichier `.sym`, dont les
+/// lignes sont `étiquette,fil,composant,nom`.
+fn read_main_signals(path: &Path) -> io::Result<HashMap<String, Vec<usize>>> {
+    let mut shapes: HashMap<String, Vec<usize>> = HashMap::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let Some(signal) = line
+            .splitn(4, ',')
+            .nth(3)
+            .and_then(|name| name.strip_prefix("main."))
+        else {
+            continue;
+        };
+        // Les signaux des sous-composants ne sont pas des entrées.
+        if signal.contains('.') {
+            continue;
+        }
+        let (name, indices) = signal.split_once('[').unwrap_or((signal, ""));
+        let indices = indices
+            .trim_end_matches(']')
+            .split("][")
+            .filter(|index| !index.is_empty())
+            .map(|index| {
+                index.parse::<usize>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("indice {:?} invalide dans la ligne {:?}", index, line),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let shape = shapes
+            .entry(name.to_string())
+            .or_insert_with(|| vec![0; indices.len()]);
+        for (dimension, index) in shape.iter_mut().zip(indices) {
+            *dimension = (*dimension).max(index + 1);
+        }
+    }
+    Ok(shapes)
+}
+
+/// Vérifie que les circuits compilés sous `map.circuit_path()` correspondent à
+/// la carte choisie et à `MAX_ACTION_COUNT` : manifeste, générateurs de
+/// témoins, entrées et sorties publiques des `.r1cs` et tailles des entrées
+/// dans les `.sym`.
+pub fn check_artifacts(map: &InitialState) -> Result<(), ArtifactError> {
+    check_artifacts_in(map.circuit_path(), map)
+}
+
+fn check_artifacts_in(root: PathBuf, map: &InitialState) -> Result<(), ArtifactError> {
+    let manifest = Manifest::new(map);
+    match Manifest::read(&root) {
+        Ok(found) if found == manifest => {}
+        Ok(_) => return Err(ArtifactError::StaleManifest(root, map.name().to_string())),
+        Err(error) => {
+            return Err(ArtifactError::MissingManifest(
+                Manifest::path(&root),
+                error.to_string(),
+            ))
+        }
+    }
+
+    for &(circuit, file) in &CIRCUITS {
+        let directory = root.join(circuit);
+        let stem = file.trim_end_matches(".circom");
+        let generator = directory.join(format!("{}_cpp", stem)).join(stem);
+        if !generator.exists() {
+            return Err(ArtifactError::Missing(generator));
+        }
+        let r1cs = directory.join(format!("{}.r1cs", stem));
+        let sym = directory.join(format!("{}.sym", stem));
+        for path in [&r1cs, &sym] {
+            if !path.exists() {
+                return Err(ArtifactError::Missing(path.clone()));
+            }
+        }
+
+        let (inputs, public) = expected_signals(circuit, &manifest);
+        let found = read_public_signals(&r1cs)
+            .map_err(|error| ArtifactError::Unreadable(r1cs.clone(), error.to_string()))?;
+        if found != public {
+            return Err(ArtifactError::PublicSignals {
+                circuit,
+                expected: public,
+                found,
+            });
+        }
+
+        let shapes = read_main_signals(&sym)
+            .map_err(|error| ArtifactError::Unreadable(sym.clone(), error.to_string()))?;
+        for (signal, expected) in inputs {
+            let found = shapes.get(signal);
+            if found != Some(&expected) {
+                return Err(ArtifactError::InputShape {
+                    circuit,
+                    signal,
+                    expected,
+                    found: found.cloned(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::unit::Commander;
+    use crate::CIRCUIT_SOURCES;
+
+    const MAP: InitialState = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+
+    #[test]
+    fn manifest_describes_the_map() {
+        let manifest = Manifest::new(&MAP);
+        assert_eq!(manifest.map, "nordic");
+        assert_eq!((manifest.state_width, manifest.state_height), (10, 10));
+        assert_eq!(manifest.actions_size, MAX_ACTION_COUNT);
+        assert_eq!(manifest.villages, MAP.village_positions());
+        assert_eq!(manifest.keeps, MAP.keeps());
+        assert_eq!(manifest.castles, MAP.castles());
+        assert_eq!(manifest.units.len(), Unit::ALL.len());
+        // Ni la case vide ni le commandant ne se recrutent.
+        assert_eq!(manifest.units[0].price, 0);
+        assert_eq!(manifest.units[1].price, -1);
+        assert_eq!(manifest.units[5].name, "OrcishGrunt");
+        assert_eq!(manifest.units[5].price, 12);
+        assert_eq!(
+            manifest
+                .mains
+                .iter()
+                .map(|(circuit, _)| circuit.as_str())
+                .collect::<Vec<_>>(),
+            CIRCUITS.map(|(circuit, _)| circuit)
+        );
+    }
+
+    #[test]
+    fn committed_circuits_match_the_manifest() {
+        // Le jeu de circuits par défaut est celui de la carte de `main`.
+        let sources = Path::new(CIRCUIT_SOURCES);
+        let manifest = Manifest::new(&MAP);
+        assert_eq!(Manifest::read(sources).unwrap(), manifest);
+        for &(circuit, file) in &CIRCUITS {
+            let source = fs::read_to_string(sources.join(circuit).join(file)).unwrap();
+            assert_eq!(
+                source.trim_end().lines().last(),
+                Some(manifest.main_component(circuit).as_str()),
+                "{}",
+                circuit
+            );
+        }
+        assert_eq!(
+            manifest.main_component("phase3"),
+            "component main = Final(10 * 10, 0, 1);"
+        );
+    }
+
+    #[test]
+    fn write_replaces_only_the_main_component() {
+        let root = tempfile::tempdir().unwrap();
+        copy_sources(Path::new(CIRCUIT_SOURCES), root.path()).unwrap();
+        let path = root.path().join("phase3/circuit.circom");
+        let source = fs::read_to_string(&path).unwrap();
+        let body = source.trim_end().rsplit_once('\n').unwrap().0;
+        fs::write(
+            &path,
+            format!("{}\ncomponent main = Final(4, 0, 1);\n", body),
+        )
+        .unwrap();
+
+        let manifest = Manifest::new(&MAP);
+        manifest.write(root.path()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), source);
+        assert_eq!(Manifest::read(root.path()).unwrap(), manifest);
+
+        // Un circuit sans `component main` final n'est pas modifié.
+        fs::write(&path, body).unwrap();
+        assert_eq!(
+            manifest.write(root.path()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), body);
+    }
+
+    /// En-tête R1CS minimal : magie, version, une section d'en-tête.
+    fn r1cs_header((public_inputs, public_outputs): (u32, u32)) -> Vec<u8> {
+        let mut bytes = b"r1cs".to_vec();
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(48u64.to_le_bytes());
+        bytes.extend(32u32.to_le_bytes());
+        bytes.extend([0; 32]);
+        bytes.extend(1000u32.to_le_bytes());
+        bytes.extend(public_outputs.to_le_bytes());
+        bytes.extend(public_inputs.to_le_bytes());
+        bytes
+    }
+
+    /// Lignes `.sym` des entrées attendues, plus un signal de sous-composant.
+    fn sym_lines(inputs: &[InputShape]) -> String {
+        let mut lines = String::new();
+        let mut wire = 1;
+        for (name, shape) in inputs {
+            let last = shape.iter().map(|dimension| dimension - 1);
+            let indices: String = last.map(|index| format!("[{}]", index)).collect();
+            lines += &format!("{},{},0,main.{}{}\n", wire, wire, name, indices);
+            wire += 1;
+        }
+        lines + &format!("{},{},1,main.hash_prev.in[3]\n", wire, wire)
+    }
+
+    /// Jeu de circuits compilés factice, complet et à jour.
+    fn artifacts(root: &Path, manifest: &Manifest) {
+        fs::write(
+            root.join(MANIFEST),
+            serde_json::to_string(manifest).unwrap(),
+        )
+        .unwrap();
+        for &(circuit, file) in &CIRCUITS {
+            let stem = file.trim_end_matches(".circom");
+            let directory = root.join(circuit);
+            fs::create_dir_all(directory.join(format!("{}_cpp", stem))).unwrap();
+            fs::write(directory.join(format!("{}_cpp", stem)).join(stem), "").unwrap();
+            let (inputs, public) = expected_signals(circuit, manifest);
+            fs::write(
+                directory.join(format!("{}.r1cs", stem)),
+                r1cs_header(public),
+            )
+            .unwrap();
+            fs::write(directory.join(format!("{}.sym", stem)), sym_lines(&inputs)).unwrap();
+        }
+    }
+
+    const SYM: &str = "\
+1,1,0,main.step_out[0]
+2,2,0,main.step_out[1]
+3,3,0,main.step_in[0]
+4,4,0,main.step_in[1]
+5,5,0,main.prev_state[0][0]
+6,6,0,main.prev_state[0][3]
+7,7,0,main.prev_state[2][1]
+8,8,0,main.phase2_exponent
+9,9,3,main.hash_prev.in[7]
+10,-1,3,other.in[12]
+";
+
+    #[test]
+    fn sym_gives_the_shape_of_main_signals() {
+        let root = tempfile::tempdir().unwrap();
+        let sym = root.path().join("circuit.sym");
+        fs::write(&sym, SYM).unwrap();
+        let shapes = read_main_signals(&sym).unwrap();
+        assert_eq!(shapes.len(), 4);
+        assert_eq!(shapes["step_out"], [2]);
+        assert_eq!(shapes["step_in"], [2]);
+        assert_eq!(shapes["prev_state"], [3, 4]);
+        assert_eq!(shapes["phase2_exponent"], Vec::<usize>::new());
+
+        for bad in [
+            "main.step_in[x]",
+            "main.step_in[-1]",
+            "main.prev_state[0][]1]",
+        ] {
+            fs::write(&sym, format!("{}11,11,0,{}\n", SYM, bad)).unwrap();
+            let error = read_main_signals(&sym).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn artifacts_are_checked_against_the_map() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().to_path_buf();
+        assert!(matches!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::MissingManifest(_, _))
+        ));
+
+        let manifest = Manifest::new(&MAP);
+        artifacts(root.path(), &manifest);
+        assert_eq!(check_artifacts_in(path.clone(), &MAP), Ok(()));
+
+        // Circuits générés pour une autre économie.
+        let mut stale = manifest.clone();
+        stale.economy.village_gold += 1;
+        fs::write(
+            root.path().join(MANIFEST),
+            serde_json::to_string(&stale).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::StaleManifest(
+                path.clone(),
+                "nordic".to_string()
+            ))
+        );
+        artifacts(root.path(), &manifest);
+
+        let generator = root.path().join("phase3/circuit_cpp/circuit");
+        fs::remove_file(&generator).unwrap();
+        assert_eq!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::Missing(generator))
+        );
+        artifacts(root.path(), &manifest);
+
+        let sym = root.path().join("phase1/circuit.sym");
+        fs::remove_file(&sym).unwrap();
+        assert_eq!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::Missing(sym))
+        );
+        artifacts(root.path(), &manifest);
+
+        // Circuit compilé avant l'ajout d'une entrée publique.
+        fs::write(
+            root.path().join("phase2nova/circuit.r1cs"),
+            r1cs_header((1, 2)),
+        )
+        .unwrap();
+        assert_eq!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::PublicSignals {
+                circuit: "phase2nova",
+                expected: (2, 2),
+                found: (1, 2),
+            })
+        );
+        fs::write(root.path().join("phase2nova/circuit.r1cs"), b"pas un r1cs").unwrap();
+        assert!(matches!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::Unreadable(_, _))
+        ));
+        artifacts(root.path(), &manifest);
+
+        fs::write(
+            root.path().join("phase3/circuit.sym"),
+            "1,1,0,main.hashed_idents[a][0]\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::Unreadable(_, _))
+        ));
+        artifacts(root.path(), &manifest);
+
+        // Circuit compilé avant l'ajout des engagements sur l'aléa.
+        let (inputs, _) = expected_signals("phase2nova", &manifest);
+        let without = inputs
+            .into_iter()
+            .filter(|(name, _)| *name != "roll_commitments")
+            .collect::<Vec<_>>();
+        fs::write(
+            root.path().join("phase2nova/circuit.sym"),
+            sym_lines(&without),
+        )
+        .unwrap();
+        assert_eq!(
+            check_artifacts_in(path.clone(), &MAP),
+            Err(ArtifactError::InputShape {
+                circuit: "phase2nova",
+                signal: "roll_commitments",
+                expected: vec![4],
+                found: None,
+            })
+        );
+
+        // Compilé pour une autre taille d'actions.
+        let mut smaller = manifest.clone();
+        smaller.actions_size = 5;
+        let (inputs, _) = expected_signals("phase2nova", &smaller);
+        fs::write(
+            root.path().join("phase2nova/circuit.sym"),
+            sym_lines(&inputs),
+        )
+        .unwrap();
+        assert_eq!(
+            check_artifacts_in(path, &MAP),
+            Err(ArtifactError::InputShape {
+                circuit: "phase2nova",
+                signal: "actions",
+                expected: vec![MAX_ACTION_COUNT, 8],
+                found: Some(vec![5, 8]),
+            })
+        );
+    }
+}
@@ -0,0 +1,654 @@
+//! Résolution des attaques, hors circuit.
+//!
+//! `Regles` ne connaît pas les combats : une attaque n'est pas une action du
+//! circuit, seuls les dégâts en sortent. Ceux infligés à l'adversaire forment le
+//! vecteur public `degats` qu'il applique au début de son prochain tour, ceux de
+//! la riposte sont appliqués à nos cases au début de notre prochain tour.
+//!
+//! Les attaques du tour sont annoncées avec ces dégâts. Le défenseur connaît
+//! ses unités, voit les attaquants qui leur sont voisins et a tiré la graine
+//! du tour avec l'attaquant : il rejoue chaque combat et vérifie les dégâts
+//! annoncés.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::hex::distance;
+use crate::pathfinding::{MovementType, Terrain};
+use crate::randomness::SharedSeed;
+use crate::rules::RuleViolation;
+use crate::unit::Unit;
+use crate::vision::VisibleEnemy;
+use crate::{InitialState, Position, Square};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DamageType {
+    Blade,
+    Pierce,
+    Impact,
+    Fire,
+    Cold,
+    Arcane,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Range {
+    Melee,
+    Ranged,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Weapon {
+    pub name: &'static str,
+    pub damage: u64,
+    pub strikes: u64,
+    pub damage_type: DamageType,
+    pub range: Range,
+    /// Au moins 60 % de chances de toucher en attaque.
+    pub marksman: bool,
+}
+
+const fn weapon(
+    name: &'static str,
+    damage: u64,
+    strikes: u64,
+    damage_type: DamageType,
+    range: Range,
+) -> Weapon {
+    Weapon {
+        name,
+        damage,
+        strikes,
+        damage_type,
+        range,
+        marksman: false,
+    }
+}
+
+use DamageType::{Blade, Fire, Impact, Pierce};
+use Range::{Melee, Ranged};
+
+const ORC_COMMANDER: &[Weapon] = &[weapon("greatsword", 10, 3, Blade, Melee)];
+const ORCISH_ARCHER: &[Weapon] = &[
+    weapon("dagger", 3, 2, Blade, Melee),
+    weapon("bow", 5, 3, Pierce, Ranged),
+    weapon("bow", 7, 2, Fire, Ranged),
+];
+const ORCISH_ASSASSIN: &[Weapon] = &[
+    weapon("dagger", 3, 1, Blade, Melee),
+    Weapon {
+        marksman: true,
+        ..weapon("throwing knives", 3, 3, Blade, Ranged)
+    },
+];
+const NAGA_FIGHTER: &[Weapon] = &[weapon("sword", 4, 4, Blade, Melee)];
+const ORCISH_GRUNT: &[Weapon] = &[weapon("greatsword", 9, 2, Blade, Melee)];
+const TROLL_WHELP: &[Weapon] = &[weapon("fist", 7, 2, Impact, Melee)];
+const WOLF_RIDER: &[Weapon] = &[weapon("fangs", 5, 3, Blade, Melee)];
+const GOBLIN_SPEARMAN: &[Weapon] = &[
+    weapon("spear", 6, 3, Pierce, Melee),
+    weapon("spear", 3, 1, Pierce, Ranged),
+];
+
+impl Unit {
+    pub fn weapons(&self) -> &'static [Weapon] {
+        match self {
+            Unit::None => &[],
+            Unit::OrcCommander => ORC_COMMANDER,
+            Unit::OrcishArcher => ORCISH_ARCHER,
+            Unit::OrcishAssassin => ORCISH_ASSASSIN,
+            Unit::NagaFighter => NAGA_FIGHTER,
+            Unit::OrcishGrunt => ORCISH_GRUNT,
+            Unit::TrollWhelp => TROLL_WHELP,
+            Unit::WolfRider => WOLF_RIDER,
+            Unit::GoblinSpearman => GOBLIN_SPEARMAN,
+        }
+    }
+
+    /// Résistance en pourcents, négative pour une faiblesse.
+    pub fn resistance(&self, damage_type: DamageType) -> i64 {
+        match (self, damage_type) {
+            (Unit::TrollWhelp, DamageType::Blade | DamageType::Pierce) => 20,
+            (Unit::TrollWhelp, DamageType::Arcane) => -10,
+            (Unit::WolfRider, DamageType::Pierce) => -20,
+            _ => 0,
+        }
+    }
+}
+
+impl MovementType {
+    /// Chances en pourcents d'esquiver un coup sur ce terrain.
+    pub fn defense(&self, terrain: Terrain) -> u64 {
+        match (self, terrain) {
+            (MovementType::OrcishFoot, Terrain::Flat) => 40,
+            (MovementType::OrcishFoot, Terrain::Castle | Terrain::Village) => 60,
+            (MovementType::OrcishFoot, Terrain::Forest | Terrain::Hills) => 50,
+            (MovementType::OrcishFoot, Terrain::Mountains) => 60,
+            (MovementType::OrcishFoot, Terrain::ShallowWater) => 20,
+            (MovementType::OrcishFoot, Terrain::Swamp) => 30,
+            (MovementType::LargeFoot, Terrain::Flat) => 30,
+            (MovementType::LargeFoot, Terrain::Castle | Terrain::Village) => 40,
+            (MovementType::LargeFoot, Terrain::Forest) => 40,
+            (MovementType::LargeFoot, Terrain::Hills) => 50,
+            (MovementType::LargeFoot, Terrain::Mountains) => 60,
+            (MovementType::LargeFoot, Terrain::ShallowWater | Terrain::Swamp) => 20,
+            (MovementType::Naga, Terrain::Flat) => 30,
+            (MovementType::Naga, Terrain::Castle) => 50,
+            (MovementType::Naga, Terrain::Village | Terrain::Forest) => 40,
+            (MovementType::Naga, Terrain::Hills | Terrain::Mountains) => 40,
+            (MovementType::Naga, Terrain::ShallowWater) => 50,
+            (MovementType::Naga, Terrain::Swamp) => 60,
+        }
+    }
+}
+
+/// Attaque d'une de nos unités sur une unité adverse voisine. Le type et les
+/// points de vie du défenseur sont ceux que nous a révélés la vision.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Attack {
+    pub from: Position,
+    pub to: Position,
+    /// Indice de l'arme dans `Unit::weapons`.
+    pub weapon: usize,
+    pub defender: Unit,
+    pub defender_health: u64,
+}
+
+/// Dégâts totaux d'un combat, de chaque côté.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Outcome {
+    pub inflicted: u64,
+    pub received: u64,
+}
+
+/// Attaque telle que l'attaquant l'annonce : le défenseur connaît déjà
+/// l'unité visée et voit l'attaquant.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AnnouncedAttack {
+    pub from: Position,
+    pub to: Position,
+    pub weapon: usize,
+}
+
+impl From<&Attack> for AnnouncedAttack {
+    fn from(attack: &Attack) -> AnnouncedAttack {
+        AnnouncedAttack {
+            from: attack.from,
+            to: attack.to,
+            weapon: attack.weapon,
+        }
+    }
+}
+
+/// Dégâts des attaques d'un tour, case par case.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Exchange {
+    /// Dégâts infligés aux cases du défenseur.
+    pub inflicted: Vec<u64>,
+    /// Ripostes reçues par les cases de l'attaquant.
+    pub received: Vec<u64>,
+}
+
+impl Exchange {
+    /// Aucun dégât, sur une carte de `size` cases.
+    pub fn none(size: usize) -> Exchange {
+        Exchange {
+            inflicted: vec![0; size],
+            received: vec![0; size],
+        }
+    }
+
+    /// Déroule les attaques d'un tour dans l'ordre, la `n`-ième avec les jets
+    /// de `seed.attack_rng(n)`. Les attaquants sont lus dans `attackers`, case
+    /// par case. Une cible tuée par une attaque précédente n'est plus attaquée.
+    pub fn resolve(
+        attacks: &[Attack],
+        attackers: &[Square],
+        map: &InitialState,
+        seed: &SharedSeed,
+    ) -> Result<Exchange, RuleViolation> {
+        let mut exchange = Exchange::none(attackers.len());
+        for (rank, attack) in attacks.iter().enumerate() {
+            let target = map.index(attack.to);
+            let defender_health = attack
+                .defender_health
+                .saturating_sub(exchange.inflicted[target]);
+            if defender_health == 0 {
+                continue;
+            }
+            let attack = Attack {
+                defender_health,
+                ..*attack
+            };
+            let from = map.index(attack.from);
+            let outcome =
+                attack.resolve(&attackers[from], map, &mut seed.attack_rng(rank as u64))?;
+            exchange.inflicted[target] += outcome.inflicted;
+            exchange.received[from] += outcome.received;
+        }
+        Ok(exchange)
+    }
+
+    /// Rejoue chez le défenseur les attaques annoncées par l'adversaire. Les
+    /// cibles sont nos cases dans `defenders`, telles qu'au moment des combats,
+    /// et les attaquants les unités adverses que nous voyons, avec leurs points
+    /// de vie d'avant leurs ripostes. Échoue sur une attaque impossible.
+    pub fn replay(
+        announced: &[AnnouncedAttack],
+        defenders: &[Square],
+        enemies: &[VisibleEnemy],
+        map: &InitialState,
+        seed: &SharedSeed,
+    ) -> Result<Exchange, RuleViolation> {
+        let grid = map.grid();
+        let mut attackers = vec![Unit::None.default_square(); defenders.len()];
+        for enemy in enemies {
+            attackers[map.index(enemy.position)] = Square {
+                health_points: enemy.health_points,
+                ..enemy.unit.default_square()
+            };
+        }
+        let attacks = announced
+            .iter()
+            .map(|&AnnouncedAttack { from, to, weapon }| {
+                for position in [from, to] {
+                    if !grid.contains(position) {
+                        return Err(RuleViolation::OutOfMap(position));
+                    }
+                }
+                if distance(from, to) != 1 {
+                    return Err(RuleViolation::NotAdjacent(from, to));
+                }
+                let defender = defenders[map.index(to)];
+                if defender.unit == Unit::None {
+                    return Err(RuleViolation::NoTarget(to));
+                }
+                Ok(Attack {
+                    from,
+                    to,
+                    weapon,
+                    defender: defender.unit,
+                    defender_health: defender.health_points,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Exchange::resolve(&attacks, &attackers, map, seed)
+    }
+}
+
+/// Dégâts d'un coup après résistance, arrondis au plus proche comme Wesnoth,
+/// et jamais nuls.
+fn strike_damage(weapon: &Weapon, target: Unit) -> u64 {
+    let modified = weapon.damage as i64 * (100 - target.resistance(weapon.damage_type));
+    ((modified + 50) / 100).max(1) as u64
+}
+
+impl Attack {
+    /// Arme choisie par l'attaquant.
+    pub fn weapon(&self, attacker: Unit) -> Result<Weapon, RuleViolation> {
+        if attacker == Unit::None {
+            return Err(RuleViolation::EmptyOrigin(self.from));
+        }
+        attacker
+            .weapons()
+            .get(self.weapon)
+            .copied()
+            .ok_or(RuleViolation::UnknownWeapon(attacker, self.weapon))
+    }
+
+    /// Arme de même portée utilisée par le défenseur pour riposter, la plus
+    /// forte s'il en a plusieurs.
+    pub fn retaliation(&self, attacker: Unit) -> Option<Weapon> {
+        let range = self.weapon(attacker).ok()?.range;
+        self.defender
+            .weapons()
+            .iter()
+            .filter(|weapon| weapon.range == range)
+            .max_by_key(|weapon| strike_damage(weapon, attacker) * weapon.strikes)
+            .copied()
+    }
+
+    /// Déroule le combat coup par coup, l'attaquant d'abord, jusqu'à épuisement
+    /// des coups ou la mort d'un des deux. Échoue sans attaquant ou sans l'arme
+    /// choisie, par exemple si l'unité est morte depuis que l'attaque a été
+    /// prévue.
+    pub fn resolve(
+        &self,
+        attacker: &Square,
+        map: &InitialState,
+        rng: &mut impl Rng,
+    ) -> Result<Outcome, RuleViolation> {
+        let weapon = self.weapon(attacker.unit)?;
+        let retaliation = self.retaliation(attacker.unit);
+
+        let defender_defense = self.defender.movement_type().defense(map.terrain(self.to));
+        let mut attack_chance = 100 - defender_defense;
+        if weapon.marksman {
+            attack_chance = attack_chance.max(60);
+        }
+        let retaliation_chance = 100
+            - attacker
+                .unit
+                .movement_type()
+                .defense(map.terrain(self.from));
+
+        let mut outcome = Outcome::default();
+        let (mut attacker_health, mut defender_health) =
+            (attacker.health_points, self.defender_health);
+        let rounds = weapon
+            .strikes
+            .max(retaliation.map_or(0, |weapon| weapon.strikes));
+        for round in 0..rounds {
+            if round < weapon.strikes && rng.gen_range(0..100) < attack_chance {
+                let damage = strike_damage(&weapon, self.defender).min(defender_health);
+                outcome.inflicted += damage;
+                defender_health -= damage;
+                if defender_health == 0 {
+                    break;
+                }
+            }
+            if let Some(retaliation) = retaliation.filter(|weapon| round < weapon.strikes) {
+                if rng.gen_range(0..100) < retaliation_chance {
+                    let damage = strike_damage(&retaliation, attacker.unit).min(attacker_health);
+                    outcome.received += damage;
+                    attacker_health -= damage;
+                    if attacker_health == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+    use crate::unit::Commander;
+
+    const MAP: InitialState = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+
+    /// Jets choisis à l'avance : chaque `gen_range(0..100)` renvoie le suivant,
+    /// puis le dernier indéfiniment.
+    struct Rolls(Vec<u64>);
+
+    impl RngCore for Rolls {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let roll = if self.0.len() > 1 {
+                self.0.remove(0)
+            } else {
+                self.0[0]
+            };
+            // `gen_range` garde les bits de poids fort de `x * 100`.
+            ((roll as u128 * (1 << 64)) / 100 + 1) as u64
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for chunk in dest.chunks_mut(8) {
+                let bytes = self.next_u64().to_le_bytes();
+                chunk.copy_from_slice(&bytes[..chunk.len()]);
+            }
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    fn attack(from: Position, to: Position, weapon: usize, defender: Unit) -> Attack {
+        Attack {
+            from,
+            to,
+            weapon,
+            defender,
+            defender_health: defender.max_health(),
+        }
+    }
+
+    fn resolve(attack: Attack, attacker: Unit, rolls: &[u64]) -> Outcome {
+        attack
+            .resolve(&attacker.default_square(), &MAP, &mut Rolls(rolls.to_vec()))
+            .unwrap()
+    }
+
+    #[test]
+    fn rolls_are_scripted() {
+        let mut rolls = Rolls(vec![0, 42, 99]);
+        let drawn: Vec<u64> = (0..4).map(|_| rolls.gen_range(0..100)).collect();
+        assert_eq!(drawn, [0, 42, 99, 99]);
+    }
+
+    #[test]
+    fn damage_follows_resistance() {
+        let greatsword = ORCISH_GRUNT[0];
+        assert_eq!(strike_damage(&greatsword, Unit::OrcishGrunt), 9);
+        // 9 * 80 % = 7,2.
+        assert_eq!(strike_damage(&greatsword, Unit::TrollWhelp), 7);
+        // 6 * 120 % = 7,2.
+        assert_eq!(strike_damage(&GOBLIN_SPEARMAN[0], Unit::WolfRider), 7);
+        // 3 * 80 % = 2,4.
+        assert_eq!(strike_damage(&ORCISH_ASSASSIN[1], Unit::TrollWhelp), 2);
+    }
+
+    #[test]
+    fn every_strike_lands() {
+        // Grunt contre troll en plaine, tous les coups touchent.
+        let outcome = resolve(
+            attack((1, 4), (1, 5), 0, Unit::TrollWhelp),
+            Unit::OrcishGrunt,
+            &[0],
+        );
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 2 * 7,
+                received: 2 * 7
+            }
+        );
+    }
+
+    #[test]
+    fn defense_depends_on_terrain() {
+        // Le troll sur la montagne en (2, 7) esquive 60 % des coups, le grunt
+        // en plaine 40 % : un jet de 50 rate le troll mais touche le grunt.
+        let outcome = resolve(
+            attack((2, 6), (2, 7), 0, Unit::TrollWhelp),
+            Unit::OrcishGrunt,
+            &[50],
+        );
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 0,
+                received: 2 * 7
+            }
+        );
+        // En plaine, le troll n'esquive que 30 % des coups.
+        let outcome = resolve(
+            attack((2, 5), (2, 4), 0, Unit::TrollWhelp),
+            Unit::OrcishGrunt,
+            &[50],
+        );
+        assert_eq!(outcome.inflicted, 2 * 7);
+    }
+
+    #[test]
+    fn marksman_hits_sixty_percent() {
+        // Seulement 40 % de chances contre le troll sur la montagne, relevées à
+        // 60 % par les couteaux de lancer. Le troll ne riposte pas à distance.
+        let outcome = resolve(
+            attack((2, 6), (2, 7), 1, Unit::TrollWhelp),
+            Unit::OrcishAssassin,
+            &[50],
+        );
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 3 * 2,
+                received: 0
+            }
+        );
+        // Pas au corps à corps.
+        let outcome = resolve(
+            attack((2, 6), (2, 7), 0, Unit::TrollWhelp),
+            Unit::OrcishAssassin,
+            &[50],
+        );
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 0,
+                received: 2 * 7
+            }
+        );
+    }
+
+    #[test]
+    fn retaliation_uses_the_same_range() {
+        let bow = attack((1, 4), (1, 5), 1, Unit::GoblinSpearman);
+        assert_eq!(
+            bow.retaliation(Unit::OrcishArcher),
+            Some(GOBLIN_SPEARMAN[1])
+        );
+        let dagger = attack((1, 4), (1, 5), 0, Unit::GoblinSpearman);
+        assert_eq!(
+            dagger.retaliation(Unit::OrcishArcher),
+            Some(GOBLIN_SPEARMAN[0])
+        );
+        assert_eq!(
+            attack((1, 4), (1, 5), 1, Unit::OrcishGrunt).retaliation(Unit::OrcishArcher),
+            None
+        );
+        // Le défenseur choisit l'arme la plus forte contre l'attaquant.
+        let grunt = attack((1, 4), (1, 5), 0, Unit::OrcishArcher);
+        assert_eq!(grunt.retaliation(Unit::OrcishGrunt), Some(ORCISH_ARCHER[0]));
+
+        // Un seul coup de lance à distance : 3 dégâts.
+        let outcome = resolve(bow, Unit::OrcishArcher, &[0]);
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 3 * 5,
+                received: 3
+            }
+        );
+    }
+
+    #[test]
+    fn combat_stops_at_death() {
+        let outcome = resolve(
+            Attack {
+                defender_health: 5,
+                ..attack((1, 4), (1, 5), 0, Unit::TrollWhelp)
+            },
+            Unit::OrcishGrunt,
+            &[0],
+        );
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 5,
+                received: 0
+            }
+        );
+
+        let mut attacker = Unit::GoblinSpearman.default_square();
+        attacker.health_points = 3;
+        let outcome = attack((1, 4), (1, 5), 0, Unit::OrcishGrunt)
+            .resolve(&attacker, &MAP, &mut Rolls(vec![99, 0]))
+            .unwrap();
+        assert_eq!(
+            outcome,
+            Outcome {
+                inflicted: 0,
+                received: 3
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_needs_attacker_and_weapon() {
+        let mut rolls = Rolls(vec![0]);
+        assert_eq!(
+            attack((1, 4), (1, 5), 0, Unit::OrcishGrunt).resolve(
+                &Unit::None.default_square(),
+                &MAP,
+                &mut rolls
+            ),
+            Err(RuleViolation::EmptyOrigin((1, 4)))
+        );
+        assert_eq!(
+            attack((1, 4), (1, 5), 1, Unit::OrcishGrunt).resolve(
+                &Unit::OrcishGrunt.default_square(),
+                &MAP,
+                &mut rolls
+            ),
+            Err(RuleViolation::UnknownWeapon(Unit::OrcishGrunt, 1))
+        );
+    }
+
+    #[test]
+    fn forged_damage_report_is_caught() {
+        let seed = SharedSeed::derive(&[7; 32], 3, [&[1; 32], &[2; 32]]);
+        let empty = vec![Unit::None.default_square(); 100];
+        // Deux grunts attaquent le troll en (1, 5), le second l'achève peut-être.
+        let attacks = [
+            attack((1, 4), (1, 5), 0, Unit::TrollWhelp),
+            attack((1, 6), (1, 5), 0, Unit::TrollWhelp),
+        ];
+        let mut attackers = empty.clone();
+        for attack in &attacks {
+            attackers[MAP.index(attack.from)] = Unit::OrcishGrunt.default_square();
+        }
+        let honest = Exchange::resolve(&attacks, &attackers, &MAP, &seed).unwrap();
+
+        // Le défenseur rejoue avec son troll et les grunts qu'il voit.
+        let mut defenders = empty.clone();
+        defenders[MAP.index((1, 5))] = Unit::TrollWhelp.default_square();
+        let enemies = attacks.map(|attack| VisibleEnemy {
+            position: attack.from,
+            unit: Unit::OrcishGrunt,
+            health_points: Unit::OrcishGrunt.max_health(),
+            captured: false,
+        });
+        let announced = attacks.each_ref().map(AnnouncedAttack::from);
+        let replayed = Exchange::replay(&announced, &defenders, &enemies, &MAP, &seed);
+        assert_eq!(replayed, Ok(honest.clone()));
+
+        // Un coup de plus annoncé sur le troll, ou une riposte de moins.
+        let mut forged = honest.clone();
+        forged.inflicted[MAP.index((1, 5))] += 1;
+        assert_ne!(replayed, Ok(forged));
+        let mut forged = honest.clone();
+        forged.received[MAP.index((1, 4))] = 0;
+        forged.inflicted[MAP.index((1, 5))] += honest.received[MAP.index((1, 4))];
+        assert_ne!(replayed, Ok(forged));
+
+        // D'autres jets que ceux de la graine du tour.
+        let other = SharedSeed::derive(&[7; 32], 4, [&[1; 32], &[2; 32]]);
+        let rerolled = Exchange::resolve(&attacks, &attackers, &MAP, &other).unwrap();
+        assert_ne!(rerolled, honest);
+        assert_ne!(replayed, Ok(rerolled));
+
+        // Une attaque sur une case où nous n'avons pas d'unité, ou depuis une
+        // case où nous ne voyons personne.
+        let mut misplaced = announced;
+        misplaced[0].to = (1, 3);
+        assert_eq!(
+            Exchange::replay(&misplaced, &defenders, &enemies, &MAP, &seed),
+            Err(RuleViolation::NoTarget((1, 3)))
+        );
+        assert_eq!(
+            Exchange::replay(&announced, &defenders, &enemies[..1], &MAP, &seed),
+            Err(RuleViolation::EmptyOrigin((1, 6)))
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Conversions vérifiées entre `BigUint`, `BigInt`, `Fr`, les chaînes
+//! décimales de Circom et les vecteurs de bits des circuits.
+//!
+//! Les circuits calculent modulo `p`, l'ordre de `Fr`. Une valeur hors de
+//! `[0, p)` est refusée. Comme Circom, les chaînes décimales négatives `-x`
+//! valent `p - x`. Un vecteur de
+//! bits a une largeur fixe, et une valeur qui ne tient pas dedans est refusée
+//! au lieu d'être tronquée.
+
+use std::fmt;
+
+use halo2curves::bn256::Fr;
+use halo2curves::ff::PrimeField;
+use num_bigint::{BigInt, BigUint, Sign};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    NotANumber(String),
+    /// Valeur absolue trop grande pour le corps, en bits.
+    OutOfField {
+        bits: u64,
+    },
+    TooWide {
+        bits: u64,
+        width: usize,
+    },
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::NotANumber(text) => {
+                write!(f, "{:?} n'est pas un nombre décimal", text)
+            }
+            ConversionError::OutOfField { bits } => {
+                write!(f, "valeur de {} bits hors du corps des circuits", bits)
+            }
+            ConversionError::TooWide { bits, width } => write!(
+                f,
+                "valeur de {} bits dans un vecteur de {} bits",
+                bits, width
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Ordre `p` du corps des circuits.
+pub fn field_modulus() -> BigUint {
+    BigUint::parse_bytes(Fr::MODULUS.trim_start_matches("0x").as_bytes(), 16).unwrap()
+}
+
+pub fn fr_to_biguint(value: &Fr) -> BigUint {
+    BigUint::from_bytes_le(&value.to_bytes())
+}
+
+/// Refuse les valeurs supérieures ou égales à `p`, quel que soit leur nombre
+/// d'octets significatifs.
+pub fn biguint_to_fr(value: &BigUint) -> Result<Fr, ConversionError> {
+    if *value >= field_modulus() {
+        return Err(ConversionError::OutOfField { bits: value.bits() });
+    }
+    let mut bytes = [0; 32];
+    let le = value.to_bytes_le();
+    bytes[..le.len()].copy_from_slice(&le);
+    Ok(Fr::from_bytes(&bytes).unwrap())
+}
+
+/// Valeur signée dans `(-p, p)`, les négatives valant `p - |x|`.
+pub fn bigint_to_fr(value: &BigInt) -> Result<Fr, ConversionError> {
+    let magnitude = biguint_to_fr(value.magnitude())?;
+    Ok(match value.sign() {
+        Sign::Minus => -magnitude,
+        _ => magnitude,
+    })
+}
+
+/// Chaîne décimale telle que Circom l'accepte en entrée.
+pub fn decimal_to_fr(text: &str) -> Result<Fr, ConversionError> {
+    let value: BigInt = text
+        .trim()
+        .parse()
+        .map_err(|_| ConversionError::NotANumber(text.to_string()))?;
+    bigint_to_fr(&value)
+}
+
+/// Chaîne décimale telle que Circom l'imprime, entre 0 et `p - 1`.
+pub fn fr_to_decimal(value: &Fr) -> String {
+    fr_to_biguint(value).to_string()
+}
+
+/// Bits petit-boutistes sur exactement `width` chiffres.
+pub fn to_bits(value: &BigUint, width: usize) -> Result<Vec<u8>, ConversionError> {
+    if value.bits() > width as u64 {
+        return Err(ConversionError::TooWide {
+            bits: value.bits(),
+            width,
+        });
+    }
+    Ok((0..width as u64).map(|bit| value.bit(bit) as u8).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::ff::Field;
+
+    use super::*;
+
+    fn modulus_minus(n: u8) -> BigUint {
+        field_modulus() - BigUint::from(n)
+    }
+
+    #[test]
+    fn modulus_matches_circom_prime() {
+        // `--prime bn128` de compile.sh.
+        assert_eq!(
+            field_modulus().to_string(),
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+        );
+    }
+
+    #[test]
+    fn field_bounds() {
+        assert_eq!(biguint_to_fr(&BigUint::default()), Ok(Fr::ZERO));
+        assert_eq!(biguint_to_fr(&modulus_minus(1)), Ok(-Fr::ONE));
+        assert_eq!(
+            biguint_to_fr(&field_modulus()),
+            Err(ConversionError::OutOfField { bits: 254 })
+        );
+        assert_eq!(
+            biguint_to_fr(&(BigUint::from(1u8) << 300)),
+            Err(ConversionError::OutOfField { bits: 301 })
+        );
+    }
+
+    #[test]
+    fn short_values_are_padded() {
+        // Moins de 32 octets significatifs, ce qui faisait paniquer l'ancien
+        // `try_into`.
+        for value in [1u64, 255, 256, u64::MAX] {
+            let fr = biguint_to_fr(&BigUint::from(value)).unwrap();
+            assert_eq!(fr, Fr::from(value));
+            assert_eq!(fr_to_biguint(&fr), BigUint::from(value));
+        }
+    }
+
+    #[test]
+    fn negative_decimals_wrap_like_circom() {
+        assert_eq!(decimal_to_fr("-1"), Ok(-Fr::ONE));
+        assert_eq!(decimal_to_fr("-0"), Ok(Fr::ZERO));
+        assert_eq!(
+            decimal_to_fr(&format!("-{}", modulus_minus(1))),
+            Ok(Fr::ONE)
+        );
+        assert_eq!(
+            decimal_to_fr(&format!("-{}", field_modulus())),
+            Err(ConversionError::OutOfField { bits: 254 })
+        );
+        assert_eq!(
+            decimal_to_fr("12a"),
+            Err(ConversionError::NotANumber("12a".to_string()))
+        );
+        assert_eq!(
+            fr_to_decimal(&decimal_to_fr("-2").unwrap()),
+            modulus_minus(2).to_string()
+        );
+    }
+
+    #[test]
+    fn bits_have_exact_width() {
+        let value = BigUint::from(0b1011u8);
+        assert_eq!(to_bits(&value, 4), Ok(vec![1, 1, 0, 1]));
+        assert_eq!(to_bits(&value, 6), Ok(vec![1, 1, 0, 1, 0, 0]));
+        assert_eq!(
+            to_bits(&value, 3),
+            Err(ConversionError::TooWide { bits: 4, width: 3 })
+        );
+        assert_eq!(to_bits(&BigUint::default(), 0), Ok(vec![]));
+
+        let widest = modulus_minus(1);
+        let bits = to_bits(&widest, 254).unwrap();
+        let mut rebuilt = BigUint::default();
+        for (index, &bit) in bits.iter().enumerate() {
+            rebuilt.set_bit(index as u64, bit == 1);
+        }
+        assert_eq!(rebuilt, widest);
+    }
+}
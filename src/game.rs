@@ -0,0 +1,543 @@
+//! Déroulement d'une partie simulée entre deux joueurs locaux.
+//!
+//! Chaque message entre les joueurs passe par `Game::send`, qui le note dans le
+//! journal de la partie et dans les transcriptions des deux joueurs.
+
+use std::str::FromStr;
+use std::time::Instant;
+
+use halo2curves::bn256::Fr;
+use serde::Serialize;
+
+use crate::journal::{Event, Journal, Message};
+use crate::metrics::{Metrics, R1csSize, Report, SideReport, BACKEND};
+use crate::phases::{Player, Side};
+use crate::prover::ProofError;
+use crate::randomness::{game_id, GameId, SharedSeed};
+use crate::rules::{RuleViolation, Scripted};
+use crate::{CompressedProof, State};
+
+/// Partie où c'est au tour de `S` de jouer.
+pub struct Game<S: Side> {
+    current: Player<S>,
+    opponent: Player<S::Opponent>,
+    turn: u64,
+    turn_limit: u64,
+    policy: ProofPolicy,
+    journal: Journal,
+}
+
+/// Quand un joueur demande la preuve de l'adversaire qui vient de jouer. La
+/// même politique s'applique aux deux joueurs.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ProofPolicy {
+    /// Seulement à la fin de la partie.
+    AtGameEnd,
+    /// Tous les `N` tours joués par l'adversaire.
+    Every(u64),
+    /// Au hasard, avec la probabilité donnée en pourcents, tirée de l'aléa
+    /// partagé du tour pour que l'adversaire ne puisse pas la prévoir.
+    RandomAudit(u64),
+    /// Quand l'adversaire a annoncé des dégâts incohérents avec notre état.
+    OnSuspicion,
+}
+
+impl ProofPolicy {
+    /// Décide d'une demande de preuve contre un adversaire qui a joué
+    /// `proven_steps` étapes, avec le tirage d'audit du tour s'il a eu lieu.
+    fn demands(&self, proven_steps: usize, audit_roll: Option<u64>, suspicious: bool) -> bool {
+        if proven_steps == 0 {
+            return false;
+        }
+        match *self {
+            ProofPolicy::AtGameEnd => false,
+            ProofPolicy::Every(turns) => (proven_steps as u64).is_multiple_of(turns),
+            ProofPolicy::RandomAudit(percent) => audit_roll.is_some_and(|roll| roll < percent),
+            ProofPolicy::OnSuspicion => suspicious,
+        }
+    }
+}
+
+impl FromStr for ProofPolicy {
+    type Err = String;
+
+    /// `fin`, `tous:N`, `audit:P` ou `soupcon`.
+    fn from_str(text: &str) -> Result<ProofPolicy, String> {
+        let number = |value: &str| {
+            value
+                .parse()
+                .map_err(|_| format!("{:?} n'est pas un entier", value))
+        };
+        match text.split_once(':') {
+            None if text == "fin" => Ok(ProofPolicy::AtGameEnd),
+            None if text == "soupcon" => Ok(ProofPolicy::OnSuspicion),
+            Some(("tous", turns)) => match number(turns)? {
+                0 => Err("une preuve tous les 0 tours n'a pas de sens".to_string()),
+                turns => Ok(ProofPolicy::Every(turns)),
+            },
+            Some(("audit", percent)) => match number(percent)? {
+                percent @ 0..=100 => Ok(ProofPolicy::RandomAudit(percent)),
+                percent => Err(format!("probabilité de {} % hors de 0 à 100", percent)),
+            },
+            _ => Err(format!("politique de preuve inconnue : {:?}", text)),
+        }
+    }
+}
+
+/// Raison de la fin de la partie.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+pub enum Ending {
+    /// Commandants morts, dans l'ordre des côtés.
+    CommanderKilled([bool; 2]),
+    TurnLimit,
+    /// Abandon du côté donné par son rang.
+    Resignation(u8),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+pub enum GameResult {
+    Won,
+    Lost,
+    Drawn,
+}
+
+/// Partie terminée, une fois les preuves finales échangées.
+#[derive(Debug)]
+pub struct GameOver {
+    pub ending: Ending,
+    pub turn: u64,
+    /// Preuve finale de chaque côté vérifiée par l'autre, dans l'ordre des
+    /// côtés.
+    pub proofs_verified: [bool; 2],
+    /// Résultat de chaque côté, dans l'ordre des côtés.
+    pub results: [GameResult; 2],
+    game_id: GameId,
+    map: (u64, u64),
+    r1cs: R1csSize,
+    /// Noms et mesures des joueurs, dans l'ordre des côtés.
+    names: [&'static str; 2],
+    metrics: [Metrics; 2],
+    pub journal: Journal,
+}
+
+impl GameOver {
+    pub fn report(&self) -> Report<'_> {
+        let side = |index: usize| SideReport {
+            name: self.names[index],
+            result: self.results[index],
+            metrics: &self.metrics[index],
+        };
+        Report {
+            game_id: self
+                .game_id
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            map: self.map,
+            backend: BACKEND,
+            r1cs: self.r1cs.clone(),
+            turns: self.turn,
+            ending: self.ending,
+            sides: [side(0), side(1)],
+        }
+    }
+}
+
+impl Ending {
+    /// Résultats dans l'ordre des côtés. Un côté dont la preuve finale est
+    /// refusée perd la partie, quelle que soit la raison de la fin.
+    fn results(&self, proofs_verified: [bool; 2]) -> [GameResult; 2] {
+        let defeated = match *self {
+            Ending::CommanderKilled(dead) => dead,
+            Ending::TurnLimit => [false; 2],
+            Ending::Resignation(side) => [side == 0, side == 1],
+        };
+        let defeated = match proofs_verified {
+            [true, true] => defeated,
+            verified => verified.map(|verified| !verified),
+        };
+        match defeated {
+            [true, false] => [GameResult::Lost, GameResult::Won],
+            [false, true] => [GameResult::Won, GameResult::Lost],
+            _ => [GameResult::Drawn; 2],
+        }
+    }
+}
+
+/// Vérifie la preuve des étapes jouées par `prover` depuis l'état initial qu'il
+/// a engagé, pour le compte de `verifier` et avec ses propres clés. La preuve
+/// est rendue avec sa sortie `z_n`, rien si elle est refusée.
+fn verify_steps(
+    proof: CompressedProof,
+    prover: &State,
+    verifier: &mut State,
+) -> (Box<CompressedProof>, Option<Vec<Fr>>) {
+    let begin = Instant::now();
+    let (proof, z_n) = verifier.prover.verify(
+        Box::new(proof),
+        prover.proven_steps(),
+        prover.initial_hash.clone(),
+    );
+    verifier.metrics.time("proof.verify", begin);
+    let z_n = match z_n {
+        Ok(z_n) => Some(z_n),
+        Err(ProofError::Rejected(_)) => None,
+        Err(error) => panic!("Vérification impossible : {}", error),
+    };
+    (proof, z_n)
+}
+
+impl<S: Side> Game<S> {
+    /// Lie les deux joueurs à une même partie, identifiée par leurs hashs
+    /// initiaux dans l'ordre des côtés.
+    ///
+    /// La partie est nulle une fois `turn_limit` tours joués.
+    pub fn new(
+        mut current: Player<S>,
+        mut opponent: Player<S::Opponent>,
+        turn_limit: u64,
+        policy: ProofPolicy,
+    ) -> Game<S> {
+        let current_hash = current.initial_hash[0].to_bytes();
+        let opponent_hash = opponent.initial_hash[0].to_bytes();
+        let hashes = if S::INDEX < <S::Opponent as Side>::INDEX {
+            [&current_hash[..], &opponent_hash[..]]
+        } else {
+            [&opponent_hash[..], &current_hash[..]]
+        };
+        let game_id = game_id(hashes);
+        for state in [&mut *current, &mut *opponent] {
+            state.game_id = game_id;
+            state.transcript.absorb("partie", &game_id);
+        }
+        let initial_hashes = if S::INDEX < <S::Opponent as Side>::INDEX {
+            [current.initial_hash.clone(), opponent.initial_hash.clone()]
+        } else {
+            [opponent.initial_hash.clone(), current.initial_hash.clone()]
+        };
+
+        Game {
+            current,
+            opponent,
+            turn: 1,
+            turn_limit,
+            policy,
+            journal: Journal::new(game_id, initial_hashes),
+        }
+    }
+
+    /// Les deux joueurs, celui du côté `side` en premier.
+    fn sides(&mut self, side: u8) -> (&mut State, &mut State) {
+        if side == S::INDEX {
+            (&mut self.current, &mut self.opponent)
+        } else {
+            (&mut self.opponent, &mut self.current)
+        }
+    }
+
+    /// Transmet un message du côté `from` à l'autre : l'expéditeur l'absorbe
+    /// comme envoyé, le destinataire comme reçu.
+    fn send(&mut self, from: u8, message: Message) {
+        let bytes = message.encode();
+        let (sender, receiver) = self.sides(from);
+        sender.transcript.absorb("envoi", &bytes);
+        receiver.transcript.absorb("réception", &bytes);
+        self.journal
+            .record(self.turn, Event::Message { from, message });
+    }
+
+    /// Note les empreintes des transcriptions à la fin d'un échange.
+    fn record_digests(&mut self) {
+        let mut digests = [[0; 32]; 2];
+        digests[S::INDEX as usize] = self.current.transcript.digest();
+        digests[<S::Opponent as Side>::INDEX as usize] = self.opponent.transcript.digest();
+        self.journal.record(self.turn, Event::Digests(digests));
+    }
+
+    /// L'autre côté demande et vérifie la preuve du côté `prover`, s'il a
+    /// joué au moins une étape. Une preuve que `prover` ne peut pas produire
+    /// est refusée, de même qu'une preuve qui contredit la mort qu'il a
+    /// annoncée.
+    fn exchange_proof(&mut self, prover: u8) -> Option<bool> {
+        let (prover_state, _) = self.sides(prover);
+        let steps = prover_state.proven_steps();
+        if steps == 0 {
+            return None;
+        }
+        self.send(1 - prover, Message::ProofRequest);
+        let (prover_state, verifier_state) = self.sides(prover);
+        let verified = match prover_state.prove() {
+            Ok(proof) => {
+                let (proof, z_n) = verify_steps(proof, prover_state, verifier_state);
+                self.send(prover, Message::Proof { steps, proof });
+                z_n.is_some_and(|z_n| self.journal.death_confirmed(prover, z_n[0]).unwrap_or(true))
+            }
+            Err(error) => {
+                println!("Preuve du joueur {} impossible : {}.", prover, error);
+                false
+            }
+        };
+        self.journal.record(
+            self.turn,
+            Event::Verdict {
+                side: prover,
+                verified,
+            },
+        );
+        self.record_digests();
+        Some(verified)
+    }
+
+    /// Dernière preuve reçue par l'un ou l'autre côté.
+    pub fn last_proof(&self) -> Option<&CompressedProof> {
+        self.journal.last_proof()
+    }
+
+    /// Numéro du tour en cours, en commençant à 1.
+    pub fn turn(&self) -> u64 {
+        self.turn
+    }
+
+    /// Prévoit les actions d'un tour scripté pour `S`, et renvoie celles que
+    /// les règles refusent.
+    pub fn script(&mut self, turn: &[Scripted]) -> Vec<RuleViolation> {
+        self.current.append_turn(turn)
+    }
+
+    /// Joue le tour de `S` : les deux joueurs tirent l'aléa des combats, puis
+    /// l'adversaire lance la PSI, `S` applique ses actions et y répond, et
+    /// l'adversaire obtient sa vision. La main passe ensuite à l'adversaire.
+    pub fn play_turn(mut self) -> Game<S::Opponent> {
+        self.current.metrics.set_turn(self.turn);
+        self.opponent.metrics.set_turn(self.turn);
+        let (current, opponent) = (S::INDEX, <S::Opponent as Side>::INDEX);
+        let (current_secret, current_commitment) = self.current.commit_roll(self.turn);
+        self.send(current, Message::from(&current_commitment));
+        let (opponent_secret, opponent_commitment) = self.opponent.commit_roll(self.turn);
+        self.send(opponent, Message::from(&opponent_commitment));
+        let (current_roll, current_reveal) = self
+            .current
+            .reveal_roll(current_secret, opponent_commitment);
+        self.send(current, Message::from(&current_reveal));
+        let (opponent_roll, opponent_reveal) = self
+            .opponent
+            .reveal_roll(opponent_secret, current_commitment);
+        self.send(opponent, Message::from(&opponent_reveal));
+        self.current
+            .agree_roll(current_roll, opponent_reveal)
+            .unwrap_or_else(|error| panic!("Tirage de l'aléa refusé : {}", error));
+        self.opponent
+            .agree_roll(opponent_roll, current_reveal)
+            .unwrap_or_else(|error| panic!("Tirage de l'aléa refusé : {}", error));
+
+        let (secret, message) = self.opponent.phase1();
+        self.send(opponent, Message::from(&message));
+        let response = self.current.phase2(message);
+        self.send(current, Message::from(&response));
+        self.opponent.phase3(secret, response);
+        self.record_digests();
+
+        Game {
+            current: self.opponent,
+            opponent: self.current,
+            turn: self.turn + 1,
+            turn_limit: self.turn_limit,
+            policy: self.policy,
+            journal: self.journal,
+        }
+    }
+
+    /// Le joueur dont c'est le tour demande la preuve de l'adversaire qui vient
+    /// de jouer, et la vérifie depuis l'état initial qu'il avait engagé.
+    pub fn request_proof(&mut self) -> bool {
+        self.exchange_proof(<S::Opponent as Side>::INDEX)
+            .expect("L'adversaire n'a encore joué aucun tour")
+    }
+
+    /// Le joueur dont c'est le tour décide, selon la politique, s'il demande la
+    /// preuve de l'adversaire qui vient de jouer.
+    pub fn demands_proof(&self) -> bool {
+        self.policy.demands(
+            self.opponent.proven_steps(),
+            self.current.roll_seed.as_ref().map(SharedSeed::audit_roll),
+            self.current.suspicious,
+        )
+    }
+
+    /// Demande et vérifie la preuve de l'adversaire si la politique l'exige.
+    pub fn audit(&mut self) -> Option<bool> {
+        if !self.demands_proof() {
+            return None;
+        }
+        self.current.suspicious = false;
+        Some(self.request_proof())
+    }
+
+    /// Raison de finir la partie avant le tour en cours, s'il y en a une. Chaque
+    /// joueur constate lui-même la mort de son commandant, les dégâts reçus
+    /// n'étant connus que de lui, et l'annonce à l'autre avec son état.
+    pub fn ending(&mut self) -> Option<Ending> {
+        let announced = self.journal.dead_commanders();
+        for side in [S::INDEX, <S::Opponent as Side>::INDEX] {
+            let (state, _) = self.sides(side);
+            if !announced[side as usize] && !state.commander_alive() {
+                let state = state.circuit_state.hash_inputs();
+                self.send(side, Message::CommanderDead { state });
+            }
+        }
+        let dead = self.journal.dead_commanders();
+        if dead.contains(&true) {
+            Some(Ending::CommanderKilled(dead))
+        } else if self.turn > self.turn_limit {
+            Some(Ending::TurnLimit)
+        } else {
+            None
+        }
+    }
+
+    /// Le joueur dont c'est le tour abandonne.
+    pub fn resign(self) -> GameOver {
+        self.finish(Ending::Resignation(S::INDEX))
+    }
+
+    /// Verdict sur la preuve finale de `side`. Sans étape jouée, il n'y a rien
+    /// à prouver, mais une mort annoncée doit partir de l'état initial.
+    fn final_proof(&mut self, side: u8) -> bool {
+        if let Some(verified) = self.exchange_proof(side) {
+            return verified;
+        }
+        let (state, _) = self.sides(side);
+        let initial_hash = state.initial_hash[0];
+        match self.journal.death_confirmed(side, initial_hash) {
+            Some(verified) => {
+                self.journal
+                    .record(self.turn, Event::Verdict { side, verified });
+                verified
+            }
+            None => true,
+        }
+    }
+
+    /// Termine la partie : chaque joueur fournit la preuve finale de ses tours,
+    /// que l'autre vérifie avant que le résultat ne soit arrêté.
+    pub fn finish(mut self, ending: Ending) -> GameOver {
+        self.current.metrics.set_turn(self.turn);
+        self.opponent.metrics.set_turn(self.turn);
+        let (current, opponent) = (S::INDEX as usize, <S::Opponent as Side>::INDEX as usize);
+        let mut proofs_verified = [false; 2];
+        proofs_verified[current] = self.final_proof(S::INDEX);
+        proofs_verified[opponent] = self.final_proof(<S::Opponent as Side>::INDEX);
+
+        let mut names = [""; 2];
+        names[current] = S::NAME;
+        names[opponent] = <S::Opponent as Side>::NAME;
+        let mut metrics = [Metrics::default(), Metrics::default()];
+        metrics[current] = std::mem::take(&mut self.current.metrics);
+        metrics[opponent] = std::mem::take(&mut self.opponent.metrics);
+        GameOver {
+            ending,
+            turn: self.turn,
+            proofs_verified,
+            results: ending.results(proofs_verified),
+            game_id: self.current.game_id,
+            map: self.current.initial_state.size(),
+            r1cs: R1csSize::from(&self.current.r1cs),
+            names,
+            metrics,
+            journal: self.journal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use GameResult::{Drawn, Lost, Won};
+
+    const VERIFIED: [bool; 2] = [true, true];
+
+    #[test]
+    fn every_policy_decides_on_its_own_input() {
+        // Rien à prouver tant que l'adversaire n'a pas joué.
+        for policy in [
+            ProofPolicy::AtGameEnd,
+            ProofPolicy::Every(1),
+            ProofPolicy::RandomAudit(100),
+            ProofPolicy::OnSuspicion,
+        ] {
+            assert!(!policy.demands(0, Some(0), true), "{:?}", policy);
+        }
+
+        assert!(!ProofPolicy::AtGameEnd.demands(3, Some(0), true));
+
+        let every = ProofPolicy::Every(2);
+        assert!(!every.demands(1, None, false));
+        assert!(every.demands(2, None, false));
+        assert!(!every.demands(3, Some(0), true));
+        assert!(every.demands(4, None, false));
+
+        let audit = ProofPolicy::RandomAudit(30);
+        assert!(audit.demands(1, Some(29), false));
+        assert!(!audit.demands(1, Some(30), true));
+        assert!(!audit.demands(1, None, true));
+        assert!(!ProofPolicy::RandomAudit(0).demands(1, Some(0), false));
+
+        assert!(ProofPolicy::OnSuspicion.demands(1, None, true));
+        assert!(!ProofPolicy::OnSuspicion.demands(1, Some(0), false));
+    }
+
+    #[test]
+    fn policies_parse() {
+        assert_eq!("fin".parse(), Ok(ProofPolicy::AtGameEnd));
+        assert_eq!("tous:3".parse(), Ok(ProofPolicy::Every(3)));
+        assert_eq!("audit:25".parse(), Ok(ProofPolicy::RandomAudit(25)));
+        assert_eq!("soupcon".parse(), Ok(ProofPolicy::OnSuspicion));
+        for text in [
+            "",
+            "tous",
+            "tous:0",
+            "tous:x",
+            "audit:101",
+            "jamais",
+            "fin:1",
+        ] {
+            assert!(text.parse::<ProofPolicy>().is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn results_follow_the_ending() {
+        assert_eq!(
+            Ending::CommanderKilled([true, false]).results(VERIFIED),
+            [Lost, Won]
+        );
+        assert_eq!(
+            Ending::CommanderKilled([false, true]).results(VERIFIED),
+            [Won, Lost]
+        );
+        assert_eq!(
+            Ending::CommanderKilled([true, true]).results(VERIFIED),
+            [Drawn, Drawn]
+        );
+        assert_eq!(Ending::TurnLimit.results(VERIFIED), [Drawn, Drawn]);
+        assert_eq!(Ending::Resignation(0).results(VERIFIED), [Lost, Won]);
+        assert_eq!(Ending::Resignation(1).results(VERIFIED), [Won, Lost]);
+    }
+
+    #[test]
+    fn refused_proof_loses_whatever_the_ending() {
+        for ending in [
+            Ending::CommanderKilled([false, true]),
+            Ending::TurnLimit,
+            Ending::Resignation(1),
+        ] {
+            assert_eq!(ending.results([false, true]), [Lost, Won], "{:?}", ending);
+        }
+        assert_eq!(Ending::Resignation(0).results([true, false]), [Won, Lost]);
+        assert_eq!(
+            Ending::CommanderKilled([true, false]).results([false, false]),
+            [Drawn, Drawn]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Géométrie de la grille hexagonale de Wesnoth, telle que l'utilisent les
+//! circuits (`functions.circom`) : coordonnées rectangulaires `(x, y)` avec les
+//! colonnes impaires descendues d'une demi-case (odd-q), hexagones à haut plat.
+//! Les calculs se font en coordonnées axiales `(q, r)` ou cubiques.
+
+use crate::Position;
+
+/// Coordonnées axiales `(q, r)`.
+pub type Axial = (i64, i64);
+/// Coordonnées cubiques `(q, r, s)`, avec `q + r + s = 0`.
+#[allow(dead_code)]
+pub type Cube = (i64, i64, i64);
+
+/// Directions des voisins en axial, dans l'ordre des circuits : haut-droit,
+/// haut, haut-gauche, bas-gauche, bas, bas-droit.
+pub const DIRECTIONS: [Axial; 6] = [(1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1), (1, 0)];
+
+pub fn to_axial((x, y): Position) -> Axial {
+    let (x, y) = (x as i64, y as i64);
+    (x, y - (x - (x & 1)) / 2)
+}
+
+/// Position d'une case axiale, `None` si elle tombe en coordonnées négatives.
+pub fn from_axial((q, r): Axial) -> Option<Position> {
+    let y = r + (q - (q & 1)) / 2;
+    (q >= 0 && y >= 0).then_some((q as u64, y as u64))
+}
+
+#[allow(dead_code)]
+pub fn axial_to_cube((q, r): Axial) -> Cube {
+    (q, r, -q - r)
+}
+
+#[allow(dead_code)]
+pub fn cube_to_axial((q, r, _): Cube) -> Axial {
+    (q, r)
+}
+
+pub fn axial_distance((q1, r1): Axial, (q2, r2): Axial) -> u64 {
+    let (dq, dr) = (q1 - q2, r1 - r2);
+    ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u64
+}
+
+/// Nombre de déplacements d'une case à l'autre, comme le template `Distance`.
+pub fn distance(one: Position, two: Position) -> u64 {
+    axial_distance(to_axial(one), to_axial(two))
+}
+
+/// Voisin dans la direction `dir` (indice dans `DIRECTIONS`), hors bornes de
+/// la carte compris tant que les coordonnées restent positives.
+pub fn neighbour(position: Position, dir: usize) -> Option<Position> {
+    let (q, r) = to_axial(position);
+    let (dq, dr) = DIRECTIONS[dir];
+    from_axial((q + dq, r + dr))
+}
+
+/// Dimensions d'une carte, pour passer des positions aux indices de cases.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Grid {
+    pub width: u64,
+    pub height: u64,
+}
+
+impl Grid {
+    pub fn new((width, height): (u64, u64)) -> Self {
+        Grid { width, height }
+    }
+
+    pub fn len(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    pub fn contains(&self, (x, y): Position) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// Indice de la case, `x * state_height + y` dans les circuits.
+    pub fn index(&self, (x, y): Position) -> usize {
+        (x * self.height + y) as usize
+    }
+
+    pub fn position(&self, index: usize) -> Position {
+        let index = index as u64;
+        (index / self.height, index % self.height)
+    }
+
+    fn axial_in_grid(&self, axial: Axial) -> Option<Position> {
+        from_axial(axial).filter(|&position| self.contains(position))
+    }
+
+    /// Voisins sur la carte, dans l'ordre de `DIRECTIONS`.
+    pub fn neighbours(&self, position: Position) -> impl Iterator<Item = Position> + '_ {
+        (0..DIRECTIONS.len()).filter_map(move |dir| {
+            neighbour(position, dir).filter(|&position| self.contains(position))
+        })
+    }
+
+    /// Cases sur la carte à exactement `radius` de `center`, parcourues comme
+    /// dans `Vision` : en partant de la case du dessous, puis en tournant dans
+    /// l'ordre de `DIRECTIONS`.
+    pub fn ring(&self, center: Position, radius: u64) -> impl Iterator<Item = Position> + '_ {
+        let (q, r) = to_axial(center);
+        let radius = radius as i64;
+        let start = (q, r + radius);
+        let steps = if radius == 0 { 1 } else { 6 * radius };
+        (0..steps).filter_map(move |step| {
+            // Au pas `step`, on a fait `step / radius` côtés complets.
+            let (mut q, mut r) = start;
+            for (side, &(dq, dr)) in DIRECTIONS.iter().enumerate() {
+                let done = (step - side as i64 * radius).clamp(0, radius);
+                q += dq * done;
+                r += dr * done;
+            }
+            self.axial_in_grid((q, r))
+        })
+    }
+
+    /// Cases sur la carte à au plus `radius` de `center`, `center` compris,
+    /// rayon par rayon.
+    #[allow(dead_code)]
+    pub fn within(&self, center: Position, radius: u64) -> impl Iterator<Item = Position> + '_ {
+        (0..=radius).flat_map(move |radius| self.ring(center, radius))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Reprises telles quelles des fonctions et templates des circuits.
+    pub(crate) mod circuit {
+        pub fn rect_to_ax(c: [i64; 2]) -> [i64; 2] {
+            [c[0], c[1] - (c[0] - (c[0] % 2)) / 2]
+        }
+
+        pub fn ax_to_rect(c: [i64; 2]) -> [i64; 2] {
+            [c[0], c[1] + (c[0] - (c[0] % 2)) / 2]
+        }
+
+        pub fn dist_ax(one: [i64; 2], two: [i64; 2]) -> i64 {
+            let qdiff = one[0] - two[0];
+            let rdiff = one[1] - two[1];
+            (qdiff.abs() + (qdiff + rdiff).abs() + rdiff.abs()) / 2
+        }
+
+        pub fn neighbour_ax(coord: [i64; 2], dir: usize) -> [i64; 2] {
+            let ax_dirs = [[1, -1], [0, -1], [-1, 0], [-1, 1], [0, 1], [1, 0]];
+            [coord[0] + ax_dirs[dir][0], coord[1] + ax_dirs[dir][1]]
+        }
+
+        pub fn neighbour_rect(coord: [i64; 2], dir: usize) -> [i64; 2] {
+            let rect_dirs = [
+                [[1, -1], [0, -1], [-1, -1], [-1, 0], [0, 1], [1, 0]],
+                [[1, 0], [0, -1], [-1, 0], [-1, 1], [0, 1], [1, 1]],
+            ];
+            let is_odd = (coord[0].abs() % 2) as usize;
+            [
+                coord[0] + rect_dirs[is_odd][dir][0],
+                coord[1] + rect_dirs[is_odd][dir][1],
+            ]
+        }
+
+        pub fn nb_neighbours(
+            state_height: i64,
+            state_width: i64,
+            x: i64,
+            y: i64,
+            radius: i64,
+        ) -> i64 {
+            if radius <= x && x + radius < state_height && radius <= y && y + radius < state_width {
+                return 3 * radius * (radius + 1);
+            }
+            let mut count = 0;
+            for rayon in 1..=radius {
+                let mut cur_coord_rect = [x, y + rayon];
+                for orientation in 0..6 {
+                    for _ in 0..rayon {
+                        if cur_coord_rect[0] < state_height
+                            && 0 <= cur_coord_rect[0]
+                            && cur_coord_rect[1] < state_width
+                            && 0 <= cur_coord_rect[1]
+                        {
+                            count += 1;
+                        }
+                        cur_coord_rect = neighbour_rect(cur_coord_rect, orientation);
+                    }
+                }
+            }
+            count
+        }
+
+        /// Template `Distance` de `regles.circom`, qui ne manipule que des
+        /// différences positives.
+        pub fn distance(in1: [i64; 2], in2: [i64; 2]) -> i64 {
+            let diff = |in1: i64, in2: i64| (in1 - in2).abs();
+            let (div1, div2) = (in1[0] / 2, in2[0] / 2);
+            let qdiff = diff(in1[0], in2[0]);
+            let rdiff = diff(in1[1] + div2, in2[1] + div1);
+            let sumdiff = diff(in1[0] + in1[1] + div2, in2[0] + in2[1] + div1);
+            (qdiff + sumdiff + rdiff) / 2
+        }
+    }
+
+    const FUNCTIONS: [&str; 3] = [
+        include_str!("../wesnoth-zkpsi/phase1/functions.circom"),
+        include_str!("../wesnoth-zkpsi/phase2/functions.circom"),
+        include_str!("../wesnoth-zkpsi/phase2nova/functions.circom"),
+    ];
+
+    const GRID: Grid = Grid {
+        width: 10,
+        height: 10,
+    };
+
+    fn positions() -> impl Iterator<Item = Position> {
+        (0..GRID.len()).map(|index| GRID.position(index))
+    }
+
+    fn rect((x, y): Position) -> [i64; 2] {
+        [x as i64, y as i64]
+    }
+
+    #[test]
+    fn functions_sources_are_pinned() {
+        // Les trois copies doivent rester identiques, et contenir ce que
+        // reprend le module `circuit`.
+        assert!(FUNCTIONS.iter().all(|source| *source == FUNCTIONS[0]));
+        for line in [
+            "function ax_to_rect(c) { return [c[0], c[1] + (c[0] - (c[0] % 2)) \\ 2]; }",
+            "function rect_to_ax(c) { return [c[0], c[1] - (c[0] - (c[0] % 2)) \\ 2]; }",
+            "var is_odd = abs(coord[0]) % 2;",
+            "if (radius <= x && x + radius < state_height && radius <= y && y + radius < state_width) {",
+            "return 3 * radius * (radius + 1);",
+        ] {
+            assert!(
+                FUNCTIONS[0].contains(line),
+                "{} absent de functions.circom",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let grid = Grid::new((7, 4));
+        for index in 0..grid.len() {
+            let position = grid.position(index);
+            assert!(grid.contains(position));
+            assert_eq!(grid.index(position), index);
+        }
+        assert_eq!(GRID.index((5, 0)), 50);
+        assert_eq!(GRID.index((9, 9)), 99);
+    }
+
+    #[test]
+    fn axial_conversions_match_circuit() {
+        for position in positions() {
+            let axial = to_axial(position);
+            assert_eq!([axial.0, axial.1], circuit::rect_to_ax(rect(position)));
+            assert_eq!(circuit::ax_to_rect([axial.0, axial.1]), rect(position));
+            assert_eq!(from_axial(axial), Some(position));
+            let cube = axial_to_cube(axial);
+            assert_eq!(cube.0 + cube.1 + cube.2, 0);
+            assert_eq!(cube_to_axial(cube), axial);
+        }
+    }
+
+    #[test]
+    fn neighbours_match_circuit() {
+        for position in positions() {
+            let axial = to_axial(position);
+            for (dir, &(dq, dr)) in DIRECTIONS.iter().enumerate() {
+                let expected = circuit::neighbour_rect(rect(position), dir);
+                let (q, r) = (axial.0 + dq, axial.1 + dr);
+                assert_eq!([q, r], circuit::neighbour_ax([axial.0, axial.1], dir));
+                match neighbour(position, dir) {
+                    Some(found) => assert_eq!(rect(found), expected),
+                    None => assert!(expected[0] < 0 || expected[1] < 0),
+                }
+            }
+            let on_map = GRID.neighbours(position).count();
+            assert_eq!(
+                on_map as i64,
+                circuit::nb_neighbours(10, 10, rect(position)[0], rect(position)[1], 1)
+            );
+        }
+    }
+
+    #[test]
+    fn distance_matches_circuit() {
+        for one in positions() {
+            for two in positions() {
+                let expected = circuit::distance(rect(one), rect(two));
+                assert_eq!(distance(one, two) as i64, expected);
+                assert_eq!(
+                    circuit::dist_ax(
+                        circuit::rect_to_ax(rect(one)),
+                        circuit::rect_to_ax(rect(two))
+                    ),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rings_match_circuit() {
+        for center in positions() {
+            for radius in 1..=8 {
+                let ring: Vec<_> = GRID.ring(center, radius).collect();
+                assert!(ring
+                    .iter()
+                    .all(|&position| distance(center, position) == radius));
+
+                // Même parcours que `Vision`, bornes comprises.
+                let (x, y) = (rect(center)[0], rect(center)[1]);
+                let mut expected = vec![];
+                let mut cur_coord_rect = [x, y + radius as i64];
+                for orientation in 0..6 {
+                    for _ in 0..radius {
+                        if (0..10).contains(&cur_coord_rect[0])
+                            && (0..10).contains(&cur_coord_rect[1])
+                        {
+                            expected.push(cur_coord_rect);
+                        }
+                        cur_coord_rect = circuit::neighbour_rect(cur_coord_rect, orientation);
+                    }
+                }
+                assert_eq!(ring.into_iter().map(rect).collect::<Vec<_>>(), expected);
+
+                let within = GRID.within(center, radius).count() as i64;
+                assert_eq!(
+                    within - 1,
+                    circuit::nb_neighbours(10, 10, x, y, radius as i64)
+                );
+            }
+        }
+    }
+}
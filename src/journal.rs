@@ -0,0 +1,963 @@
+//! Journal d'une partie : les messages échangés dans les deux sens, les
+//! demandes de preuve et les preuves, avec leur tour.
+//!
+//! Chaque message passe aussi dans la transcription de ses deux joueurs,
+//! absorbé comme envoyé par l'un et comme reçu par l'autre. Le journal note les
+//! deux empreintes après chaque échange. `Journal::replay` recalcule ces deux
+//! transcriptions depuis les messages, ainsi que la chaîne des échanges que le
+//! circuit de la phase 2 accumule pour chaque côté. Il revérifie chaque preuve
+//! avec une clé tirée des paramètres publics, jamais fournie par le joueur, et
+//! exige qu'elle finisse sur la chaîne recalculée. Il se passe des circuits et
+//! s'arrête au premier tour incohérent.
+//!
+//! Un joueur dont le commandant est mort l'annonce en révélant son état : la
+//! preuve qui suit n'est acceptée que si cet état est celui qu'elle établit et
+//! que les dégâts reçus depuis y tuent le commandant.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use halo2curves::bn256::Fr;
+use halo2curves::ff::{Field, PrimeField};
+use serde::{Deserialize, Serialize};
+
+use crate::anemoi::sponge;
+use crate::combat::AnnouncedAttack;
+use crate::convert::decimal_to_fr;
+use crate::prover::verify;
+use crate::randomness::{commitment_limbs, game_id, Commitment, GameId, Nonce, Transcript};
+use crate::unit::Unit;
+use crate::{CompressedProof, HiddenData, Point, ProofVerifierKey};
+
+/// Entier en décimal, tel que les circuits le lisent et l'impriment.
+type Decimal = String;
+
+pub fn decimal_points(points: &[Point]) -> Vec<(Decimal, Decimal)> {
+    points
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect()
+}
+
+pub fn decimal_data(data: &[HiddenData]) -> Vec<(Decimal, Decimal, Decimal)> {
+    data.iter()
+        .map(|(a, b, c)| (a.to_string(), b.to_string(), c.to_string()))
+        .collect()
+}
+
+/// Ce qu'un joueur envoie à l'autre.
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    RollCommitment(Commitment),
+    RollReveal {
+        turn: u64,
+        nonce: Nonce,
+    },
+    Phase1 {
+        diffie_hellman: Vec<(Decimal, Decimal)>,
+    },
+    Phase2 {
+        dh_output: Vec<(Decimal, Decimal)>,
+        hidden_tags: Vec<(Decimal, Decimal)>,
+        hidden_data: Vec<(Decimal, Decimal, Decimal)>,
+        /// Points de phase 1 accumulés dans la chaîne de l'expéditeur.
+        phase1_output: Vec<(Decimal, Decimal)>,
+        /// Dégâts infligés au destinataire.
+        damages: Vec<u64>,
+        /// Ripostes reçues par l'expéditeur.
+        received: Vec<u64>,
+        /// Villages capturés par l'expéditeur pendant son tour.
+        captures: Vec<u64>,
+        /// Attaques de l'expéditeur, que le destinataire a rejouées.
+        attacks: Vec<AnnouncedAttack>,
+    },
+    ProofRequest,
+    /// Mort du commandant de l'expéditeur, avec son état en clair après sa
+    /// dernière étape, dans l'ordre des entrées du hachage d'état.
+    CommanderDead {
+        state: Vec<u64>,
+    },
+    /// Preuve des `steps` premières étapes de l'expéditeur. La clé de
+    /// vérification n'est pas transmise : chacun la tire des paramètres publics.
+    Proof {
+        steps: usize,
+        proof: Box<CompressedProof>,
+    },
+}
+
+impl Message {
+    /// Octets absorbés par les transcriptions.
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Message impossible à sérialiser")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Event {
+    /// Message du côté `from` à l'autre.
+    Message { from: u8, message: Message },
+    /// Verdict de l'autre côté sur la dernière preuve de `side`, refusée si
+    /// `side` n'a pas pu la produire.
+    Verdict { side: u8, verified: bool },
+    /// Empreintes des transcriptions, dans l'ordre des côtés.
+    Digests([[u8; 32]; 2]),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub turn: u64,
+    pub event: Event,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Journal {
+    game_id: GameId,
+    /// Entrée publique `z0` de la preuve de chaque côté, dans l'ordre des
+    /// côtés.
+    initial_hashes: [Vec<Fr>; 2],
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// Les hashs initiaux d'un côté ne sont pas l'état et la chaîne.
+    InitialHash {
+        side: u8,
+    },
+    /// L'identifiant ne correspond pas aux hashs initiaux.
+    GameId,
+    /// Entrée attribuée à un côté qui n'existe pas.
+    Side {
+        turn: u64,
+        side: u8,
+    },
+    TurnOrder {
+        turn: u64,
+        previous: u64,
+    },
+    Digest {
+        turn: u64,
+        side: u8,
+    },
+    /// Une preuve ne couvre pas toutes les phases 2 envoyées par son côté.
+    StepCount {
+        turn: u64,
+        side: u8,
+        claimed: usize,
+        played: usize,
+    },
+    /// Un message est illisible, ou une phase 2 ne répond pas à une phase 1
+    /// de même taille ou annonce des captures invalides.
+    Message {
+        turn: u64,
+        side: u8,
+    },
+    ProofRejected {
+        turn: u64,
+        side: u8,
+    },
+    /// La preuve ne finit pas sur la chaîne recalculée depuis les messages.
+    Chain {
+        turn: u64,
+        side: u8,
+    },
+    /// Le verdict noté pendant la partie n'est pas celui recalculé.
+    Verdict {
+        turn: u64,
+        side: u8,
+        recorded: bool,
+    },
+}
+
+impl Inconsistency {
+    pub fn turn(&self) -> u64 {
+        match *self {
+            Inconsistency::InitialHash { .. } | Inconsistency::GameId => 0,
+            Inconsistency::Side { turn, .. }
+            | Inconsistency::TurnOrder { turn, .. }
+            | Inconsistency::Digest { turn, .. }
+            | Inconsistency::StepCount { turn, .. }
+            | Inconsistency::Message { turn, .. }
+            | Inconsistency::ProofRejected { turn, .. }
+            | Inconsistency::Chain { turn, .. }
+            | Inconsistency::Verdict { turn, .. } => turn,
+        }
+    }
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tour {} : ", self.turn())?;
+        match self {
+            Inconsistency::InitialHash { side } => write!(
+                f,
+                "les hashs initiaux du joueur {} ne sont pas un état et une chaîne",
+                side
+            ),
+            Inconsistency::GameId => {
+                write!(
+                    f,
+                    "l'identifiant de la partie ne vient pas des hashs initiaux"
+                )
+            }
+            Inconsistency::Side { side, .. } => {
+                write!(f, "entrée attribuée à un joueur {} inexistant", side)
+            }
+            Inconsistency::TurnOrder { previous, .. } => {
+                write!(f, "entrée notée après une entrée du tour {}", previous)
+            }
+            Inconsistency::Digest { side, .. } => write!(
+                f,
+                "la transcription du joueur {} ne correspond pas aux messages",
+                side
+            ),
+            Inconsistency::StepCount {
+                side,
+                claimed,
+                played,
+                ..
+            } => write!(
+                f,
+                "la preuve du joueur {} couvre {} étapes sur {} jouées",
+                side, claimed, played
+            ),
+            Inconsistency::Message { side, .. } => write!(
+                f,
+                "un message du joueur {} est illisible ou ne couvre pas la carte",
+                side
+            ),
+            Inconsistency::ProofRejected { side, .. } => {
+                write!(f, "la preuve du joueur {} est refusée", side)
+            }
+            Inconsistency::Chain { side, .. } => write!(
+                f,
+                "la preuve du joueur {} ne finit pas sur la chaîne de ses messages",
+                side
+            ),
+            Inconsistency::Verdict { side, recorded, .. } => write!(
+                f,
+                "la preuve du joueur {} a été notée {} pendant la partie",
+                side,
+                if *recorded { "acceptée" } else { "refusée" }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Inconsistency {}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal")
+            .field("entries", &self.entries.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Journal {
+    pub fn new(game_id: GameId, initial_hashes: [Vec<Fr>; 2]) -> Journal {
+        Journal {
+            game_id,
+            initial_hashes,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, turn: u64, event: Event) {
+        self.entries.push(Entry { turn, event });
+    }
+
+    /// Dernière preuve transmise.
+    pub fn last_proof(&self) -> Option<&CompressedProof> {
+        self.entries
+            .iter()
+            .rev()
+            .find_map(|entry| match &entry.event {
+                Event::Message {
+                    message: Message::Proof { proof, .. },
+                    ..
+                } => Some(proof.as_ref()),
+                _ => None,
+            })
+    }
+
+    /// Côtés qui ont annoncé la mort de leur commandant.
+    pub fn dead_commanders(&self) -> [bool; 2] {
+        let mut dead = [false; 2];
+        for entry in &self.entries {
+            if let Event::Message {
+                from: from @ 0..=1,
+                message: Message::CommanderDead { .. },
+            } = entry.event
+            {
+                dead[from as usize] = true;
+            }
+        }
+        dead
+    }
+
+    /// Vérifie la mort annoncée du commandant de `side`, s'il l'a annoncée,
+    /// avec le hachage `state_hash` de son état après sa dernière étape.
+    pub fn death_confirmed(&self, side: u8, state_hash: Fr) -> Option<bool> {
+        let mut chains = Chains::new([Fr::ZERO; 2]);
+        let mut state = None;
+        for entry in &self.entries {
+            if let Event::Message { from, message } = &entry.event {
+                if *from > 1 || chains.absorb(*from as usize, message).is_none() {
+                    return Some(false);
+                }
+                if let Message::CommanderDead { state: announced } = message {
+                    if *from == side {
+                        state = Some(announced);
+                    }
+                }
+            }
+        }
+        let damages = &chains.damages[side as usize];
+        state.map(|state| commander_killed(state, state_hash, damages))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(writer, self).map_err(io::Error::other)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Journal> {
+        let reader = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(reader).map_err(io::Error::other)
+    }
+
+    /// Rejoue le journal avec la clé de vérification tirée des paramètres
+    /// publics, et renvoie le nombre de preuves revérifiées.
+    pub fn replay(&self, vk: &ProofVerifierKey) -> Result<usize, Inconsistency> {
+        self.replay_with(|proof, steps, z0| verify(proof, vk, steps, z0).ok())
+    }
+
+    /// Rejoue le journal, `verify` donnant la sortie `z_n` d'une preuve des
+    /// `steps` premières étapes depuis `z0`, ou rien si elle est refusée.
+    fn replay_with(
+        &self,
+        verify: impl Fn(&CompressedProof, usize, Vec<Fr>) -> Option<Vec<Fr>>,
+    ) -> Result<usize, Inconsistency> {
+        for (side, initial_hash) in self.initial_hashes.iter().enumerate() {
+            if initial_hash.len() != 2 {
+                return Err(Inconsistency::InitialHash { side: side as u8 });
+            }
+        }
+        let hashes = self
+            .initial_hashes
+            .each_ref()
+            .map(|initial_hash| initial_hash[0].to_bytes());
+        if game_id([&hashes[0], &hashes[1]]) != self.game_id {
+            return Err(Inconsistency::GameId);
+        }
+
+        let mut transcripts = [Transcript::default(), Transcript::default()];
+        for transcript in &mut transcripts {
+            transcript.absorb("partie", &self.game_id);
+        }
+        let mut chains = Chains::new(self.initial_hashes.each_ref().map(|hash| hash[1]));
+        let mut previous = 0;
+        let mut played = [0; 2];
+        let mut verdicts = [None; 2];
+        let mut deaths: [Option<&[u64]>; 2] = [None; 2];
+        let mut proofs = 0;
+        for &Entry { turn, ref event } in &self.entries {
+            if turn < previous {
+                return Err(Inconsistency::TurnOrder { turn, previous });
+            }
+            previous = turn;
+            match event {
+                Event::Message { from, message } => {
+                    if *from > 1 {
+                        return Err(Inconsistency::Side { turn, side: *from });
+                    }
+                    let (sender, receiver) = (*from as usize, 1 - *from as usize);
+                    let bytes = message.encode();
+                    transcripts[sender].absorb("envoi", &bytes);
+                    transcripts[receiver].absorb("réception", &bytes);
+                    if chains.absorb(sender, message).is_none() {
+                        return Err(Inconsistency::Message { turn, side: *from });
+                    }
+                    match message {
+                        Message::Phase2 { .. } => played[sender] += 1,
+                        // Sans étape jouée, l'état annoncé est l'état initial.
+                        Message::CommanderDead { state } if played[sender] == 0 => {
+                            verdicts[sender] = Some(commander_killed(
+                                state,
+                                self.initial_hashes[sender][0],
+                                &chains.damages[sender],
+                            ));
+                        }
+                        Message::CommanderDead { state } => deaths[sender] = Some(state),
+                        Message::Proof { steps, proof } => {
+                            if *steps != played[sender] {
+                                return Err(Inconsistency::StepCount {
+                                    turn,
+                                    side: *from,
+                                    claimed: *steps,
+                                    played: played[sender],
+                                });
+                            }
+                            let Some(z_n) =
+                                verify(proof, *steps, self.initial_hashes[sender].clone())
+                            else {
+                                return Err(Inconsistency::ProofRejected { turn, side: *from });
+                            };
+                            if z_n.get(1) != Some(&chains.hashes[sender]) {
+                                return Err(Inconsistency::Chain { turn, side: *from });
+                            }
+                            verdicts[sender] = Some(deaths[sender].is_none_or(|state| {
+                                commander_killed(state, z_n[0], &chains.damages[sender])
+                            }));
+                            proofs += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Verdict { side, verified } => {
+                    let Some(verdict) = verdicts.get_mut(*side as usize) else {
+                        return Err(Inconsistency::Side { turn, side: *side });
+                    };
+                    // Sans preuve envoyée, seul un refus est cohérent.
+                    if verdict.take().unwrap_or(false) != *verified {
+                        return Err(Inconsistency::Verdict {
+                            turn,
+                            side: *side,
+                            recorded: *verified,
+                        });
+                    }
+                }
+                Event::Digests(digests) => {
+                    for side in 0..2 {
+                        if transcripts[side].digest() != digests[side] {
+                            return Err(Inconsistency::Digest {
+                                turn,
+                                side: side as u8,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(proofs)
+    }
+}
+
+/// Chaînes des échanges de chaque côté, accumulées comme le fait le circuit de
+/// la phase 2 depuis les seuls messages.
+struct Chains {
+    /// Chaîne en sortie de la dernière étape, dans l'ordre des côtés.
+    hashes: [Fr; 2],
+    /// Engagements sur l'aléa du tour en cours, dans l'ordre des côtés.
+    commitments: [Commitment; 2],
+    /// Derniers points de phase 1 envoyés par chaque côté.
+    phase1: [Option<Vec<(Fr, Fr)>>; 2],
+    /// Entrée `degats` de la prochaine étape de chaque côté, vide tant qu'aucun
+    /// dégât n'est connu.
+    damages: [Vec<u64>; 2],
+    /// Entrée `captures` de la prochaine étape de chaque côté : villages que
+    /// l'adversaire a annoncé lui avoir pris. Vide tant qu'aucune n'est connue.
+    captures: [Vec<u64>; 2],
+}
+
+impl Chains {
+    fn new(hashes: [Fr; 2]) -> Chains {
+        Chains {
+            hashes,
+            commitments: [Commitment::default(); 2],
+            phase1: [None, None],
+            damages: [Vec::new(), Vec::new()],
+            captures: [Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Tient compte d'un message de `sender`. Rien si le message est illisible,
+    /// ou si c'est une phase 2 qui ne répond pas à une phase 1 de même taille ou
+    /// dont les captures ne sont pas des booléens en nombre de villages.
+    fn absorb(&mut self, sender: usize, message: &Message) -> Option<()> {
+        match message {
+            Message::RollCommitment(commitment) => self.commitments[sender] = *commitment,
+            Message::Phase1 { diffie_hellman } => {
+                self.phase1[sender] = Some(field_points(diffie_hellman)?);
+            }
+            Message::Phase2 {
+                dh_output,
+                hidden_tags,
+                hidden_data,
+                phase1_output,
+                damages,
+                received,
+                captures,
+                ..
+            } => {
+                let receiver = 1 - sender;
+                let phase1_received = self.phase1[receiver].as_ref()?;
+                let size = phase1_received.len();
+                for damage in &mut self.damages {
+                    if damage.is_empty() {
+                        *damage = vec![0; size];
+                    }
+                }
+                let lengths = [
+                    dh_output.len(),
+                    hidden_tags.len(),
+                    hidden_data.len(),
+                    phase1_output.len(),
+                    damages.len(),
+                    received.len(),
+                    self.damages[sender].len(),
+                    self.damages[receiver].len(),
+                ];
+                if lengths.iter().any(|&length| length != size) {
+                    return None;
+                }
+                for lost in &mut self.captures {
+                    if lost.is_empty() {
+                        *lost = vec![0; captures.len()];
+                    }
+                }
+                if self.captures[sender].len() != captures.len() {
+                    return None;
+                }
+
+                // Même ordre que la chaîne du circuit.
+                let mut inputs = vec![self.hashes[sender]];
+                inputs.extend(
+                    self.commitments
+                        .iter()
+                        .flat_map(commitment_limbs)
+                        .map(Fr::from_u128),
+                );
+                inputs.extend(self.damages[sender].iter().map(|&damage| Fr::from(damage)));
+                inputs.push(bits_to_fr(&self.captures[sender])?);
+                inputs.extend(phase1_received.iter().flat_map(|&(x, y)| [x, y]));
+                inputs.push(bits_to_fr(captures)?);
+                for points in [phase1_output, dh_output, hidden_tags] {
+                    inputs.extend(field_points(points)?.into_iter().flat_map(|(x, y)| [x, y]));
+                }
+                // Les trois mots de 64 bits, regroupés par `Bits2Num(192)`.
+                let word = Fr::from_u128(1 << 64);
+                for (a, b, c) in hidden_data {
+                    let [a, b, c] = [a, b, c].map(|value| decimal_to_fr(value).ok());
+                    inputs.push(a? + word * (b? + word * c?));
+                }
+                self.hashes[sender] = sponge(&inputs);
+
+                self.damages[sender] = received.clone();
+                for (total, damage) in self.damages[receiver].iter_mut().zip(damages) {
+                    *total += damage;
+                }
+                self.captures[sender].fill(0);
+                for (lost, capture) in self.captures[receiver].iter_mut().zip(captures) {
+                    *lost |= capture;
+                }
+            }
+            Message::RollReveal { .. }
+            | Message::ProofRequest
+            | Message::CommanderDead { .. }
+            | Message::Proof { .. } => {}
+        }
+        Some(())
+    }
+}
+
+/// Booléens regroupés comme par `Bits2Num`, le premier en bit de poids faible.
+/// Rien si une valeur n'est pas un booléen.
+fn bits_to_fr(bits: &[u64]) -> Option<Fr> {
+    bits.iter().rev().try_fold(Fr::ZERO, |number, &bit| {
+        (bit <= 1).then(|| number.double() + Fr::from(bit))
+    })
+}
+
+/// L'état en clair a le hachage `state_hash`, et les dégâts reçus depuis,
+/// rien s'ils sont vides, y tuent le commandant.
+fn commander_killed(state: &[u64], state_hash: Fr, damages: &[u64]) -> bool {
+    let size = (state.len().saturating_sub(3)) / 4;
+    if state.len() != size * 4 + 3 || !(damages.is_empty() || damages.len() == size) {
+        return false;
+    }
+    if sponge(
+        &state
+            .iter()
+            .map(|&value| Fr::from(value))
+            .collect::<Vec<_>>(),
+    ) != state_hash
+    {
+        return false;
+    }
+    state
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(_, square)| Unit::try_from(square[0]).is_ok_and(|unit| unit.is_commander()))
+        .all(|(index, square)| square[1] <= damages.get(index).copied().unwrap_or(0))
+}
+
+fn field_points(points: &[(Decimal, Decimal)]) -> Option<Vec<(Fr, Fr)>> {
+    points
+        .iter()
+        .map(|(x, y)| Some((decimal_to_fr(x).ok()?, decimal_to_fr(y).ok()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal() -> Journal {
+        let initial_hashes = [vec![Fr::from(1), Fr::ZERO], vec![Fr::from(2), Fr::ZERO]];
+        let hashes = initial_hashes.each_ref().map(|hash| hash[0].to_bytes());
+        Journal::new(game_id([&hashes[0], &hashes[1]]), initial_hashes)
+    }
+
+    /// Aucune preuve n'est notée dans ces journaux.
+    fn replay(journal: &Journal) -> Result<usize, Inconsistency> {
+        journal.replay_with(|_, _, _| unreachable!("aucune preuve à vérifier"))
+    }
+
+    fn points(first: u64, size: u64) -> Vec<(Decimal, Decimal)> {
+        (first..first + size)
+            .map(|value| (value.to_string(), (value + 1).to_string()))
+            .collect()
+    }
+
+    fn phase1(first: u64) -> Message {
+        Message::Phase1 {
+            diffie_hellman: points(first, 2),
+        }
+    }
+
+    fn phase2(first: u64, damages: [u64; 2], received: [u64; 2]) -> Message {
+        Message::Phase2 {
+            dh_output: points(first, 2),
+            hidden_tags: points(first + 10, 2),
+            hidden_data: vec![
+                ("1".to_string(), "2".to_string(), "3".to_string()),
+                ("4".to_string(), "5".to_string(), "6".to_string()),
+            ],
+            phase1_output: points(first + 20, 2),
+            damages: damages.to_vec(),
+            received: received.to_vec(),
+            captures: vec![0; 8],
+            attacks: Vec::new(),
+        }
+    }
+
+    /// Empreintes des transcriptions après les messages déjà notés.
+    fn digests(journal: &Journal) -> [[u8; 32]; 2] {
+        let mut transcripts = [Transcript::default(), Transcript::default()];
+        for transcript in &mut transcripts {
+            transcript.absorb("partie", &journal.game_id);
+        }
+        for entry in &journal.entries {
+            if let Event::Message { from, message } = &entry.event {
+                let bytes = message.encode();
+                transcripts[*from as usize].absorb("envoi", &bytes);
+                transcripts[1 - *from as usize].absorb("réception", &bytes);
+            }
+        }
+        transcripts.map(|transcript| transcript.digest())
+    }
+
+    /// Tour de `from` : engagements, phase 1 de l'autre côté puis phase 2.
+    fn play(journal: &mut Journal, turn: u64, from: u8, response: Message) {
+        for (side, commitment) in [(from, [turn as u8; 32]), (1 - from, [0; 32])] {
+            let message = Message::RollCommitment(commitment);
+            journal.record(
+                turn,
+                Event::Message {
+                    from: side,
+                    message,
+                },
+            );
+        }
+        let message = phase1(turn * 100);
+        journal.record(
+            turn,
+            Event::Message {
+                from: 1 - from,
+                message,
+            },
+        );
+        journal.record(
+            turn,
+            Event::Message {
+                from,
+                message: response,
+            },
+        );
+        let digests = digests(journal);
+        journal.record(turn, Event::Digests(digests));
+    }
+
+    #[test]
+    fn missing_proof_is_only_consistent_as_refused() {
+        let mut refused = journal();
+        refused.record(
+            3,
+            Event::Message {
+                from: 0,
+                message: Message::ProofRequest,
+            },
+        );
+        refused.record(
+            3,
+            Event::Verdict {
+                side: 1,
+                verified: false,
+            },
+        );
+        assert_eq!(replay(&refused), Ok(0));
+
+        let mut accepted = journal();
+        accepted.record(
+            3,
+            Event::Verdict {
+                side: 1,
+                verified: true,
+            },
+        );
+        assert_eq!(
+            replay(&accepted),
+            Err(Inconsistency::Verdict {
+                turn: 3,
+                side: 1,
+                recorded: true
+            })
+        );
+    }
+
+    #[test]
+    fn tampered_message_breaks_the_digest() {
+        let mut journal = journal();
+        play(&mut journal, 1, 0, phase2(1, [0, 3], [1, 0]));
+        play(&mut journal, 2, 1, phase2(2, [2, 0], [0, 0]));
+        assert_eq!(replay(&journal), Ok(0));
+
+        let entry = journal
+            .entries
+            .iter_mut()
+            .find(|entry| {
+                entry.turn == 2
+                    && matches!(
+                        entry.event,
+                        Event::Message {
+                            from: 1,
+                            message: Message::Phase2 { .. }
+                        }
+                    )
+            })
+            .unwrap();
+        entry.event = Event::Message {
+            from: 1,
+            message: phase2(2, [0, 0], [0, 0]),
+        };
+        assert_eq!(
+            replay(&journal),
+            Err(Inconsistency::Digest { turn: 2, side: 0 })
+        );
+    }
+
+    #[test]
+    fn unknown_sides_are_inconsistent() {
+        let mut message = journal();
+        message.record(
+            2,
+            Event::Message {
+                from: 2,
+                message: Message::ProofRequest,
+            },
+        );
+        assert_eq!(
+            replay(&message),
+            Err(Inconsistency::Side { turn: 2, side: 2 })
+        );
+
+        let mut verdict = journal();
+        verdict.record(
+            4,
+            Event::Verdict {
+                side: 7,
+                verified: false,
+            },
+        );
+        assert_eq!(
+            replay(&verdict),
+            Err(Inconsistency::Side { turn: 4, side: 7 })
+        );
+    }
+
+    #[test]
+    fn initial_hashes_are_a_state_and_a_chain() {
+        let missing = Journal::new(GameId::default(), [Vec::new(), vec![Fr::ONE, Fr::ZERO]]);
+        assert_eq!(
+            replay(&missing),
+            Err(Inconsistency::InitialHash { side: 0 })
+        );
+        let short = Journal::new(GameId::default(), [vec![Fr::ONE, Fr::ZERO], vec![Fr::ONE]]);
+        assert_eq!(replay(&short), Err(Inconsistency::InitialHash { side: 1 }));
+    }
+
+    #[test]
+    fn phase2_answers_a_readable_phase1_of_its_size() {
+        let mut unanswered = journal();
+        unanswered.record(
+            1,
+            Event::Message {
+                from: 0,
+                message: phase2(1, [0; 2], [0; 2]),
+            },
+        );
+        assert_eq!(
+            replay(&unanswered),
+            Err(Inconsistency::Message { turn: 1, side: 0 })
+        );
+
+        let mut shorter = journal();
+        let mut response = phase2(1, [0; 2], [0; 2]);
+        if let Message::Phase2 { damages, .. } = &mut response {
+            damages.pop();
+        }
+        play(&mut shorter, 1, 0, response);
+        assert_eq!(
+            replay(&shorter),
+            Err(Inconsistency::Message { turn: 1, side: 0 })
+        );
+
+        let mut unreadable = journal();
+        let message = Message::Phase1 {
+            diffie_hellman: vec![("x".to_string(), "1".to_string())],
+        };
+        unreadable.record(1, Event::Message { from: 1, message });
+        assert_eq!(
+            replay(&unreadable),
+            Err(Inconsistency::Message { turn: 1, side: 1 })
+        );
+    }
+
+    #[test]
+    fn chains_follow_the_exchanges() {
+        let initial = [Fr::from(5), Fr::from(6)];
+        let exchange = |chains: &mut Chains, from: usize, response: &Message| {
+            chains.absorb(1 - from, &phase1(7)).unwrap();
+            chains.absorb(from, response).unwrap();
+        };
+
+        let mut chains = Chains::new(initial);
+        exchange(&mut chains, 0, &phase2(1, [0, 3], [1, 0]));
+        assert_ne!(chains.hashes[0], initial[0]);
+        assert_eq!(chains.hashes[1], initial[1]);
+        // Les ripostes ouvrent les dégâts de l'expéditeur, les dégâts infligés
+        // s'ajoutent à ceux du destinataire.
+        assert_eq!(chains.damages, [vec![1, 0], vec![0, 3]]);
+        let first = chains.hashes[0];
+
+        let mut same = Chains::new(initial);
+        exchange(&mut same, 0, &phase2(1, [0, 3], [1, 0]));
+        assert_eq!(same.hashes[0], first);
+        let mut tampered = Chains::new(initial);
+        exchange(&mut tampered, 0, &phase2(2, [0, 3], [1, 0]));
+        assert_ne!(tampered.hashes[0], first);
+
+        exchange(&mut chains, 1, &phase2(3, [4, 0], [0, 2]));
+        assert_eq!(chains.damages, [vec![5, 0], vec![0, 2]]);
+        // Les dégâts reçus entrent dans la chaîne de la prochaine étape.
+        exchange(&mut same, 1, &phase2(3, [4, 0], [0, 2]));
+        assert_eq!(same.hashes, chains.hashes);
+        let mut other_damages = Chains::new(initial);
+        exchange(&mut other_damages, 0, &phase2(1, [0, 1], [1, 0]));
+        exchange(&mut other_damages, 1, &phase2(3, [4, 0], [0, 2]));
+        assert_ne!(other_damages.hashes[1], chains.hashes[1]);
+    }
+
+    #[test]
+    fn chains_follow_the_announced_captures() {
+        let initial = [Fr::from(5), Fr::from(6)];
+        let exchange = |chains: &mut Chains, from: usize, captured: &[u64]| {
+            let mut response = phase2(1, [0; 2], [0; 2]);
+            if let Message::Phase2 { captures, .. } = &mut response {
+                *captures = captured.to_vec();
+            }
+            chains.absorb(1 - from, &phase1(7))?;
+            chains.absorb(from, &response)
+        };
+        let mut captured = [0; 8];
+        captured[1] = 1;
+
+        let mut chains = Chains::new(initial);
+        exchange(&mut chains, 0, &captured).unwrap();
+        let mut quiet = Chains::new(initial);
+        exchange(&mut quiet, 0, &[0; 8]).unwrap();
+        // Les captures de l'expéditeur entrent dans sa chaîne, puis dans
+        // l'entrée `captures` de la prochaine étape du destinataire.
+        assert_ne!(chains.hashes[0], quiet.hashes[0]);
+        assert_eq!(chains.captures, [vec![0; 8], captured.to_vec()]);
+
+        exchange(&mut chains, 1, &[0; 8]).unwrap();
+        exchange(&mut quiet, 1, &[0; 8]).unwrap();
+        assert_ne!(chains.hashes[1], quiet.hashes[1]);
+        assert_eq!(chains.captures, [vec![0; 8], vec![0; 8]]);
+
+        // Bits2Num : le village 0 en bit de poids faible.
+        assert_eq!(bits_to_fr(&[1, 0, 1]), Some(Fr::from(5)));
+        let mut forged = captured;
+        forged[2] = 2;
+        assert_eq!(exchange(&mut Chains::new(initial), 0, &forged), None);
+        assert_eq!(exchange(&mut chains, 0, &[0; 7]), None);
+    }
+
+    #[test]
+    fn commander_death_is_checked_against_the_proven_state() {
+        // Deux cases : le commandant sur la première, puis l'or, les villages
+        // et l'upkeep.
+        let commander: u64 = (&Unit::OrcCommander).into();
+        let state = vec![commander, 10, 0, 5, 0, 0, 0, 0, 12, 1, 0];
+        let state_hash = sponge(
+            &state
+                .iter()
+                .map(|&value| Fr::from(value))
+                .collect::<Vec<_>>(),
+        );
+        assert!(commander_killed(&state, state_hash, &[10, 0]));
+        assert!(commander_killed(&state, state_hash, &[12, 3]));
+        assert!(!commander_killed(&state, state_hash, &[9, 0]));
+        assert!(!commander_killed(&state, state_hash, &[]));
+        assert!(!commander_killed(&state, state_hash + Fr::ONE, &[10, 0]));
+        assert!(!commander_killed(&state[..10], state_hash, &[10, 0]));
+
+        // Le côté 0 meurt avant d'avoir joué : l'état annoncé est l'initial.
+        let dying = |damages: [u64; 2], verified: bool| {
+            let initial_hashes = [vec![state_hash, Fr::ZERO], vec![Fr::from(2), Fr::ZERO]];
+            let hashes = initial_hashes.each_ref().map(|hash| hash[0].to_bytes());
+            let mut journal = Journal::new(game_id([&hashes[0], &hashes[1]]), initial_hashes);
+            play(&mut journal, 1, 1, phase2(1, damages, [0, 0]));
+            let message = Message::CommanderDead {
+                state: state.clone(),
+            };
+            journal.record(2, Event::Message { from: 0, message });
+            journal.record(2, Event::Verdict { side: 0, verified });
+            journal
+        };
+        let killed = dying([10, 0], true);
+        assert_eq!(replay(&killed), Ok(0));
+        assert_eq!(killed.dead_commanders(), [true, false]);
+        assert_eq!(killed.death_confirmed(0, state_hash), Some(true));
+        assert_eq!(killed.death_confirmed(0, Fr::ONE), Some(false));
+        assert_eq!(killed.death_confirmed(1, state_hash), None);
+
+        // Le commandant a survécu aux dégâts : l'annonce est refusée.
+        let survived = dying([9, 0], true);
+        assert_eq!(survived.death_confirmed(0, state_hash), Some(false));
+        assert_eq!(
+            replay(&survived),
+            Err(Inconsistency::Verdict {
+                turn: 2,
+                side: 0,
+                recorded: true
+            })
+        );
+        assert_eq!(replay(&dying([9, 0], false)), Ok(0));
+    }
+}
@@ -1,38 +1,75 @@
+mod anemoi;
+mod circuits;
+mod combat;
+mod convert;
+mod game;
+mod hex;
+mod journal;
+mod metrics;
+mod output;
+mod pathfinding;
+mod phases;
+mod prover;
+mod randomness;
+mod rules;
+mod scalar;
+mod secret;
 mod unit;
+mod vision;
+mod wml;
 
 use std::fs::File;
 use std::io::BufWriter;
-use std::io::{BufReader, Read, Write};
-use std::mem::MaybeUninit;
-use std::ops::{Index, IndexMut};
+use std::io::{BufReader, Write};
 use std::path::PathBuf;
 use std::process::Command;
-use std::str::FromStr;
-use std::{collections::HashMap, env::current_dir, fs, time::Instant};
-
+use std::time::Instant;
+
+use crate::circuits::{check_artifacts, copy_sources, Manifest};
+use crate::combat::Attack;
+use crate::convert::biguint_to_fr;
+use crate::game::{Game, ProofPolicy};
+use crate::hex::Grid;
+use crate::journal::Journal;
+use crate::metrics::Metrics;
+use crate::pathfinding::Terrain;
+use crate::phases::{Player, Side, SideA, SideB};
+use crate::prover::{verifier_key, ProofError, Prover};
+use crate::randomness::{secret_rng, Commitment, GameId, SecretRng, SharedSeed, Transcript};
+use crate::rules::{Economy, Plan, RuleViolation, Scripted};
+use crate::secret::Secrets;
 use crate::unit::{Commander, Unit};
+use crate::vision::VisibleEnemy;
 use bincode::{deserialize_from, serialize_into};
-use halo2curves::bn256::{Bn256, Fr};
+use halo2curves::bn256::Fr;
 use halo2curves::ff::Field;
-use halo2curves::pairing::Engine;
 use nova_scotia::circom::circuit::{CircomCircuit, R1CS};
-use nova_scotia::circom::reader::load_witness_from_bin_file;
-use nova_scotia::{
-    circom::reader::load_r1cs, create_public_params, create_recursive_circuit, FileLocation, C1,
-    C2, F, S,
-};
+use nova_scotia::{circom::reader::load_r1cs, create_public_params, FileLocation, C1, C2, S};
 use nova_snark::provider::bn256_grumpkin::{bn256, grumpkin};
-use nova_snark::spartan::direct::DirectSNARK;
 use nova_snark::traits::circuit::TrivialTestCircuit;
 use nova_snark::{provider, CompressedSNARK, PublicParams, RecursiveSNARK, VerifierKey};
-use num_bigint::Sign;
-use num_bigint::{BigInt, BigUint, RandBigInt};
-use serde::ser::{SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple};
+use num_bigint::BigUint;
+use serde::ser::SerializeTuple;
 use serde::{Serialize, Serializer};
 
-const BABY_JUBJUB_ORDER: &str =
-    "21888242871839275222246405745257275088614511777268538073601725287587578984328";
 const MAX_ACTION_COUNT: usize = 10;
+/// Nombre de tours joués, les deux côtés compris, avant une partie nulle.
+const TURN_LIMIT: u64 = 14;
+/// Chaque joueur demande la preuve de l'autre tous les deux tours de celui-ci,
+/// sauf si `POLICY_VAR` en donne une autre.
+const PROOF_POLICY: ProofPolicy = ProofPolicy::Every(2);
+/// Politique de preuve de la partie : `fin`, `tous:N`, `audit:P` ou `soupcon`.
+const POLICY_VAR: &str = "WESNOTH_ZKPSI_PREUVES";
+/// Dossier des jeux de circuits compilés, un sous-dossier par carte et taille.
+const ARTIFACTS_VAR: &str = "WESNOTH_ZKPSI_ARTIFACTS";
+/// Graine des secrets des joueurs, pour rejouer une partie en déboguage.
+/// Ignorée dans les versions optimisées, qui tirent toujours leur aléa du
+/// système.
+const SEED_VAR: &str = "WESNOTH_ZKPSI_SEED";
+/// Sources des circuits, qui servent aussi de jeu de circuits par défaut.
+const CIRCUIT_SOURCES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/wesnoth-zkpsi");
+/// Dossier des rapports de performance, un fichier JSON par partie.
+const REPORT_DIR: &str = "rapports";
 
 type Snark = RecursiveSNARK<
     provider::bn256_grumpkin::bn256::Point,
@@ -41,18 +78,26 @@ type Snark = RecursiveSNARK<
     C2<provider::bn256_grumpkin::grumpkin::Point>,
 >;
 
-fn string_to_f1(s: &str) -> Fr {
-    let mut out = [0; 32];
-    match s.parse::<BigInt>().unwrap().to_bytes_le() {
-        (Sign::Plus, v) => {
-            for i in 0..v.len() {
-                out[v.len() - i - 1] = v[v.len() - i - 1];
-            }
-        }
-        _ => panic!(),
-    }
-    Fr::from_bytes(&out).unwrap()
-}
+type PublicParameters =
+    PublicParams<bn256::Point, grumpkin::Point, C1<bn256::Point>, C2<grumpkin::Point>>;
+
+type CompressedProof = CompressedSNARK<
+    bn256::Point,
+    grumpkin::Point,
+    C1<bn256::Point>,
+    C2<grumpkin::Point>,
+    S<bn256::Point>,
+    S<grumpkin::Point>,
+>;
+
+type ProofVerifierKey = VerifierKey<
+    bn256::Point,
+    grumpkin::Point,
+    C1<bn256::Point>,
+    C2<grumpkin::Point>,
+    S<bn256::Point>,
+    S<grumpkin::Point>,
+>;
 
 #[derive(Copy, Clone)]
 enum InitialState {
@@ -68,12 +113,27 @@ impl InitialState {
     //         }
     //     }
     // }
+    /// Dossier des circuits compilés pour cette carte. Avec
+    /// `WESNOTH_ZKPSI_ARTIFACTS`, c'est le jeu `<carte>-<largeur>x<hauteur>` de
+    /// ce dossier, ce qui permet de garder côte à côte les circuits de plusieurs
+    /// cartes ou tailles. Sans, ce sont les sources du dépôt.
     fn circuit_path(&self) -> PathBuf {
+        match std::env::var_os(ARTIFACTS_VAR) {
+            Some(root) => PathBuf::from(root).join(self.artifact_set()),
+            None => PathBuf::from(CIRCUIT_SOURCES),
+        }
+    }
+
+    /// Nom du jeu de circuits compilés de cette carte.
+    fn artifact_set(&self) -> String {
+        let (width, height) = self.size();
+        format!("{}-{}x{}", self.name(), width, height)
+    }
+
+    /// Nom de la carte, repris dans le manifeste des circuits.
+    fn name(&self) -> &'static str {
         match self {
-            InitialState::Nordic(_, _, _) => {
-                // "map_circuits/nordic".into()
-                "wesnoth-zkpsi/".into()
-            }
+            InitialState::Nordic(_, _, _) => "nordic",
         }
     }
 
@@ -84,14 +144,75 @@ impl InitialState {
     }
 
     fn village_count(&self) -> u64 {
+        self.village_positions().len() as u64
+    }
+
+    /// Indices des cases des villages, paramètre `pos_villages` des circuits.
+    fn village_positions(&self) -> &'static [usize] {
+        match self {
+            InitialState::Nordic(_, _, _) => &[50, 90, 5, 45, 54, 94, 9, 49],
+        }
+    }
+
+    /// Indices des cases des donjons, paramètre `donjons` des circuits.
+    fn keeps(&self) -> &'static [usize] {
         match self {
-            InitialState::Nordic(_, _, _) => 8,
+            InitialState::Nordic(_, _, _) => &[0, 99],
         }
     }
+
+    /// Cases de château sous la forme `(donjon, case)`, paramètre `chateaux`
+    /// des circuits.
+    fn castles(&self) -> &'static [(usize, usize)] {
+        match self {
+            InitialState::Nordic(_, _, _) => &[(0, 1), (0, 10), (0, 20), (1, 89), (1, 98), (1, 79)],
+        }
+    }
+
+    /// Terrain d'une case. Les circuits l'ignorent, il ne sert qu'à vérifier
+    /// les chemins avant de les jouer.
+    fn terrain(&self, position: Position) -> Terrain {
+        let index = self.index(position);
+        if self.keeps().contains(&index) || self.castles().iter().any(|&(_, c)| c == index) {
+            return Terrain::Castle;
+        }
+        if self.village_positions().contains(&index) {
+            return Terrain::Village;
+        }
+        match self {
+            // Symétrique par rapport au centre, comme les villages.
+            InitialState::Nordic(_, _, _) => match index {
+                23 | 76 | 33 | 66 | 37 | 62 => Terrain::Forest,
+                14 | 85 | 41 | 58 => Terrain::Hills,
+                27 | 72 => Terrain::Mountains,
+                35 | 64 | 44 | 55 => Terrain::ShallowWater,
+                46 | 53 => Terrain::Swamp,
+                _ => Terrain::Flat,
+            },
+        }
+    }
+
+    fn grid(&self) -> Grid {
+        Grid::new(self.size())
+    }
+
+    /// Revenu, upkeep et soins, qui doivent correspondre aux derniers
+    /// paramètres de `Final` dans les circuits.
+    fn economy(&self) -> Economy {
+        match self {
+            InitialState::Nordic(_, _, _) => Economy::default(),
+        }
+    }
+
+    /// Indice de la case d'une position, comme `x * state_height + y` dans les
+    /// circuits.
+    fn index(&self, position: Position) -> usize {
+        self.grid().index(position)
+    }
 }
 
 #[derive(Copy, Clone)]
-struct Square {
+pub struct Square {
     unit: Unit,
     health_points: u64,
     captured: bool,
@@ -100,42 +221,55 @@ struct Square {
 
 type Position = (u64, u64);
 
-#[derive(Copy, Clone)]
+/// Point de Baby Jubjub en coordonnées affines.
+type Point = (BigUint, BigUint);
+
+/// Données d'une case masquées pour la PSI, en trois blocs de 64 bits.
+type HiddenData = (BigUint, BigUint, BigUint);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum Transaction {
     None,
     MoveUnit(Position, Position),
     CaptureVillage(u64),
     PurchaseUnit(u64, Unit),
+    /// Hors circuit, seuls ses dégâts sont échangés.
+    Attack(Attack),
 }
 
 impl Transaction {
-    fn to_action(&self) -> [i64; 8] {
+    /// Encodage de l'action pour l'entrée `actions` du circuit de la phase 2,
+    /// qui ne connaît pas les attaques.
+    fn to_action(self) -> Option<[i64; 8]> {
         let na = -1;
-        match self {
+        let action = match self {
             Transaction::None => [0, na, na, na, na, na, na, na],
             Transaction::MoveUnit((orig_x, orig_y), (dest_x, dest_y)) => [
                 1,
-                *orig_x as i64,
-                *orig_y as i64,
-                *dest_x as i64,
-                *dest_y as i64,
+                orig_x as i64,
+                orig_y as i64,
+                dest_x as i64,
+                dest_y as i64,
                 na,
                 na,
                 na,
             ],
             Transaction::CaptureVillage(village_id) => {
-                [2, na, na, na, na, *village_id as i64, na, na]
+                [2, na, na, na, na, village_id as i64, na, na]
             }
             Transaction::PurchaseUnit(castle_id, unit) => {
-                let unit_id: u64 = unit.into();
-                [3, na, na, na, na, na, *castle_id as i64, unit_id as i64]
+                let unit_id: u64 = (&unit).into();
+                [3, na, na, na, na, na, castle_id as i64, unit_id as i64]
             }
-        }
+            Transaction::Attack(_) => return None,
+        };
+        Some(action)
     }
 }
 
-type Turn = Vec<Transaction>;
+type Turn = Vec<Scripted>;
 
+#[derive(Clone)]
 struct CircuitState {
     squares: Vec<Square>,
     gold_amount: u64,
@@ -143,6 +277,57 @@ struct CircuitState {
     current_upkeep_costs: u64,
 }
 
+impl CircuitState {
+    /// Entrées du hachage d'état : les quatre champs de chaque case, puis l'or,
+    /// les villages et l'upkeep.
+    fn hash_inputs(&self) -> Vec<u64> {
+        let mut hash_input: Vec<u64> = self
+            .squares
+            .iter()
+            .flat_map(|x| {
+                let unit: u64 = (&x.unit).into();
+                [unit, x.health_points, x.captured as u64, x.move_credits]
+            })
+            .collect();
+        hash_input.push(self.gold_amount);
+        hash_input.push(self.captured_village_count);
+        hash_input.push(self.current_upkeep_costs);
+        hash_input
+    }
+
+    fn hash(&self, initial_state: &InitialState) -> BigUint {
+        let hash_input = self.hash_inputs();
+        let json = serde_json::to_string(&HashObject {
+            to_hash: &hash_input,
+        })
+        .unwrap();
+
+        let mut input =
+            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
+        let output =
+            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
+        input
+            .write_all(json.as_bytes())
+            .expect("Impossible d'écrire dans le fichier les entrées du hash d'état");
+
+        // let phase_cmd = Command::new(initial_state.circuit_path().join("hash/hash_state"))
+        let phase_cmd = Command::new(
+            initial_state
+                .circuit_path()
+                .join("hash/hash_state_cpp/hash_state"),
+        )
+        .arg(input.path())
+        .arg(output.path())
+        .output()
+        .expect("Le circuit de hachagé a échoué !");
+        assert!(phase_cmd.status.success(), "{:?}", phase_cmd);
+        let hash_output: String =
+            String::from_utf8(phase_cmd.stdout).expect("Le hash n'est pas en UTF-8 ??");
+        output::parse_hash(&hash_output)
+            .unwrap_or_else(|error| panic!("Sortie du hachage invalide : {}", error))
+    }
+}
+
 impl Serialize for Square {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -158,10 +343,8 @@ impl Serialize for Square {
 }
 
 struct UnencryptedData {
-    last_hash: BigUint,
-    own_received_damage: Vec<u64>, // carte des dégats subis par chacune des unités au début du tour précédent
+    own_received_damage: Vec<u64>, // carte des dégats subis par chacune des unités depuis le début de notre tour précédent
     adversary_captures: Vec<u64>, // de taille nombre_village, 1 si capturé au début par l'adversaire, 0 sinon
-    allied_captures: Vec<u64>, // de taille nombre_village, 1 si capturé à la fin du tour, 0 sinon
 }
 
 impl UnencryptedData {
@@ -170,64 +353,98 @@ impl UnencryptedData {
         let village_count = initial_state.village_count() as usize;
 
         UnencryptedData {
-            last_hash: BigUint::new(vec![]),
             own_received_damage: vec![0u64; (width * height) as usize],
             adversary_captures: vec![0u64; village_count],
-            allied_captures: vec![0u64; village_count],
         }
     }
 }
 
-struct State {
+/// Joueur dont l'état initial n'a pas encore été engagé : il n'a ni preuve Nova
+/// ni hash initial, et ne peut donc pas encore jouer.
+struct UncommittedState {
     circuit_state: CircuitState,
-    public_params: PublicParams<
-        provider::bn256_grumpkin::bn256::Point,
-        provider::bn256_grumpkin::grumpkin::Point,
-        C1<provider::bn256_grumpkin::bn256::Point>,
-        C2<provider::bn256_grumpkin::grumpkin::Point>,
-    >,
-    snark: Snark,
+    public_params: PublicParameters,
     r1cs: R1CS<Fr>,
-    initial_hash: Vec<Fr>,
     phase2_circuit: CircomCircuit<Fr>,
     unencrypted_state: UnencryptedData,
     initial_state: InitialState,
-    pending_transactions: Vec<Transaction>,
-    roll_hash: BigUint,
 }
 
-struct Phase1<'a> {
-    previous_state: &'a State,
-    damages_inflicted: Vec<u64>,
-    captures: Vec<u64>,
-    exponents: Vec<Vec<u8>>,
-}
+impl UncommittedState {
+    /// Engage l'état initial en le hachant, ce qui initialise la preuve Nova.
+    /// `rng` tire ensuite tous les secrets du joueur.
+    fn commit(self, rng: SecretRng) -> State {
+        let initial_hash = vec![
+            biguint_to_fr(&self.circuit_state.hash(&self.initial_state))
+                .unwrap_or_else(|error| panic!("Hash de l'état initial invalide : {}", error)),
+            0.into(),
+        ];
 
-impl<'a> Serialize for Phase1<'a> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut serializer = serializer.serialize_map(Some(5))?;
-        let previous_circuit_state = &self.previous_state.circuit_state;
-        serializer.serialize_entry("prev_state", &previous_circuit_state.squares)?;
-        serializer.serialize_entry(
-            "prev_misc_state",
-            &[
-                previous_circuit_state.gold_amount,
-                previous_circuit_state.captured_village_count,
-                previous_circuit_state.current_upkeep_costs,
-            ],
-        )?;
-        serializer.serialize_entry("degats", &self.damages_inflicted)?;
-        serializer.serialize_entry("captures", &self.captures)?;
-        serializer.serialize_entry("phase1_exponents", &self.exponents)?;
-        serializer.end()
+        let circuit_secondary = TrivialTestCircuit::default();
+        let z0_secondary =
+            vec![<halo2curves::grumpkin::G1 as halo2curves::group::Group>::Scalar::ZERO];
+        let snark = Snark::new(
+            &self.public_params,
+            &self.phase2_circuit,
+            &circuit_secondary,
+            initial_hash.clone(),
+            z0_secondary,
+        );
+
+        State {
+            circuit_state: self.circuit_state,
+            prover: Prover::spawn(self.public_params, snark, initial_hash.clone()),
+            r1cs: self.r1cs,
+            initial_hash,
+            unencrypted_state: self.unencrypted_state,
+            initial_state: self.initial_state,
+            plan: None,
+            roll_hash: Fr::ZERO,
+            roll_commitments: [Commitment::default(); 2],
+            proven_steps: 0,
+            game_id: GameId::default(),
+            transcript: Transcript::default(),
+            roll_seed: None,
+            visible_enemies: Vec::new(),
+            suspicious: false,
+            metrics: Metrics::default(),
+            secrets: Secrets::new(rng),
+        }
     }
 }
 
+pub struct State {
+    circuit_state: CircuitState,
+    /// Replie les étapes en arrière-plan, avec les paramètres publics.
+    prover: Prover,
+    r1cs: R1CS<Fr>,
+    initial_hash: Vec<Fr>,
+    unencrypted_state: UnencryptedData,
+    initial_state: InitialState,
+    /// Tour en préparation, créé à la première action prévue.
+    plan: Option<Plan>,
+    /// Chaîne des échanges en sortie de notre dernière étape.
+    roll_hash: Fr,
+    /// Engagements des deux côtés sur l'aléa du tour en cours.
+    roll_commitments: [Commitment; 2],
+    /// Nombre d'étapes accumulées dans la preuve Nova.
+    proven_steps: usize,
+    /// Fixé par la partie une fois les deux états initiaux engagés.
+    game_id: GameId,
+    transcript: Transcript,
+    /// Graine des combats du tour en cours, tirée avec l'adversaire.
+    roll_seed: Option<SharedSeed>,
+    /// Unités adverses révélées par la dernière PSI.
+    visible_enemies: Vec<VisibleEnemy>,
+    /// L'adversaire a envoyé des dégâts impossibles depuis sa dernière preuve.
+    suspicious: bool,
+    metrics: Metrics,
+    /// Aléa des exposants et des nonces, jamais partagé.
+    secrets: Secrets,
+}
+
 impl State {
-    fn initial_states(initial_state: InitialState) -> (State, State) {
+    fn initial_states(initial_state: InitialState) -> (UncommittedState, UncommittedState) {
         let none = Unit::None.default_square();
 
         let circuit_file = initial_state.circuit_path().join("phase2nova/circuit.r1cs");
@@ -243,7 +460,7 @@ impl State {
         println!("Circuit lu en {:?}", begin.elapsed());
 
         let begin = Instant::now();
-        let pp1: PublicParams<bn256::Point, grumpkin::Point, _, _>;
+        let pp1: PublicParameters;
 
         if key_path.exists() {
             println!("Lecture des paramètres publics.");
@@ -258,28 +475,18 @@ impl State {
             let parameters_writer = BufWriter::new(parameters_file);
             serialize_into(parameters_writer, &pp1).unwrap();
         }
-        let parameters_file = File::open(key_path.clone()).unwrap();
-        let parameters_writer = BufReader::new(parameters_file);
-        let pp2: PublicParams<bn256::Point, grumpkin::Point, _, _> =
-            deserialize_from(parameters_writer).unwrap();
-
+        // Les paramètres publics ne sont pas clonables, on les relit donc pour
+        // le second joueur.
         let parameters_file = File::open(key_path).unwrap();
         let parameters_writer = BufReader::new(parameters_file);
-        let pp: PublicParams<bn256::Point, grumpkin::Point, _, _> =
-            deserialize_from(parameters_writer).unwrap();
+        let pp2: PublicParameters = deserialize_from(parameters_writer).unwrap();
         println!("Paramètres publics obtenus en {:?}", begin.elapsed());
 
         let circuit = CircomCircuit {
-            r1cs: load_r1cs::<bn256::Point, grumpkin::Point>(&FileLocation::PathBuf(
-                initial_state.circuit_path().join("phase2nova/circuit.r1cs"),
-            )),
+            r1cs: r1cs.clone(),
             witness: None,
         };
 
-        let circuit_secondary = TrivialTestCircuit::default();
-        let z0_secondary =
-            vec![<halo2curves::grumpkin::G1 as halo2curves::group::Group>::Scalar::ZERO];
-
         match initial_state {
             InitialState::Nordic((w, h), a, b) => {
                 let a: Unit = a.into();
@@ -287,13 +494,13 @@ impl State {
 
                 let default_map = vec![none; (w * h) as usize];
                 let mut map_a = default_map.clone();
-                let mut map_b = default_map.clone();
+                let mut map_b = default_map;
 
                 map_a[0] = a.default_square();
                 map_b[(w * h) as usize - 1] = b.default_square();
 
-                let (mut sa, mut sb) = (
-                    State {
+                (
+                    UncommittedState {
                         circuit_state: CircuitState {
                             squares: map_a,
                             gold_amount: 100,
@@ -301,16 +508,12 @@ impl State {
                             current_upkeep_costs: 0,
                         },
                         public_params: pp1,
-                        snark: unsafe { MaybeUninit::zeroed().assume_init() },
-                        initial_hash: unsafe { MaybeUninit::zeroed().assume_init() },
                         r1cs: r1cs.clone(),
                         phase2_circuit: circuit.clone(),
                         unencrypted_state: UnencryptedData::init(initial_state),
                         initial_state,
-                        pending_transactions: Vec::new(),
-                        roll_hash: BigUint::new(vec![]),
                     },
-                    State {
+                    UncommittedState {
                         circuit_state: CircuitState {
                             squares: map_b,
                             gold_amount: 100,
@@ -318,476 +521,99 @@ impl State {
                             current_upkeep_costs: 0,
                         },
                         public_params: pp2,
-                        snark: unsafe { MaybeUninit::zeroed().assume_init() },
-                        initial_hash: unsafe { MaybeUninit::zeroed().assume_init() },
-                        r1cs: r1cs.clone(),
+                        r1cs,
                         phase2_circuit: circuit,
                         unencrypted_state: UnencryptedData::init(initial_state),
                         initial_state,
-                        pending_transactions: Vec::new(),
-                        roll_hash: BigUint::new(vec![]),
                     },
-                );
-
-                let in_a = vec![
-                    Fr::from_bytes(&sa.hash().to_bytes_le().try_into().unwrap()).unwrap(),
-                    0.into(),
-                ];
-                let in_b = vec![
-                    Fr::from_bytes(&sb.hash().to_bytes_le().try_into().unwrap()).unwrap(),
-                    0.into(),
-                ];
-                let snark_a = Snark::new(
-                    &pp,
-                    &sa.phase2_circuit,
-                    &circuit_secondary,
-                    in_a.clone(),
-                    z0_secondary.clone(),
-                );
-                let snark_b = Snark::new(
-                    &pp,
-                    &sb.phase2_circuit,
-                    &circuit_secondary,
-                    in_b.clone(),
-                    z0_secondary,
-                );
-                sa.snark = snark_a;
-                sb.snark = snark_b;
-                sa.initial_hash = in_a;
-                sb.initial_hash = in_b;
-
-                (sa, sb)
+                )
             }
         }
     }
 
-    fn append_transaction(&mut self, transaction: Transaction) {
-        self.pending_transactions.push(transaction);
+    /// Tour en préparation, commencé depuis l'état que le circuit obtiendra en
+    /// appliquant le début de tour : dégâts reçus, villages perdus, soins,
+    /// revenu et points de mouvement.
+    fn plan(&mut self) -> &mut Plan {
+        self.plan.get_or_insert_with(|| {
+            Plan::new(
+                &self.circuit_state,
+                &self.unencrypted_state.own_received_damage,
+                &self.unencrypted_state.adversary_captures,
+                self.visible_enemies
+                    .iter()
+                    .map(|enemy| enemy.position)
+                    .collect(),
+                &self.initial_state,
+            )
+        })
     }
 
-    fn phase1(
-        &self,
-    ) -> (
-        Vec<BigUint>,
-        Vec<(BigUint, BigUint)>,
-        Vec<(BigUint, BigUint)>,
-    ) {
-        let map_size = self.circuit_state.squares.len();
-        let exponents = random_exponents(map_size);
-        let phase1 = Phase1 {
-            previous_state: self,
-            damages_inflicted: vec![0; map_size],
-            captures: vec![0; self.initial_state.village_count() as usize],
-            exponents: to_exponent_bits(&exponents),
-        };
-        let phase1_input =
-            serde_json::to_string(&phase1).expect("Échec de l'initialisation de la phase 1 !");
-
-        let mut phase1_input_file =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        let phase1_witness =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        phase1_input_file
-            .write_all(phase1_input.as_bytes())
-            .expect("Impossible d'écrire dans le fichier les entrées de la phase 1");
-
-        // let circuit1 = self.initial_state.circuit_path().join("phase1/circuit");
-        let circuit1 = self
-            .initial_state
-            .circuit_path()
-            .join("phase1/circuit_cpp/circuit");
-        println!("Fichier entrée: {:?}", phase1_input_file.path());
-        println!("Fichier témoin: {:?}", phase1_witness.path());
-
-        let phase1_cmd = Command::new(circuit1)
-            .arg(phase1_input_file.path())
-            .arg(phase1_witness.path())
-            .output()
-            .expect("Le circuit de la phase 1 a échoué !");
-        assert!(phase1_cmd.status.success(), "{:?}", phase1_cmd);
-        let phase1_out: String =
-            String::from_utf8(phase1_cmd.stdout).expect("La phase 1 a donné du non-UTF-8 ??");
-
-        let couples = phase1_out
-            .lines()
-            .map(|x| {
-                let x: Vec<&str> = x.split_whitespace().collect();
-                (
-                    BigUint::from_str(x[0]).unwrap(),
-                    BigUint::from_str(x[1]).unwrap(),
-                )
-            })
-            .collect::<Vec<_>>();
-        assert_eq!(couples.len(), map_size * 2);
-        (
-            exponents.to_vec(),
-            couples[0..map_size].to_vec(),
-            couples[map_size..map_size * 2].to_vec(),
-        )
+    /// Prévoit les actions d'un tour scripté, en renvoyant celles refusées. Le
+    /// défenseur d'une attaque est celui que la vision révèle sur sa case.
+    fn append_turn(&mut self, turn: &[Scripted]) -> Vec<RuleViolation> {
+        let map = self.initial_state;
+        let visible = self.visible_enemies.clone();
+        let plan = self.plan();
+        turn.iter()
+            .filter_map(|action| plan.push_scripted(action, &visible, &map).err())
+            .collect()
     }
 
     fn hash(&self) -> BigUint {
-        // assert_eq!(self.circuit_state.squares.len(), )
-        let mut hash_input: Vec<u64> = self
-            .circuit_state
-            .squares
-            .iter()
-            .flat_map(|x| {
-                let unit: u64 = (&x.unit).into();
-                [unit, x.health_points, x.captured as u64, x.move_credits]
-            })
-            .collect();
-        hash_input.push(self.circuit_state.gold_amount);
-        hash_input.push(self.circuit_state.captured_village_count);
-        hash_input.push(self.circuit_state.current_upkeep_costs);
-
-        let json = serde_json::to_string(&HashObject {
-            to_hash: &hash_input,
-        })
-        .unwrap();
-
-        let mut input =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        let output =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        input
-            .write_all(json.as_bytes())
-            .expect("Impossible d'écrire dans le fichier les entrées du hash d'état");
-
-        // let phase_cmd = Command::new(self.initial_state.circuit_path().join("hash/hash_state"))
-        let phase_cmd = Command::new(
-            self.initial_state
-                .circuit_path()
-                .join("hash/hash_state_cpp/hash_state"),
-        )
-        .arg(input.path())
-        .arg(output.path())
-        .output()
-        .expect("Le circuit de hachagé a échoué !");
-        assert!(phase_cmd.status.success(), "{:?}", phase_cmd);
-        let mut hash_output: String =
-            String::from_utf8(phase_cmd.stdout).expect("Le hash n'est pas en UTF-8 ??");
-        hash_output.pop();
-        BigUint::from_str(hash_output.as_str()).unwrap()
+        self.circuit_state.hash(&self.initial_state)
     }
 
-    fn roll_hash(&mut self) -> BigUint {
-        /*
-        let hash_input: Vec<u64> = self.circuit_state.squares.iter().flat_map(|x| {
-            let unit: u64 = (&x.unit).into();
-            [
-                unit,
-                x.health_points,
-                x.captured as u64,
-                x.move_credits
-            ]
-        }).collect();
-
-        let mut hash_input = Vec::<u64>::with_capacity((width * height) as usize * 10 + 3);
-        hash_input.push(self.roll_hash);
-
-        hash_input = vec![
-            vec![],
-            vec![0; self.]
-            vec!hash_input
-        ].concat();
-        hash_input.push(self.circuit_state.gold_amount);
-        hash_input.push(self.circuit_state.captured_village_count);
-        hash_input.push(self.circuit_state.current_upkeep_costs);
-
-        let json = serde_json::to_string(&HashObject {
-            to_hash: &hash_input
-        }).unwrap();
-
-        let mut input = tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        let output = tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        input.write_all(json.as_bytes()).expect("Impossible d'écrire dans le fichier les entrées du hash d'état");
-
-        let phase_cmd = Command::new("map_circuits/hash/hash_state")
-            .arg(input.path())
-            .arg(output.path())
-            .output().expect("Le circuit de hachagé a échoué !");
-        assert!(phase_cmd.status.success(), "{:?}", phase_cmd);
-        let mut hash_output: String =
-            String::from_utf8(phase_cmd.stdout).expect("Le hash n'est pas en UTF-8 ??");
-        println!("{:?}", hash_output);
-        hash_output.pop();
-        BigUint::from_str(hash_output.as_str()).unwrap()*/
-        BigUint::new(vec![]) // TODO: hache chainé
+    /// État une fois appliqués les dégâts reçus depuis notre dernier tour, qui
+    /// ne le seront dans le circuit qu'au début de notre prochain tour.
+    fn damaged_state(&self) -> CircuitState {
+        let mut damaged = self.circuit_state.clone();
+        damaged.receive_damage(&self.unencrypted_state.own_received_damage);
+        damaged
     }
 
-    fn phase2(
-        &mut self,
-        diffie_hellmann_phase_1: Vec<(BigUint, BigUint)>,
-    ) -> (
-        Vec<(BigUint, BigUint)>,
-        Vec<(BigUint, BigUint)>,
-        Vec<(BigUint, BigUint, BigUint)>,
-    ) {
-        let mut random = rand::thread_rng();
-        let baby_jubjub_curve_order = BigUint::from_str(BABY_JUBJUB_ORDER).unwrap();
-        let exponent = random.gen_biguint_below(&baby_jubjub_curve_order);
-        let (width, height) = self.initial_state.size();
-        let map_size = width * height;
-        let own_exponents = random_exponents(map_size as usize);
-
-        let squares = &mut self.circuit_state.squares;
-        for transaction in &self.pending_transactions {
-            match transaction {
-                // TODO implement all of that
-                Transaction::None => {}
-                Transaction::MoveUnit((x, y), (x_, y_)) => {
-                    squares[(width * y_ + x_) as usize] = squares[(width * y + x) as usize];
-                    squares[(width * y + x) as usize] = Square {
-                        unit: Unit::None,
-                        health_points: 0,
-                        captured: false,
-                        move_credits: 0,
-                    };
-                }
-                Transaction::CaptureVillage(_) => {}
-                Transaction::PurchaseUnit(_, _) => {}
-            }
-        }
-
-        let phase2 = Phase2 {
-            rolling_hash: self.roll_hash(),
-            state: self,
-            damages_inflicted: vec![0; map_size as usize], // FIXME damages
-            exponent,
-            own_exponents,
-            received_data: diffie_hellmann_phase_1,
-        };
-        let phase2_input =
-            serde_json::to_string(&phase2).expect("Échec de l'initialisation de la phase 2 !");
-
-        let mut phase2_input_file =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        let mut phase2_witness =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        phase2_input_file
-            .write_all(phase2_input.as_bytes())
-            .expect("Impossible d'écrire dans le fichier les entrées de la phase 2");
-
-        // let circuit2 = self.initial_state.circuit_path().join("phase2nova/circuit");
-        let circuit2 = self
-            .initial_state
-            .circuit_path()
-            .join("phase2nova/circuit_cpp/circuit");
-        println!("Fichier entrée: {:?}", phase2_input_file.path());
-        println!("Fichier témoin: {:?}", phase2_witness.path());
-
-        let phase2_cmd = Command::new(circuit2)
-            .arg(phase2_input_file.path())
-            .arg(phase2_witness.path())
-            .output()
-            .expect("Le circuit de la phase 2 a échoué !");
-        assert!(phase2_cmd.status.success(), "{:?}", phase2_cmd);
-        let phase2_out: String =
-            String::from_utf8(phase2_cmd.stdout).expect("La phase 2 a donné du non-UTF-8 ??");
-
-        let mut lines = phase2_out.lines().collect::<Vec<_>>();
-        assert_eq!(lines.len(), 3 * map_size as usize);
-        let diffie_hellman = lines
-            .drain(0..map_size as usize)
-            .map(|x| {
-                let x: Vec<&str> = x.split_whitespace().collect();
-                (
-                    BigUint::from_str(x[0]).unwrap(),
-                    BigUint::from_str(x[1]).unwrap(),
-                )
-            })
-            .collect::<Vec<_>>();
-        let hidden_tags = lines
-            .drain(0..map_size as usize)
-            .map(|x| {
-                let x: Vec<&str> = x.split_whitespace().collect();
-                (
-                    BigUint::from_str(x[0]).unwrap(),
-                    BigUint::from_str(x[1]).unwrap(),
-                )
-            })
-            .collect::<Vec<_>>();
-        let hidden_data = lines
-            .drain(0..map_size as usize)
-            .map(|x| {
-                let x: Vec<&str> = x.split_whitespace().collect();
-                (
-                    BigUint::from_str(x[0]).unwrap(),
-                    BigUint::from_str(x[1]).unwrap(),
-                    BigUint::from_str(x[2]).unwrap(),
-                )
-            })
-            .collect::<Vec<_>>();
-        assert_eq!(
-            lines.len(),
-            0,
-            "Des lignes en plus données par le circuit 2 ?"
-        );
-
-        let circuit = CircomCircuit {
-            r1cs: self.r1cs.clone(),
-            witness: Some(load_witness_from_bin_file(phase2_witness.path())),
-        };
-
-        let circuit_secondary = TrivialTestCircuit::default();
-        let z0_secondary =
-            vec![<halo2curves::grumpkin::G1 as halo2curves::group::Group>::Scalar::ZERO];
-
-        self.snark
-            .prove_step(
-                &self.public_params,
-                &circuit,
-                &circuit_secondary,
-                self.initial_hash.clone(),
-                z0_secondary.clone(),
-            )
-            .unwrap();
-
-        (diffie_hellman, hidden_tags, hidden_data)
-    }
-
-    fn prove(
-        &self,
-    ) -> (
-        CompressedSNARK<
-            bn256::Point,
-            grumpkin::Point,
-            C1<bn256::Point>,
-            C2<grumpkin::Point>,
-            S<bn256::Point>,
-            S<grumpkin::Point>,
-        >,
-        VerifierKey<
-            bn256::Point,
-            grumpkin::Point,
-            C1<bn256::Point>,
-            C2<grumpkin::Point>,
-            S<bn256::Point>,
-            S<grumpkin::Point>,
-        >,
-    ) {
-        let (pk, vk) = CompressedSNARK::<_, _, _, _, S<bn256::Point>, S<grumpkin::Point>>::setup(
-            &self.public_params,
-        )
-        .unwrap();
-        (
-            CompressedSNARK::prove(&self.public_params, &pk, &self.snark).unwrap(),
-            vk,
-        )
+    /// Cases que nous voyons au début du tour adverse, une fois appliqués les
+    /// dégâts reçus comme le fait la phase 1.
+    fn visible_squares(&self) -> Vec<bool> {
+        self.damaged_state().vision(&self.initial_state)
     }
 
-    fn phase3(
-        &self,
-        exponents_a: Vec<BigUint>,
-        part3_stuff: Vec<(BigUint, BigUint)>,
-        dh_output: Vec<(BigUint, BigUint)>,
-        hidden_tags: Vec<(BigUint, BigUint)>,
-        hidden_data: Vec<(BigUint, BigUint, BigUint)>,
-    ) {
-        let baby_jubjub_curve_order = BigUint::from_str(BABY_JUBJUB_ORDER).unwrap();
-        let inv_a = exponents_a
+    /// Notre commandant a survécu aux dégâts reçus.
+    fn commander_alive(&self) -> bool {
+        self.damaged_state()
+            .squares
             .iter()
-            .map(|x| {
-                x.modinv(&baby_jubjub_curve_order).unwrap_or_else(|| {
-                    panic!("Les impairs n'étaient pas inversibles (notamment {}).", x)
-                })
-            })
-            .collect::<Vec<_>>();
-
-        let phase3_input = serde_json::to_string(&Phase3 {
-            hashed_idents: part3_stuff,
-            exponents_a: inv_a,
-            dh_output: dh_output,
-            hidden_tags: hidden_tags,
-            hidden_data: hidden_data,
-        })
-        .expect("Impossible de convertir en JSON.");
-        // println!("{}", phase3_input);
-
-        let mut phase3_input_file =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        let phase3_witness =
-            tempfile::NamedTempFile::new().expect("Impossible de créer un fichier temporaire");
-        phase3_input_file
-            .write_all(phase3_input.as_bytes())
-            .expect("Impossible d'écrire dans le fichier les entrées de la phase 3");
-
-        // let circuit3 = self.initial_state.circuit_path().join("phase3/circuit");
-        let circuit3 = self
-            .initial_state
-            .circuit_path()
-            .join("phase3/circuit_cpp/circuit");
-        println!("Fichier entrée: {:?}", phase3_input_file.path());
-        println!("Fichier témoin: {:?}", phase3_witness.path());
-
-        let phase3_cmd = Command::new(circuit3)
-            .arg(phase3_input_file.path())
-            .arg(phase3_witness.path())
-            .output()
-            .expect("Le circuit de la phase 3 a échoué !");
-        assert!(phase3_cmd.status.success(), "{:?}", phase3_cmd);
-        let phase3_out: String =
-            String::from_utf8(phase3_cmd.stdout).expect("La phase 3 a donné du non-UTF-8 ??");
-        // println!("{}", phase3_out);
+            .any(|square| square.unit.is_commander() && square.health_points > 0)
     }
-}
 
-struct Phase3 {
-    hashed_idents: Vec<(BigUint, BigUint)>,
-    exponents_a: Vec<BigUint>,
-    dh_output: Vec<(BigUint, BigUint)>,
-    hidden_tags: Vec<(BigUint, BigUint)>,
-    hidden_data: Vec<(BigUint, BigUint, BigUint)>,
-}
+    fn proven_steps(&self) -> usize {
+        self.proven_steps
+    }
 
-impl Serialize for Phase3 {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut serializer = serializer.serialize_map(Some(5))?;
-        serializer.serialize_entry(
-            "hashed_idents",
-            &self
-                .hashed_idents
-                .iter()
-                .map(|(a, b)| (a.to_string(), b.to_string()))
-                .collect::<Vec<_>>(),
-        )?;
-        serializer.serialize_entry("inv_phase1_exponents", &to_exponent_bits(&self.exponents_a))?;
-        serializer.serialize_entry(
-            "phase2_dh_output",
-            &self
-                .dh_output
-                .iter()
-                .map(|(a, b)| (a.to_string(), b.to_string()))
-                .collect::<Vec<_>>(),
-        )?;
-        serializer.serialize_entry(
-            "phase2_hidden_tags",
-            &self
-                .hidden_tags
-                .iter()
-                .map(|(a, b)| (a.to_string(), b.to_string()))
-                .collect::<Vec<_>>(),
-        )?;
-
-        fn to_bits(big_uint: &BigUint) -> Vec<u8> {
-            let mut x: Vec<u8> = big_uint.to_bytes_le().iter().flat_map(u8_to_bits).collect();
-            x.resize(64, 0);
-            x
+    /// Preuve de toutes les étapes jouées, une fois la file de preuve vidée.
+    fn prove(&mut self) -> Result<CompressedProof, ProofError> {
+        let compressed = self.prover.compress();
+        self.record_proving_times();
+        let compressed = compressed?;
+        let turn = self.metrics.turn();
+        if let Some(setup) = compressed.setup {
+            self.metrics.duration(turn, "proof.setup", setup);
         }
+        self.metrics
+            .duration(turn, "proof.compress", compressed.compress);
+        let proof = compressed.proof;
+        self.metrics.message(
+            "proof",
+            bincode::serialized_size(&proof).expect("Preuve impossible à sérialiser") as usize,
+        );
+        Ok(proof)
+    }
 
-        serializer.serialize_entry(
-            "phase2_hidden_data",
-            &self
-                .hidden_data
-                .iter()
-                .map(|(a, b, c)| (to_bits(a), to_bits(b), to_bits(c)))
-                .collect::<Vec<_>>(),
-        )?;
-        serializer.end()
+    /// Relève les `prove_step` terminés en arrière-plan.
+    fn record_proving_times(&mut self) {
+        for (turn, duration) in self.prover.finished_steps() {
+            self.metrics.duration(turn, "phase2.prove_step", duration);
+        }
     }
 }
 
@@ -796,394 +622,221 @@ struct HashObject<'a> {
     to_hash: &'a Vec<u64>,
 }
 
-struct Phase2<'a> {
-    rolling_hash: BigUint,
-    state: &'a State,
-    damages_inflicted: Vec<u64>,
-    exponent: BigUint,
-    own_exponents: Vec<BigUint>,
-    received_data: Vec<(BigUint, BigUint)>,
+/// Le joueur dont c'est le tour demande la preuve de l'autre si la politique
+/// l'exige.
+fn audit<S: Side>(game: &mut Game<S>) {
+    match game.audit() {
+        Some(true) => println!(
+            "{} a trouvé la preuve de {} de taille {}",
+            S::NAME,
+            <S::Opponent as Side>::NAME,
+            serde_json::to_string(&game.last_proof()).unwrap().len()
+        ),
+        Some(false) => println!(
+            "{} a refusé la preuve de {}",
+            S::NAME,
+            <S::Opponent as Side>::NAME
+        ),
+        None => {}
+    }
 }
 
-fn u8_to_bits(val: &u8) -> [u8; 8] {
-    [
-        (val >> 0) & 1u8,
-        (val >> 1) & 1u8,
-        (val >> 2) & 1u8,
-        (val >> 3) & 1u8,
-        (val >> 4) & 1u8,
-        (val >> 5) & 1u8,
-        (val >> 6) & 1u8,
-        (val >> 7) & 1u8,
-    ]
+/// Prévoit le tour scripté de `S` s'il y en a un. Les combats ne tirant pas le
+/// même aléa que dans Wesnoth, la partie peut s'écarter du replay : les actions
+/// devenues illégales sont ignorées. Renvoie faux quand le replay n'a plus de
+/// tour pour `S`, qui abandonne alors.
+fn script<S: Side>(game: &mut Game<S>, scripted: Option<&[Vec<Turn>; 2]>) -> bool {
+    let Some(turns) = scripted else {
+        return true;
+    };
+    // Chaque côté joue un tour sur deux, A aux tours impairs.
+    let Some(turn) = turns[S::INDEX as usize].get((game.turn() as usize - 1) / 2) else {
+        return false;
+    };
+    for violation in game.script(turn) {
+        println!("Action importée ignorée pour {} : {}.", S::NAME, violation);
+    }
+    true
 }
 
-impl Serialize for Transaction {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        self.to_action().serialize(serializer)
+/// Graine donnée par `SEED_VAR`, seulement dans les versions de déboguage.
+fn debug_seed() -> Option<u64> {
+    if !cfg!(debug_assertions) {
+        return None;
     }
+    let seed = std::env::var(SEED_VAR).ok()?;
+    Some(
+        seed.parse()
+            .unwrap_or_else(|_| panic!("{} doit être un entier, pas {:?}", SEED_VAR, seed)),
+    )
 }
 
-impl<'a> Serialize for Phase2<'a> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut serializer = serializer.serialize_map(Some(5))?;
-        let previous_circuit_state = &self.state.circuit_state;
-        let (width, height) = self.state.initial_state.size(); // , "0"]
-        serializer.serialize_entry(
-            "step_in",
-            &[self.state.hash().to_string(), self.rolling_hash.to_string()],
-        )?;
-        serializer.serialize_entry("prev_state", &previous_circuit_state.squares)?;
-        serializer.serialize_entry(
-            "prev_misc_state",
-            &[
-                previous_circuit_state.gold_amount,
-                previous_circuit_state.captured_village_count,
-                previous_circuit_state.current_upkeep_costs,
-            ],
-        )?;
-
-        let mut actions = self.state.pending_transactions.clone();
-        assert!(
-            actions.len() <= MAX_ACTION_COUNT,
-            "Trop d'actions en un seul tour !!!!"
-        );
-        actions.resize(MAX_ACTION_COUNT, Transaction::None);
-        serializer.serialize_entry("actions", &actions)?;
-        serializer.serialize_entry("degats", &self.damages_inflicted)?;
-        let mut captures = vec![0; self.state.initial_state.village_count() as usize];
-        for action in actions {
-            if let Transaction::CaptureVillage(village_id) = action {
-                captures[village_id as usize] = 1;
-            }
-        }
-        serializer.serialize_entry("captures", &captures)?;
-        serializer.serialize_entry("actions_captures", &vec![0; captures.len()])?; // TODO demander à françois ce que veut dire "action_captures" dans son circuit
-        serializer.serialize_entry("phase1_exponents", &to_exponent_bits(&self.own_exponents))?;
-        serializer.serialize_entry("phase2_exponent", &to_bits(&self.exponent))?;
-        serializer.serialize_entry(
-            "phase1_received",
-            &self
-                .received_data
-                .iter()
-                .map(|(x, y)| (x.to_string(), y.to_string()))
-                .collect::<Vec<_>>(),
-        )?;
-        serializer.end()
+/// Politique donnée par `POLICY_VAR`, ou celle par défaut.
+fn proof_policy() -> ProofPolicy {
+    match std::env::var(POLICY_VAR) {
+        Ok(policy) => policy
+            .parse()
+            .unwrap_or_else(|error| panic!("{} invalide : {}", POLICY_VAR, error)),
+        Err(_) => PROOF_POLICY,
     }
 }
 
-fn random_exponents(count: usize) -> Vec<BigUint> {
-    let mut random = rand::thread_rng();
-    let baby_jubjub_curve_order = BigUint::from_str(BABY_JUBJUB_ORDER).unwrap();
-    let p: BigUint = &baby_jubjub_curve_order / BigUint::new(vec![8]);
-    let mut exps: Vec<BigUint> = vec![0; count]
-        .iter()
-        .map(|_| random.gen_biguint_below(&baby_jubjub_curve_order))
-        .collect();
-    exps.iter_mut().for_each(|nbr| {
-        nbr.set_bit(0, true);
-        let reborrow = &*nbr;
-        let two: BigUint = BigUint::new(vec![2]);
-        if reborrow % &p == BigUint::ZERO {
-            *nbr = (reborrow + two) % &baby_jubjub_curve_order
-        };
-    });
-    exps
-}
-
-fn to_bits(big_uint: &BigUint) -> Vec<u8> {
-    let mut x: Vec<u8> = big_uint.to_bytes_le().iter().flat_map(u8_to_bits).collect();
-    x.resize(254, 0);
-    x
-}
-
-fn to_exponent_bits(exponents: &[BigUint]) -> Vec<Vec<u8>> {
-    exponents.iter().map(|x| to_bits(x)).collect()
-}
-
 fn main() {
     // On choisit la carte et les commandants
     let selected_config = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
 
-    // On récupère le circuit et les états initiaux.
-    let (mut state_joueur_a, mut state_joueur_b) = State::initial_states(selected_config);
-
-    // (Décision qui est le joueur A et qui est le joueur B, et chacun ne fera que sa partie)
-    
-    // state_joueur_a.circuit_state.squares[9] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[8] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[7] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[6] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[5] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[4] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[3] = state_joueur_a.circuit_state.squares[0];
-    // state_joueur_a.circuit_state.squares[2] = state_joueur_a.circuit_state.squares[0];
-
-    // state_joueur_a.circuit_state.squares[0] = Square {
-    //     unit: Unit::None,
-    //     health_points: 0,
-    //     captured: false,
-    //     move_credits: 0,
-    // };
-
-    // state_joueur_a.append_transaction(Transaction::MoveUnit((100, 0), (2, 2)));
-    // state_joueur_a.append_transaction(Transaction::MoveUnit((0, 0), (8, 8)));
-    // state_joueur_a.append_transaction(Transaction::MoveUnit((2, 2), (4, 4)));
-    // state_joueur_a.append_transaction(Transaction::MoveUnit((4, 4), (6, 6)));
-    // state_joueur_a.append_transaction(Transaction::MoveUnit((6, 6), (8, 8)));
-    println!("Au tour d'Ashley.");
-    let begin = Instant::now();
-    {
-        // state_joueur_a.append_transaction(Transaction::MoveUnit((0, 0), (2, 2)));
-        // state_joueur_a.circuit_state.squares[2] = state_joueur_a.circuit_state.squares[0];
-        // state_joueur_a.circuit_state.squares[0] = Square {
-        //     unit: Unit::None,
-        //     health_points: 0,
-        //     captured: false,
-        //     move_credits: 0,
-        // };
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
+    // `cargo run -- circuits` génère les circuits de la carte, à recompiler
+    // ensuite avec `compile.sh`. Seul le dossier des circuits est écrit sur la
+    // sortie standard.
+    if std::env::args().nth(1).as_deref() == Some("circuits") {
+        let root = selected_config.circuit_path();
+        copy_sources(&PathBuf::from(CIRCUIT_SOURCES), &root)
+            .expect("Impossible de copier les sources des circuits");
+        Manifest::new(&selected_config)
+            .write(&root)
+            .expect("Impossible de générer les circuits");
+        eprintln!("Circuits générés dans {:?}.", root);
+        println!("{}", root.display());
+        return;
+    }
+    // `cargo run -- replay <journal>` revérifie une partie déjà jouée, sans
+    // les circuits. La clé de vérification est tirée des paramètres publics de
+    // la carte, pas du journal.
+    if std::env::args().nth(1).as_deref() == Some("replay") {
+        let path = PathBuf::from(
+            std::env::args()
+                .nth(2)
+                .expect("Il manque le journal à rejouer"),
         );
-    }
-    println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        println!("Brandon veut une preuve !");
-        // println!("Il n'aura qu'à se faire foutre.");
-        let (proof, vk) = state_joueur_a.prove();
-        let proof_res = proof.verify(
-            &vk,
-            2,
-            state_joueur_a.initial_hash.clone(),
-            vec![<halo2curves::grumpkin::G1 as halo2curves::group::Group>::Scalar::ZERO],
+        let journal = Journal::read(&path)
+            .unwrap_or_else(|error| panic!("Journal {:?} illisible : {}", path, error));
+        let key_path = selected_config
+            .circuit_path()
+            .join("phase2nova/public_parameters");
+        let public_params: PublicParameters = File::open(&key_path)
+            .map_err(bincode::Error::from)
+            .and_then(|file| deserialize_from(BufReader::new(file)))
+            .unwrap_or_else(|error| {
+                panic!("Paramètres publics {:?} illisibles : {}", key_path, error)
+            });
+        let vk = verifier_key(&public_params)
+            .unwrap_or_else(|error| panic!("Clé de vérification impossible : {}", error));
+        match journal.replay(&vk) {
+            Ok(proofs) => println!("Journal cohérent, {} preuves revérifiées.", proofs),
+            Err(inconsistency) => {
+                eprintln!("Premier tour incohérent, {}", inconsistency);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // `cargo run -- wml <sauvegarde>` joue les tours d'un replay de Wesnoth sur
+    // la même carte.
+    let scripted = (std::env::args().nth(1).as_deref() == Some("wml")).then(|| {
+        let path = PathBuf::from(
+            std::env::args()
+                .nth(2)
+                .expect("Il manque la sauvegarde à importer"),
         );
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("Sauvegarde {:?} illisible : {}", path, error));
+        let turns = wml::import_replay(&text, &selected_config)
+            .unwrap_or_else(|error| panic!("Replay {:?} invalide : {}", path, error));
         println!(
-            "Brandon a trouvé la preuve d'Ashley de taille {} {:?}",
-            serde_json::to_string(&proof).unwrap().len(),
-            proof_res
-        );
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
-    println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
+            "Replay importé : {} tours pour {}, {} pour {}.",
+            turns[SideA::INDEX as usize].len(),
+            SideA::NAME,
+            turns[SideB::INDEX as usize].len(),
+            SideB::NAME
         );
+        turns
+    });
+    if let Err(error) = check_artifacts(&selected_config) {
+        panic!("Démarrage refusé : {}", error);
     }
-    println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
+
+    let seed = debug_seed();
+    if let Some(seed) = seed {
+        eprintln!("Secrets tirés de la graine {}, partie rejouable.", seed);
     }
-    println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
+
+    // On récupère le circuit et les états initiaux.
+    let (state_joueur_a, state_joueur_b) = State::initial_states(selected_config);
+
+    // Chaque joueur engage son état initial.
+    // (Décision qui est le joueur A et qui est le joueur B, et chacun ne fera que sa partie)
+    let mut game: Game<SideA> = Game::new(
+        Player::new(state_joueur_a.commit(secret_rng(seed, SideA::INDEX))),
+        Player::new(state_joueur_b.commit(secret_rng(seed, SideB::INDEX))),
+        TURN_LIMIT,
+        proof_policy(),
     );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
+
+    let game_over = loop {
+        if let Some(ending) = game.ending() {
+            break game.finish(ending);
+        }
+        let begin = Instant::now();
+        println!("Tour {}, au tour de {}.", game.turn(), SideA::NAME);
+        if !script(&mut game, scripted.as_ref()) {
+            println!("Replay terminé, {} abandonne.", SideA::NAME);
+            break game.resign();
+        }
+        let mut game_b = game.play_turn();
+        println!(
+            "{:?} pour valider les actions de {}.",
+            begin.elapsed(),
+            SideA::NAME
         );
-    }
-    println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
-    );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_b.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_a.phase2(diffie_hellmann);
-        state_joueur_b.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
+
+        audit(&mut game_b);
+
+        if let Some(ending) = game_b.ending() {
+            break game_b.finish(ending);
+        }
+        let begin = Instant::now();
+        println!("Tour {}, au tour de {}.", game_b.turn(), SideB::NAME);
+        if !script(&mut game_b, scripted.as_ref()) {
+            println!("Replay terminé, {} abandonne.", SideB::NAME);
+            break game_b.resign();
+        }
+        game = game_b.play_turn();
+        println!(
+            "{:?} pour valider les actions de {}.",
+            begin.elapsed(),
+            SideB::NAME
         );
-    }
+        audit(&mut game);
+    };
+
+    let report = game_over.report();
+    std::fs::create_dir_all(REPORT_DIR).expect("Impossible de créer le dossier des rapports");
+    let report_path = PathBuf::from(REPORT_DIR).join(format!("{}.json", report.game_id));
+    report
+        .write(&report_path)
+        .expect("Impossible d'écrire le rapport de la partie");
+    println!("Rapport de la partie écrit dans {:?}.", report_path);
+    let journal_path = report_path.with_extension("journal");
+    game_over
+        .journal
+        .write(&journal_path)
+        .expect("Impossible d'écrire le journal de la partie");
+    println!("Journal de la partie écrit dans {:?}.", journal_path);
+
     println!(
-        "{:?} pour valider les actions d'Ashley. Au tour de Brandon.",
-        begin.elapsed()
+        "Preuves finales vérifiées : {} {}, {} {}.",
+        SideA::NAME,
+        game_over.proofs_verified[SideA::INDEX as usize],
+        SideB::NAME,
+        game_over.proofs_verified[SideB::INDEX as usize]
     );
-    let begin = Instant::now();
-    {
-        let (exponents_a, part3_stuff, diffie_hellmann) = state_joueur_a.phase1();
-        let (dh_output, hidden_tags, hidden_data) = state_joueur_b.phase2(diffie_hellmann);
-        state_joueur_a.phase3(
-            exponents_a,
-            part3_stuff,
-            dh_output,
-            hidden_tags,
-            hidden_data,
-        );
-    }
     println!(
-        "{:?} pour valider les actions de Brandon. Au tour d'Ashley.",
-        begin.elapsed()
+        "Fin de la partie au tour {} ({:?}) : {} {:?}, {} {:?}.",
+        game_over.turn,
+        game_over.ending,
+        SideA::NAME,
+        game_over.results[SideA::INDEX as usize],
+        SideB::NAME,
+        game_over.results[SideB::INDEX as usize]
     );
-    let begin = Instant::now();
 }
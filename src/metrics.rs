@@ -0,0 +1,219 @@
+//! Mesures de performance d'une partie, écrites en JSON pour comparer les
+//! cartes et les systèmes de preuve d'une exécution à l'autre.
+//!
+//! Chaque joueur mesure ses propres étapes : sérialisation des entrées des
+//! circuits, génération des témoins, lecture de leurs sorties, `prove_step`,
+//! `CompressedSNARK::setup` et compression. Les messages sont comptés en octets
+//! par celui qui les envoie, avec des éléments de corps de 32 octets.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use halo2curves::bn256::Fr;
+use nova_scotia::circom::circuit::R1CS;
+use serde::Serialize;
+
+use crate::game::{Ending, GameResult};
+
+/// Taille d'un élément de corps envoyé à l'adversaire.
+pub const FIELD_BYTES: usize = 32;
+
+/// Système de preuve utilisé, pour distinguer les rapports.
+pub const BACKEND: &str = "nova-snark 0.23, bn256/grumpkin, Spartan";
+
+#[derive(Serialize, Clone, Debug)]
+struct Timing {
+    turn: u64,
+    step: &'static str,
+    micros: u128,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct MessageSize {
+    turn: u64,
+    message: &'static str,
+    bytes: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct WitnessSize {
+    turn: u64,
+    circuit: &'static str,
+    length: usize,
+}
+
+/// Mesures d'un joueur au fil de la partie.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Metrics {
+    #[serde(skip)]
+    turn: u64,
+    timings: Vec<Timing>,
+    messages: Vec<MessageSize>,
+    witnesses: Vec<WitnessSize>,
+}
+
+impl Metrics {
+    /// Les mesures suivantes sont attribuées au tour `turn`.
+    pub fn set_turn(&mut self, turn: u64) {
+        self.turn = turn;
+    }
+
+    pub fn turn(&self) -> u64 {
+        self.turn
+    }
+
+    /// Enregistre le temps écoulé depuis `begin` pour l'étape `step`.
+    pub fn time(&mut self, step: &'static str, begin: Instant) {
+        self.duration(self.turn, step, begin.elapsed());
+    }
+
+    /// Enregistre une durée mesurée ailleurs, par exemple par le fil de preuve,
+    /// pour le tour où l'étape a commencé.
+    pub fn duration(&mut self, turn: u64, step: &'static str, duration: Duration) {
+        self.timings.push(Timing {
+            turn,
+            step,
+            micros: duration.as_micros(),
+        });
+    }
+
+    pub fn message(&mut self, message: &'static str, bytes: usize) {
+        self.messages.push(MessageSize {
+            turn: self.turn,
+            message,
+            bytes,
+        });
+    }
+
+    /// Nombre d'éléments du témoin d'un circuit.
+    pub fn witness(&mut self, circuit: &'static str, length: usize) {
+        self.witnesses.push(WitnessSize {
+            turn: self.turn,
+            circuit,
+            length,
+        });
+    }
+}
+
+/// Taille du circuit de la phase 2, le seul prouvé.
+#[derive(Serialize, Clone, Debug)]
+pub struct R1csSize {
+    pub constraints: usize,
+    pub variables: usize,
+    pub public_inputs: usize,
+}
+
+impl From<&R1CS<Fr>> for R1csSize {
+    fn from(r1cs: &R1CS<Fr>) -> Self {
+        R1csSize {
+            constraints: r1cs.constraints.len(),
+            variables: r1cs.num_variables,
+            public_inputs: r1cs.num_inputs,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SideReport<'a> {
+    pub name: &'static str,
+    pub result: GameResult,
+    pub metrics: &'a Metrics,
+}
+
+/// Rapport d'une partie, un fichier JSON par partie.
+#[derive(Serialize, Debug)]
+pub struct Report<'a> {
+    pub game_id: String,
+    pub map: (u64, u64),
+    pub backend: &'static str,
+    pub r1cs: R1csSize,
+    pub turns: u64,
+    pub ending: Ending,
+    /// Dans l'ordre des côtés.
+    pub sides: [SideReport<'a>; 2],
+}
+
+impl Report<'_> {
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn report_keeps_every_measure_under_its_turn() {
+        let mut metrics = Metrics::default();
+        metrics.set_turn(3);
+        metrics.duration(2, "phase2.prove_step", Duration::from_micros(1500));
+        metrics.message("phase2", 640);
+        metrics.witness("phase2", 1234);
+        let begin = Instant::now();
+        metrics.time("phase3.witness", begin);
+
+        let r1cs = R1CS::<Fr> {
+            num_inputs: 5,
+            num_aux: 10,
+            num_variables: 16,
+            constraints: vec![(vec![], vec![], vec![]); 7],
+        };
+        let empty = Metrics::default();
+        let report = Report {
+            game_id: "00ff".to_string(),
+            map: (10, 10),
+            backend: BACKEND,
+            r1cs: R1csSize::from(&r1cs),
+            turns: 3,
+            ending: Ending::TurnLimit,
+            sides: [
+                SideReport {
+                    name: "Ashley",
+                    result: GameResult::Drawn,
+                    metrics: &metrics,
+                },
+                SideReport {
+                    name: "Brandon",
+                    result: GameResult::Drawn,
+                    metrics: &empty,
+                },
+            ],
+        };
+        let report: Value = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(
+            report["r1cs"],
+            json!({"constraints": 7, "variables": 16, "public_inputs": 5})
+        );
+        let side = &report["sides"][0]["metrics"];
+        // La durée mesurée par le fil de preuve garde le tour de son étape.
+        assert_eq!(
+            side["timings"][0],
+            json!({"turn": 2, "step": "phase2.prove_step", "micros": 1500})
+        );
+        assert_eq!(side["timings"][1]["turn"], 3);
+        assert_eq!(side["timings"][1]["step"], "phase3.witness");
+        assert!(side["timings"][1]["micros"].is_u64());
+        assert_eq!(
+            side["messages"],
+            json!([{"turn": 3, "message": "phase2", "bytes": 640}])
+        );
+        assert_eq!(
+            side["witnesses"],
+            json!([{"turn": 3, "circuit": "phase2", "length": 1234}])
+        );
+        // Le tour courant n'est pas une mesure.
+        assert!(side.get("turn").is_none());
+        assert_eq!(
+            report["sides"][1]["metrics"],
+            json!({"timings": [], "messages": [], "witnesses": []})
+        );
+    }
+}
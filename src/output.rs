@@ -0,0 +1,381 @@
+//! Sorties des circuits, imprimées par leurs `log`.
+//!
+//! Chaque ligne utile est `groupe indice valeurs...` : le groupe dit ce que
+//! contient la ligne, l'indice est celui de la case. Les lignes des autres
+//! groupes, comme des `log` de déboguage, sont ignorées. Une case manquante ou
+//! en double, ou une valeur en trop, manquante ou illisible dans un groupe
+//! attendu est une erreur.
+
+use std::fmt;
+
+use num_bigint::BigUint;
+
+use crate::convert::{decimal_to_fr, fr_to_biguint};
+use crate::{HiddenData, Point};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputError {
+    Missing {
+        group: &'static str,
+        index: usize,
+    },
+    Duplicate {
+        group: &'static str,
+        index: usize,
+    },
+    IndexOutOfRange {
+        group: &'static str,
+        index: usize,
+        count: usize,
+    },
+    WrongArity {
+        group: &'static str,
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+    Malformed {
+        group: &'static str,
+        line: String,
+    },
+    NotABit {
+        group: &'static str,
+        index: usize,
+    },
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::Missing { group, index } => {
+                write!(f, "aucune ligne {} pour la case {}", group, index)
+            }
+            OutputError::Duplicate { group, index } => {
+                write!(f, "plusieurs lignes {} pour la case {}", group, index)
+            }
+            OutputError::IndexOutOfRange {
+                group,
+                index,
+                count,
+            } => write!(
+                f,
+                "ligne {} pour la case {} alors qu'il n'y en a que {}",
+                group, index, count
+            ),
+            OutputError::WrongArity {
+                group,
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} valeurs au lieu de {} dans la ligne {} de la case {}",
+                found, expected, group, index
+            ),
+            OutputError::Malformed { group, line } => {
+                write!(f, "ligne {} illisible : {:?}", group, line)
+            }
+            OutputError::NotABit { group, index } => {
+                write!(f, "la ligne {} de la case {} n'est ni 0 ni 1", group, index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+/// Lit les groupes `groups`, donnés avec leur nombre de valeurs par ligne, pour
+/// les cases `0..count`. Les lignes sont renvoyées dans l'ordre des cases.
+fn parse<const N: usize>(
+    stdout: &str,
+    groups: [(&'static str, usize); N],
+    count: usize,
+) -> Result<[Vec<Vec<BigUint>>; N], OutputError> {
+    let mut rows: [Vec<Option<Vec<BigUint>>>; N] = std::array::from_fn(|_| vec![None; count]);
+    for line in stdout.lines() {
+        let mut words = line.split_whitespace();
+        let Some(slot) = words
+            .next()
+            .and_then(|word| groups.iter().position(|&(group, _)| group == word))
+        else {
+            continue;
+        };
+        let (group, arity) = groups[slot];
+        let malformed = || OutputError::Malformed {
+            group,
+            line: line.to_string(),
+        };
+        let index: usize = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(malformed)?;
+        let values = words
+            .map(|word| {
+                decimal_to_fr(word)
+                    .map(|value| fr_to_biguint(&value))
+                    .map_err(|_| malformed())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() != arity {
+            return Err(OutputError::WrongArity {
+                group,
+                index,
+                expected: arity,
+                found: values.len(),
+            });
+        }
+        let row = rows[slot]
+            .get_mut(index)
+            .ok_or(OutputError::IndexOutOfRange {
+                group,
+                index,
+                count,
+            })?;
+        if row.is_some() {
+            return Err(OutputError::Duplicate { group, index });
+        }
+        *row = Some(values);
+    }
+
+    let mut parsed: [Vec<Vec<BigUint>>; N] = std::array::from_fn(|_| Vec::new());
+    for (slot, group_rows) in rows.into_iter().enumerate() {
+        let group = groups[slot].0;
+        parsed[slot] = group_rows
+            .into_iter()
+            .enumerate()
+            .map(|(index, row)| row.ok_or(OutputError::Missing { group, index }))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(parsed)
+}
+
+fn points(rows: Vec<Vec<BigUint>>) -> Vec<Point> {
+    rows.into_iter()
+        .map(|row| {
+            let [x, y] = <[BigUint; 2]>::try_from(row).unwrap();
+            (x, y)
+        })
+        .collect()
+}
+
+fn hidden_data(rows: Vec<Vec<BigUint>>) -> Vec<HiddenData> {
+    rows.into_iter()
+        .map(|row| {
+            let [a, b, c] = <[BigUint; 3]>::try_from(row).unwrap();
+            (a, b, c)
+        })
+        .collect()
+}
+
+fn bits(group: &'static str, rows: Vec<Vec<BigUint>>) -> Result<Vec<bool>, OutputError> {
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, row)| match u8::try_from(&row[0]) {
+            Ok(0) => Ok(false),
+            Ok(1) => Ok(true),
+            _ => Err(OutputError::NotABit { group, index }),
+        })
+        .collect()
+}
+
+/// Sortie de la phase 1 : hashs des identifiants des cases, points envoyés à
+/// l'adversaire et cases visibles.
+pub struct Phase1Output {
+    pub hashed_idents: Vec<Point>,
+    pub diffie_hellman: Vec<Point>,
+    pub vision: Vec<bool>,
+}
+
+impl Phase1Output {
+    pub fn parse(stdout: &str, map_size: usize) -> Result<Phase1Output, OutputError> {
+        let [idents, diffie_hellman, vision] =
+            parse(stdout, [("ident", 2), ("dh", 2), ("vision", 1)], map_size)?;
+        Ok(Phase1Output {
+            hashed_idents: points(idents),
+            diffie_hellman: points(diffie_hellman),
+            vision: bits("vision", vision)?,
+        })
+    }
+}
+
+/// Sortie de la phase 2 : réponse à la phase 1 adverse, tags et données
+/// masquées de nos cases, et points de phase 1 accumulés dans la chaîne.
+pub struct Phase2Output {
+    pub dh_output: Vec<Point>,
+    pub hidden_tags: Vec<Point>,
+    pub hidden_data: Vec<HiddenData>,
+    pub phase1_output: Vec<Point>,
+}
+
+impl Phase2Output {
+    pub fn parse(stdout: &str, map_size: usize) -> Result<Phase2Output, OutputError> {
+        let [dh_output, hidden_tags, data, phase1_output] = parse(
+            stdout,
+            [("dh", 2), ("tag", 2), ("data", 3), ("p1", 2)],
+            map_size,
+        )?;
+        Ok(Phase2Output {
+            dh_output: points(dh_output),
+            hidden_tags: points(hidden_tags),
+            hidden_data: hidden_data(data),
+            phase1_output: points(phase1_output),
+        })
+    }
+}
+
+/// Sortie de la phase 3 : tags recalculés pour chaque case et données
+/// démasquées, qui ne sont justes que si le tag correspond à celui reçu.
+pub struct Phase3Output {
+    pub own_tags: Vec<Point>,
+    pub candidates: Vec<HiddenData>,
+}
+
+impl Phase3Output {
+    pub fn parse(stdout: &str, map_size: usize) -> Result<Phase3Output, OutputError> {
+        let [own_tags, candidates] = parse(stdout, [("tag", 2), ("data", 3)], map_size)?;
+        Ok(Phase3Output {
+            own_tags: points(own_tags),
+            candidates: hidden_data(candidates),
+        })
+    }
+}
+
+/// Sortie du circuit de hachage de l'état.
+pub fn parse_hash(stdout: &str) -> Result<BigUint, OutputError> {
+    let [mut hash] = parse(stdout, [("hash", 1)], 1)?;
+    Ok(hash.remove(0).remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::field_modulus;
+
+    const PHASE1: &str = "\
+ident 0 1 2
+ident 1 3 4
+debug 0 ce que le circuit veut
+dh 1 7 8
+dh 0 5 6
+vision 0 1
+vision 1 0
+";
+
+    fn phase1(stdout: &str) -> Result<Phase1Output, OutputError> {
+        Phase1Output::parse(stdout, 2)
+    }
+
+    #[test]
+    fn lines_are_read_in_square_order() {
+        let output = phase1(PHASE1).unwrap();
+        assert_eq!(output.hashed_idents[1], (3u8.into(), 4u8.into()));
+        assert_eq!(output.diffie_hellman[0], (5u8.into(), 6u8.into()));
+        assert_eq!(output.diffie_hellman[1], (7u8.into(), 8u8.into()));
+        assert_eq!(output.vision, [true, false]);
+        assert_eq!(parse_hash("hash 0 42\n"), Ok(42u8.into()));
+    }
+
+    #[test]
+    fn missing_square() {
+        let stdout = PHASE1.replace("dh 1 7 8\n", "");
+        assert_eq!(
+            phase1(&stdout).err(),
+            Some(OutputError::Missing {
+                group: "dh",
+                index: 1
+            })
+        );
+        assert_eq!(
+            parse_hash("").err(),
+            Some(OutputError::Missing {
+                group: "hash",
+                index: 0
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_square() {
+        let stdout = format!("{}ident 0 1 2\n", PHASE1);
+        assert_eq!(
+            phase1(&stdout).err(),
+            Some(OutputError::Duplicate {
+                group: "ident",
+                index: 0
+            })
+        );
+    }
+
+    #[test]
+    fn square_out_of_range() {
+        let stdout = format!("{}vision 2 1\n", PHASE1);
+        assert_eq!(
+            phase1(&stdout).err(),
+            Some(OutputError::IndexOutOfRange {
+                group: "vision",
+                index: 2,
+                count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn wrong_arity() {
+        let stdout = PHASE1.replace("dh 0 5 6", "dh 0 5 6 9");
+        assert_eq!(
+            phase1(&stdout).err(),
+            Some(OutputError::WrongArity {
+                group: "dh",
+                index: 0,
+                expected: 2,
+                found: 3
+            })
+        );
+        let stdout = "dh 0 1 2\ntag 0 3\ndata 0 4 5 6\n";
+        assert_eq!(
+            Phase2Output::parse(stdout, 1).err(),
+            Some(OutputError::WrongArity {
+                group: "tag",
+                index: 0,
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_line() {
+        // Une valeur hors du corps ne peut pas sortir d'un circuit.
+        let out_of_field = format!("ident 1 3 {}", field_modulus());
+        for line in [
+            "ident x 1 2",
+            "ident 0 1 2e3",
+            "ident",
+            "ident -1 1 2",
+            &out_of_field,
+        ] {
+            let stdout = PHASE1.replace("ident 1 3 4", line);
+            assert_eq!(
+                phase1(&stdout).err(),
+                Some(OutputError::Malformed {
+                    group: "ident",
+                    line: line.to_string()
+                }),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn vision_is_a_bit() {
+        let stdout = PHASE1.replace("vision 1 0", "vision 1 2");
+        assert_eq!(
+            phase1(&stdout).err(),
+            Some(OutputError::NotABit {
+                group: "vision",
+                index: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! Déplacements selon le terrain et les zones de contrôle ennemies.
+//!
+//! Le circuit ne connaît pas le terrain : il vérifie seulement que la distance
+//! à vol d'oiseau d'un `MoveUnit` tient dans les points de mouvement, et n'en
+//! retire que cette distance. Les chemins sont donc vérifiés ici avec les coûts
+//! de Wesnoth, puis traduits en actions que le circuit accepte.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::hex::distance;
+use crate::rules::RuleViolation;
+use crate::unit::Unit;
+use crate::{CircuitState, InitialState, Position, Transaction};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Terrain {
+    Flat,
+    Castle,
+    Village,
+    Forest,
+    Hills,
+    Mountains,
+    ShallowWater,
+    Swamp,
+}
+
+/// Types de déplacement des unités de la faction, comme dans les `movetype`
+/// de Wesnoth.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MovementType {
+    OrcishFoot,
+    LargeFoot,
+    Naga,
+}
+
+impl MovementType {
+    /// Points de mouvement pour entrer sur une case de ce terrain.
+    pub fn cost(&self, terrain: Terrain) -> u64 {
+        match (self, terrain) {
+            (MovementType::Naga, Terrain::Flat | Terrain::Castle) => 2,
+            (MovementType::Naga, Terrain::Village) => 1,
+            (MovementType::Naga, Terrain::Forest | Terrain::Hills) => 3,
+            (MovementType::Naga, Terrain::Mountains) => 5,
+            (MovementType::Naga, Terrain::ShallowWater | Terrain::Swamp) => 1,
+            (MovementType::LargeFoot, Terrain::ShallowWater | Terrain::Swamp) => 2,
+            (_, Terrain::Flat | Terrain::Castle | Terrain::Village | Terrain::Hills) => 1,
+            (_, Terrain::Forest | Terrain::Mountains) => 2,
+            (_, Terrain::ShallowWater | Terrain::Swamp) => 3,
+        }
+    }
+}
+
+/// Chemin case par case, départ compris.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Path {
+    pub steps: Vec<Position>,
+}
+
+impl Path {
+    pub fn origin(&self) -> Option<Position> {
+        self.steps.first().copied()
+    }
+
+    pub fn destination(&self) -> Option<Position> {
+        self.steps.last().copied()
+    }
+
+    /// Le déplacement en une seule action, que le circuit accepte puisque la
+    /// distance ne dépasse jamais le coût du chemin.
+    pub fn as_move(&self) -> Result<Transaction, RuleViolation> {
+        match (self.origin(), self.destination()) {
+            (Some(origin), Some(destination)) => Ok(Transaction::MoveUnit(origin, destination)),
+            _ => Err(RuleViolation::EmptyPath),
+        }
+    }
+}
+
+/// Cases atteignables par une unité, avec le coût minimal pour y arriver.
+pub struct Reachable {
+    origin: Position,
+    /// Coût et case précédente de chaque case traversable, y compris celles
+    /// de nos unités, où l'on ne peut pas s'arrêter.
+    costs: HashMap<Position, (u64, Position)>,
+    occupied: HashSet<Position>,
+}
+
+impl Reachable {
+    pub fn cost(&self, position: Position) -> Option<u64> {
+        if self.occupied.contains(&position) {
+            return None;
+        }
+        self.costs.get(&position).map(|&(cost, _)| cost)
+    }
+
+    /// Chemin le moins cher jusqu'à `destination`.
+    pub fn path_to(&self, destination: Position) -> Option<Path> {
+        self.cost(destination)?;
+        let mut steps = vec![destination];
+        while *steps.last().unwrap() != self.origin {
+            steps.push(self.costs[steps.last().unwrap()].1);
+        }
+        steps.reverse();
+        Some(Path { steps })
+    }
+}
+
+/// Cases en zone de contrôle : adjacentes à un ennemi connu.
+fn zones_of_control(map: &InitialState, enemies: &[Position]) -> HashSet<Position> {
+    let grid = map.grid();
+    enemies
+        .iter()
+        .flat_map(|&enemy| grid.neighbours(enemy).collect::<Vec<_>>())
+        .collect()
+}
+
+/// Dijkstra depuis `origin` avec les points de mouvement restants de l'unité.
+/// On traverse nos unités sans pouvoir s'y arrêter, jamais les ennemis, et on
+/// s'arrête en entrant dans une zone de contrôle.
+pub fn reachable(
+    map: &InitialState,
+    state: &CircuitState,
+    origin: Position,
+    enemies: &[Position],
+) -> Reachable {
+    let grid = map.grid();
+    let square = state.squares[map.index(origin)];
+    let movement_type = square.unit.movement_type();
+    let zones = zones_of_control(map, enemies);
+
+    let mut best: HashMap<Position, (u64, Position)> = HashMap::from([(origin, (0, origin))]);
+    let mut queue = BinaryHeap::from([Reverse((0, origin))]);
+    while let Some(Reverse((cost, position))) = queue.pop() {
+        if cost > best[&position].0 || (position != origin && zones.contains(&position)) {
+            continue;
+        }
+        for next in grid.neighbours(position) {
+            if enemies.contains(&next) {
+                continue;
+            }
+            let next_cost = cost + movement_type.cost(map.terrain(next));
+            if next_cost <= square.move_credits
+                && best.get(&next).is_none_or(|&(known, _)| next_cost < known)
+            {
+                best.insert(next, (next_cost, position));
+                queue.push(Reverse((next_cost, next)));
+            }
+        }
+    }
+
+    let occupied = best
+        .keys()
+        .copied()
+        .filter(|&position| state.squares[map.index(position)].unit != Unit::None)
+        .collect();
+    Reachable {
+        origin,
+        costs: best,
+        occupied,
+    }
+}
+
+impl CircuitState {
+    /// Vérifie un chemin case par case, en plus des règles du `MoveUnit`
+    /// équivalent. Renvoie les points de mouvement qu'il consomme : son coût,
+    /// ou tous ceux qui restent s'il finit dans une zone de contrôle.
+    pub fn check_path(
+        &self,
+        path: &Path,
+        map: &InitialState,
+        enemies: &[Position],
+    ) -> Result<u64, RuleViolation> {
+        let transaction = path.as_move()?;
+        self.check(transaction, map)?;
+
+        let square = self.squares[map.index(path.steps[0])];
+        let movement_type = square.unit.movement_type();
+        let zones = zones_of_control(map, enemies);
+        let mut cost = 0;
+        for (index, pair) in path.steps.windows(2).enumerate() {
+            let (from, to) = (pair[0], pair[1]);
+            if distance(from, to) != 1 || !map.grid().contains(to) {
+                return Err(RuleViolation::NotAdjacent(from, to));
+            }
+            if index > 0 && zones.contains(&from) {
+                return Err(RuleViolation::ZoneOfControl(from));
+            }
+            if enemies.contains(&to) {
+                return Err(RuleViolation::BlockedByEnemy(to));
+            }
+            cost += movement_type.cost(map.terrain(to));
+        }
+        if cost > square.move_credits {
+            return Err(RuleViolation::TooExpensive {
+                cost,
+                move_credits: square.move_credits,
+            });
+        }
+        if path
+            .destination()
+            .is_some_and(|destination| zones.contains(&destination))
+        {
+            Ok(square.move_credits)
+        } else {
+            Ok(cost)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unit::Commander;
+
+    const MAP: InitialState = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+
+    /// Une seule unité sur la carte.
+    fn alone(unit: Unit, position: Position) -> CircuitState {
+        let mut squares = vec![Unit::None.default_square(); 100];
+        squares[MAP.index(position)] = unit.default_square();
+        CircuitState {
+            squares,
+            gold_amount: 0,
+            captured_village_count: 0,
+            current_upkeep_costs: 0,
+        }
+    }
+
+    /// Coûts minimaux par relaxations successives, sans ennemis.
+    fn bellman_ford(origin: Position, movement_type: MovementType) -> HashMap<Position, u64> {
+        let grid = MAP.grid();
+        let mut costs = HashMap::from([(origin, 0)]);
+        loop {
+            let mut changed = false;
+            for index in 0..grid.len() {
+                let position = grid.position(index);
+                let Some(&cost) = costs.get(&position) else {
+                    continue;
+                };
+                for next in grid.neighbours(position) {
+                    let next_cost = cost + movement_type.cost(MAP.terrain(next));
+                    if costs.get(&next).is_none_or(|&known| next_cost < known) {
+                        costs.insert(next, next_cost);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                return costs;
+            }
+        }
+    }
+
+    #[test]
+    fn terrain_costs() {
+        // Forêt en (3, 3), eau en (4, 4) et (3, 5), village en (4, 5).
+        let grunt = reachable(&MAP, &alone(Unit::OrcishGrunt, (3, 4)), (3, 4), &[]);
+        assert_eq!(grunt.cost((3, 3)), Some(2));
+        assert_eq!(grunt.cost((4, 4)), Some(3));
+        assert_eq!(grunt.cost((4, 5)), Some(1));
+        assert_eq!(grunt.cost((3, 4)), None);
+
+        let naga = reachable(&MAP, &alone(Unit::NagaFighter, (3, 4)), (3, 4), &[]);
+        assert_eq!(naga.cost((3, 3)), Some(3));
+        assert_eq!(naga.cost((4, 4)), Some(1));
+        assert_eq!(naga.cost((3, 5)), Some(1));
+        assert_eq!(naga.cost((2, 4)), Some(2));
+    }
+
+    #[test]
+    fn dijkstra_finds_cheapest_costs() {
+        for unit in [
+            Unit::OrcishGrunt,
+            Unit::NagaFighter,
+            Unit::TrollWhelp,
+            Unit::WolfRider,
+        ] {
+            for origin in [(0, 0), (3, 4), (5, 5), (9, 2)] {
+                let state = alone(unit, origin);
+                let found = reachable(&MAP, &state, origin, &[]);
+                let expected = bellman_ford(origin, unit.movement_type());
+                for (position, cost) in expected {
+                    let expected = (position != origin && cost <= unit.movement()).then_some(cost);
+                    assert_eq!(
+                        found.cost(position),
+                        expected,
+                        "{:?} vers {:?}",
+                        unit,
+                        position
+                    );
+                    if let Some(path) = found.path_to(position) {
+                        assert_eq!(state.check_path(&path, &MAP, &[]), Ok(cost));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn own_units_are_crossed_but_not_entered() {
+        let mut state = alone(Unit::OrcishGrunt, (0, 3));
+        state.squares[MAP.index((0, 4))] = Unit::TrollWhelp.default_square();
+        let found = reachable(&MAP, &state, (0, 3), &[]);
+        assert_eq!(found.cost((0, 4)), None);
+        let path = found.path_to((0, 5)).unwrap();
+        assert_eq!(path.steps, vec![(0, 3), (0, 4), (0, 5)]);
+        assert_eq!(state.check_path(&path, &MAP, &[]), Ok(2));
+    }
+
+    #[test]
+    fn zones_of_control_stop_movement() {
+        // Zone de contrôle de l'ennemi en (2, 2) : (1, 1), (1, 2), (2, 1),
+        // (2, 3), (3, 1), (3, 2).
+        let enemies = [(2, 2)];
+        let state = alone(Unit::WolfRider, (0, 2));
+        let found = reachable(&MAP, &state, (0, 2), &enemies);
+        assert_eq!(found.cost((2, 2)), None);
+        assert_eq!(found.cost((1, 2)), Some(1));
+        for index in 0..100 {
+            let position = MAP.grid().position(index);
+            if let Some(path) = found.path_to(position) {
+                let spent = state.check_path(&path, &MAP, &enemies).unwrap();
+                // Entrer dans la zone de contrôle consomme tout le mouvement.
+                if distance(position, (2, 2)) == 1 {
+                    assert_eq!(spent, Unit::WolfRider.movement());
+                } else {
+                    assert_eq!(Some(spent), found.cost(position));
+                }
+            }
+        }
+        // On ne traverse pas (1, 2), il faut passer par (0, 3).
+        let through = Path {
+            steps: vec![(0, 2), (1, 2), (1, 3), (2, 4)],
+        };
+        assert_eq!(
+            state.check_path(&through, &MAP, &enemies),
+            Err(RuleViolation::ZoneOfControl((1, 2)))
+        );
+        assert_eq!(
+            found.path_to((2, 4)).unwrap().steps,
+            vec![(0, 2), (0, 3), (1, 3), (2, 4)]
+        );
+    }
+
+    #[test]
+    fn check_path_rejects_bad_paths() {
+        let state = alone(Unit::OrcishGrunt, (3, 4));
+        let path = |steps: &[Position]| Path {
+            steps: steps.to_vec(),
+        };
+        assert_eq!(
+            state.check_path(&path(&[]), &MAP, &[]),
+            Err(RuleViolation::EmptyPath)
+        );
+        assert_eq!(
+            state.check_path(&path(&[(3, 4), (3, 6)]), &MAP, &[]),
+            Err(RuleViolation::NotAdjacent((3, 4), (3, 6)))
+        );
+        assert_eq!(
+            state.check_path(&path(&[(3, 4), (2, 5), (2, 6)]), &MAP, &[(2, 5)]),
+            Err(RuleViolation::BlockedByEnemy((2, 5)))
+        );
+        assert_eq!(
+            state.check_path(&path(&[(3, 4), (2, 4), (1, 4)]), &MAP, &[(2, 5)]),
+            Err(RuleViolation::ZoneOfControl((2, 4)))
+        );
+        // Eau puis marais : 3 + 3.
+        assert_eq!(
+            state.check_path(&path(&[(3, 4), (4, 4), (5, 3)]), &MAP, &[]),
+            Err(RuleViolation::TooExpensive {
+                cost: 6,
+                move_credits: 5
+            })
+        );
+        assert_eq!(
+            state.check_path(&path(&[(3, 4), (3, 3), (4, 4)]), &MAP, &[]),
+            Ok(5)
+        );
+    }
+}
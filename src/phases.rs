@@ -0,0 +1,819 @@
+//! Phases de l'échange, exposées par une API typée qui impose l'ordre d'un tour.
+//!
+//! Au tour d'un joueur, l'adversaire lance la PSI avec la phase 1, le joueur
+//! applique ses actions et y répond avec la phase 2, puis l'adversaire obtient sa
+//! vision avec la phase 3. Chaque message porte le côté qui l'a produit et n'est
+//! pas clonable : il ne peut être consommé qu'une fois, et seulement par le bon
+//! joueur.
+
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::process::Command;
+use std::time::Instant;
+
+use nova_scotia::circom::circuit::CircomCircuit;
+use nova_scotia::circom::reader::load_witness_from_bin_file;
+use num_bigint::BigUint;
+use serde::ser::{self, SerializeMap};
+use serde::{Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use crate::combat::{AnnouncedAttack, Exchange};
+use crate::convert::{fr_to_decimal, to_bits};
+use crate::journal::{decimal_data, decimal_points, Message};
+use crate::metrics::FIELD_BYTES;
+use crate::output::{Phase1Output, Phase2Output, Phase3Output};
+use crate::randomness::{
+    commitment, commitment_limbs, verify_opening, Commitment, Nonce, RandomnessError, SharedSeed,
+};
+use crate::scalar::{exponent_bits, Scalar};
+use crate::secret::{SecretCircuit, SecretFile};
+use crate::vision::{reveal_enemies, VisibleEnemy};
+use crate::{HiddenData, Point, State, Transaction, MAX_ACTION_COUNT};
+
+/// Côté d'un joueur dans la partie.
+pub trait Side {
+    type Opponent: Side<Opponent = Self>;
+
+    const NAME: &'static str;
+    /// Rang du côté, qui fixe l'ordre des deux joueurs dans les hashs.
+    const INDEX: u8;
+}
+
+/// Le joueur qui commence la partie.
+pub enum SideA {}
+
+/// Le joueur qui joue en second.
+pub enum SideB {}
+
+impl Side for SideA {
+    type Opponent = SideB;
+
+    const NAME: &'static str = "Ashley";
+    const INDEX: u8 = 0;
+}
+
+impl Side for SideB {
+    type Opponent = SideA;
+
+    const NAME: &'static str = "Brandon";
+    const INDEX: u8 = 1;
+}
+
+/// Un joueur engagé, attaché à son côté de la partie.
+pub struct Player<S: Side> {
+    state: State,
+    side: PhantomData<S>,
+}
+
+/// Points de la phase 1 envoyés à l'adversaire, à consommer par sa phase 2.
+#[must_use = "la phase 1 doit être envoyée à la phase 2 de l'adversaire"]
+pub struct Phase1Message<S: Side> {
+    diffie_hellman: Vec<Point>,
+    side: PhantomData<S>,
+}
+
+/// Ce que garde le joueur après sa phase 1 pour terminer la PSI en phase 3.
+/// Les exposants ne quittent jamais le joueur qui les a tirés.
+#[must_use = "les secrets de la phase 1 doivent être consommés par la phase 3"]
+pub struct Phase1Secret<S: Side> {
+    exponents: Vec<Scalar>,
+    hashed_idents: Vec<Point>,
+    side: PhantomData<S>,
+}
+
+/// Réponse de la phase 2, à consommer par la phase 3 de l'adversaire.
+#[must_use = "la phase 2 doit être envoyée à la phase 3 de l'adversaire"]
+pub struct Phase2Message<S: Side> {
+    dh_output: Vec<Point>,
+    hidden_tags: Vec<Point>,
+    hidden_data: Vec<HiddenData>,
+    /// Points de phase 1 que le circuit a accumulés dans notre chaîne.
+    phase1_output: Vec<Point>,
+    /// Dégâts infligés pendant le tour, case par case chez l'adversaire : ils
+    /// entrent dans son entrée publique `degats`.
+    damages: Vec<u64>,
+    /// Ripostes reçues pendant le tour, case par case chez nous, qui ouvrent
+    /// notre prochaine entrée `degats`.
+    received: Vec<u64>,
+    /// Villages capturés pendant le tour, par numéro : ils entrent dans
+    /// l'entrée publique `captures` de l'adversaire.
+    captures: Vec<u64>,
+    /// Attaques du tour dans l'ordre, que l'adversaire rejoue pour vérifier
+    /// `damages` et `received`.
+    attacks: Vec<AnnouncedAttack>,
+    side: PhantomData<S>,
+}
+
+/// Nonce tiré pour l'aléa d'un tour, à garder jusqu'à avoir reçu l'engagement
+/// de l'adversaire.
+#[must_use = "le nonce doit être révélé une fois l'engagement adverse reçu"]
+pub struct RollSecret<S: Side> {
+    turn: u64,
+    nonce: Nonce,
+    side: PhantomData<S>,
+}
+
+/// Engagement sur un nonce, à envoyer à l'adversaire avant toute révélation.
+#[must_use = "l'engagement doit être envoyé à l'adversaire"]
+pub struct RollCommitment<S: Side> {
+    commitment: Commitment,
+    side: PhantomData<S>,
+}
+
+/// Nonce révélé, que l'adversaire vérifie contre notre engagement.
+#[must_use = "le nonce révélé doit être envoyé à l'adversaire"]
+pub struct RollReveal<S: Side> {
+    turn: u64,
+    nonce: Nonce,
+    side: PhantomData<S>,
+}
+
+/// Tirage en attente de la révélation adverse.
+#[must_use = "le tirage doit être terminé avec la révélation adverse"]
+pub struct PendingRoll<S: Side> {
+    turn: u64,
+    nonce: Nonce,
+    opponent_commitment: Commitment,
+    side: PhantomData<S>,
+}
+
+impl<S: Side> Player<S> {
+    pub fn new(state: State) -> Player<S> {
+        Player {
+            state,
+            side: PhantomData,
+        }
+    }
+
+    /// S'engage sur un nonce pour l'aléa du tour `turn`.
+    pub fn commit_roll(&mut self, turn: u64) -> (RollSecret<S>, RollCommitment<S>) {
+        let nonce = self.state.secrets.nonce();
+        let commitment = commitment(&self.state.game_id, turn, S::INDEX, &nonce);
+        self.state
+            .metrics
+            .message("roll_commitment", commitment.len());
+        (
+            RollSecret {
+                turn,
+                nonce,
+                side: PhantomData,
+            },
+            RollCommitment {
+                commitment,
+                side: PhantomData,
+            },
+        )
+    }
+
+    /// Révèle notre nonce, ce qui n'est possible qu'après avoir reçu
+    /// l'engagement de l'adversaire.
+    pub fn reveal_roll(
+        &mut self,
+        secret: RollSecret<S>,
+        opponent: RollCommitment<S::Opponent>,
+    ) -> (PendingRoll<S>, RollReveal<S>) {
+        self.state.metrics.message(
+            "roll_reveal",
+            std::mem::size_of::<u64>() + secret.nonce.len(),
+        );
+        (
+            PendingRoll {
+                turn: secret.turn,
+                nonce: secret.nonce,
+                opponent_commitment: opponent.commitment,
+                side: PhantomData,
+            },
+            RollReveal {
+                turn: secret.turn,
+                nonce: secret.nonce,
+                side: PhantomData,
+            },
+        )
+    }
+
+    /// Vérifie le nonce révélé par l'adversaire et fixe la graine des combats
+    /// du tour.
+    pub fn agree_roll(
+        &mut self,
+        pending: PendingRoll<S>,
+        opponent: RollReveal<S::Opponent>,
+    ) -> Result<(), RandomnessError> {
+        let game_id = self.state.game_id;
+        let opponent_index = <S::Opponent as Side>::INDEX;
+        verify_opening(
+            &game_id,
+            pending.turn,
+            opponent.turn,
+            opponent_index,
+            &opponent.nonce,
+            &pending.opponent_commitment,
+        )?;
+
+        let own_commitment = commitment(&game_id, pending.turn, S::INDEX, &pending.nonce);
+        let (nonces, commitments) = if S::INDEX < opponent_index {
+            (
+                [&pending.nonce, &opponent.nonce],
+                [own_commitment, pending.opponent_commitment],
+            )
+        } else {
+            (
+                [&opponent.nonce, &pending.nonce],
+                [pending.opponent_commitment, own_commitment],
+            )
+        };
+        self.state.roll_commitments = commitments;
+        self.state.roll_seed = Some(SharedSeed::derive(&game_id, pending.turn, nonces));
+        Ok(())
+    }
+
+    /// Lance la PSI au début du tour de l'adversaire.
+    pub fn phase1(&mut self) -> (Phase1Secret<S>, Phase1Message<S>) {
+        let (exponents, hashed_idents, diffie_hellman) = self.state.phase1();
+        self.state
+            .metrics
+            .message("phase1", diffie_hellman.len() * 2 * FIELD_BYTES);
+        (
+            Phase1Secret {
+                exponents,
+                hashed_idents,
+                side: PhantomData,
+            },
+            Phase1Message {
+                diffie_hellman,
+                side: PhantomData,
+            },
+        )
+    }
+
+    /// Applique les actions du tour et répond à la phase 1 de l'adversaire.
+    pub fn phase2(&mut self, message: Phase1Message<S::Opponent>) -> Phase2Message<S> {
+        let (output, exchange, attacks, captures) = self.state.phase2(message.diffie_hellman);
+        let Exchange {
+            inflicted: damages,
+            received,
+        } = exchange;
+        let Phase2Output {
+            dh_output,
+            hidden_tags,
+            hidden_data,
+            phase1_output,
+        } = output;
+        self.state.metrics.message(
+            "phase2",
+            (dh_output.len() + hidden_tags.len() + phase1_output.len()) * 2 * FIELD_BYTES
+                + hidden_data.len() * 3 * FIELD_BYTES
+                + (damages.len() + received.len() + captures.len() + attacks.len() * 5)
+                    * std::mem::size_of::<u64>(),
+        );
+        Phase2Message {
+            dh_output,
+            hidden_tags,
+            hidden_data,
+            phase1_output,
+            damages,
+            received,
+            captures,
+            attacks,
+            side: PhantomData,
+        }
+    }
+
+    /// Termine la PSI avec la réponse de l'adversaire à notre phase 1, rejoue
+    /// ses attaques, et note les dégâts qu'il nous a infligés et les villages
+    /// qu'il a pris pour le début de notre tour. Renvoie les unités adverses que
+    /// nous voyons.
+    pub fn phase3(
+        &mut self,
+        secret: Phase1Secret<S>,
+        response: Phase2Message<S::Opponent>,
+    ) -> Vec<VisibleEnemy> {
+        let revealed = self.state.phase3(
+            secret.exponents,
+            secret.hashed_idents,
+            response.dh_output,
+            response.hidden_tags,
+            response.hidden_data,
+        );
+
+        // Les dégâts de l'adversaire ne sont pas encore appliqués : nos unités
+        // sont telles qu'il les a attaquées. Les siennes sont révélées après ses
+        // actions, sans les ripostes qui ne l'atteindront qu'à son prochain tour.
+        let reported = Exchange {
+            inflicted: response.damages,
+            received: response.received,
+        };
+        let replayed = match &self.state.roll_seed {
+            Some(seed) => Exchange::replay(
+                &response.attacks,
+                &self.state.damaged_state().squares,
+                &revealed,
+                &self.state.initial_state,
+                seed,
+            ),
+            None if response.attacks.is_empty() => {
+                Ok(Exchange::none(self.state.circuit_state.squares.len()))
+            }
+            None => panic!("L'aléa du tour n'a pas été tiré avant la phase 3"),
+        };
+        match replayed {
+            Ok(replayed) if replayed == reported => {}
+            Ok(_) => {
+                println!("Les dégâts annoncés ne sont pas ceux des attaques rejouées.");
+                self.state.suspicious = true;
+            }
+            Err(violation) => {
+                println!("Attaque adverse impossible : {}.", violation);
+                self.state.suspicious = true;
+            }
+        }
+
+        let received_damage = &mut self.state.unencrypted_state.own_received_damage;
+        for (received, damage) in received_damage.iter_mut().zip(&reported.inflicted) {
+            *received += damage;
+        }
+        let lost_villages = &mut self.state.unencrypted_state.adversary_captures;
+        for (lost, &capture) in lost_villages.iter_mut().zip(&response.captures) {
+            if capture != 0 {
+                *lost = 1;
+            }
+        }
+        // Nos prochaines attaques visent les unités adverses après leurs ripostes.
+        let map = self.state.initial_state;
+        let visible_enemies = revealed
+            .into_iter()
+            .filter_map(|enemy| {
+                let received = reported.received.get(map.index(enemy.position))?;
+                let health_points = enemy.health_points.checked_sub(*received)?;
+                (health_points > 0).then_some(VisibleEnemy {
+                    health_points,
+                    ..enemy
+                })
+            })
+            .collect::<Vec<_>>();
+        self.state.visible_enemies = visible_enemies.clone();
+        visible_enemies
+    }
+}
+
+impl<S: Side> From<&RollCommitment<S>> for Message {
+    fn from(commitment: &RollCommitment<S>) -> Message {
+        Message::RollCommitment(commitment.commitment)
+    }
+}
+
+impl<S: Side> From<&RollReveal<S>> for Message {
+    fn from(reveal: &RollReveal<S>) -> Message {
+        Message::RollReveal {
+            turn: reveal.turn,
+            nonce: reveal.nonce,
+        }
+    }
+}
+
+impl<S: Side> From<&Phase1Message<S>> for Message {
+    fn from(message: &Phase1Message<S>) -> Message {
+        Message::Phase1 {
+            diffie_hellman: decimal_points(&message.diffie_hellman),
+        }
+    }
+}
+
+impl<S: Side> From<&Phase2Message<S>> for Message {
+    fn from(message: &Phase2Message<S>) -> Message {
+        Message::Phase2 {
+            dh_output: decimal_points(&message.dh_output),
+            hidden_tags: decimal_points(&message.hidden_tags),
+            hidden_data: decimal_data(&message.hidden_data),
+            phase1_output: decimal_points(&message.phase1_output),
+            damages: message.damages.clone(),
+            received: message.received.clone(),
+            captures: message.captures.clone(),
+            attacks: message.attacks.clone(),
+        }
+    }
+}
+
+impl<S: Side> Deref for Player<S> {
+    type Target = State;
+
+    fn deref(&self) -> &State {
+        &self.state
+    }
+}
+
+impl<S: Side> DerefMut for Player<S> {
+    fn deref_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+}
+
+struct Phase1<'a> {
+    previous_state: &'a State,
+    received_damage: &'a [u64],
+    captures: Vec<u64>,
+    exponents: Zeroizing<Vec<Vec<u8>>>,
+}
+
+impl<'a> Serialize for Phase1<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut serializer = serializer.serialize_map(Some(5))?;
+        let previous_circuit_state = &self.previous_state.circuit_state;
+        serializer.serialize_entry("prev_state", &previous_circuit_state.squares)?;
+        serializer.serialize_entry(
+            "prev_misc_state",
+            &[
+                previous_circuit_state.gold_amount,
+                previous_circuit_state.captured_village_count,
+                previous_circuit_state.current_upkeep_costs,
+            ],
+        )?;
+        serializer.serialize_entry("degats", self.received_damage)?;
+        serializer.serialize_entry("captures", &self.captures)?;
+        serializer.serialize_entry("phase1_exponents", &*self.exponents)?;
+        serializer.end()
+    }
+}
+
+struct Phase2<'a> {
+    state: &'a State,
+    /// Actions du tour, attaques comprises.
+    transactions: &'a [Transaction],
+    /// Villages capturés par ces actions, annoncés à l'adversaire.
+    actions_captures: &'a [u64],
+    exponent: Scalar,
+    own_exponents: Vec<Scalar>,
+    received_data: Vec<(BigUint, BigUint)>,
+}
+
+impl<'a> Serialize for Phase2<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut serializer = serializer.serialize_map(Some(5))?;
+        let previous_circuit_state = &self.state.circuit_state;
+        serializer.serialize_entry(
+            "step_in",
+            &[
+                self.state.hash().to_string(),
+                fr_to_decimal(&self.state.roll_hash),
+            ],
+        )?;
+        serializer.serialize_entry("prev_state", &previous_circuit_state.squares)?;
+        serializer.serialize_entry(
+            "prev_misc_state",
+            &[
+                previous_circuit_state.gold_amount,
+                previous_circuit_state.captured_village_count,
+                previous_circuit_state.current_upkeep_costs,
+            ],
+        )?;
+
+        let mut actions = self
+            .transactions
+            .iter()
+            .filter_map(|transaction| transaction.to_action())
+            .collect::<Vec<_>>();
+        assert!(
+            actions.len() <= MAX_ACTION_COUNT,
+            "Trop d'actions en un seul tour !!!!"
+        );
+        let no_action = Transaction::None.to_action().unwrap();
+        actions.resize(MAX_ACTION_COUNT, no_action);
+        serializer.serialize_entry("actions", &actions)?;
+        serializer.serialize_entry("degats", &self.state.unencrypted_state.own_received_damage)?;
+        serializer.serialize_entry("captures", &self.state.unencrypted_state.adversary_captures)?;
+        serializer.serialize_entry("actions_captures", self.actions_captures)?;
+        let roll_commitments = self
+            .state
+            .roll_commitments
+            .iter()
+            .flat_map(commitment_limbs)
+            .map(|limb| limb.to_string())
+            .collect::<Vec<_>>();
+        serializer.serialize_entry("roll_commitments", &roll_commitments)?;
+        serializer.serialize_entry("phase1_exponents", &*exponent_bits(&self.own_exponents))?;
+        serializer.serialize_entry("phase2_exponent", &*self.exponent.to_bits())?;
+        serializer.serialize_entry(
+            "phase1_received",
+            &self
+                .received_data
+                .iter()
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+                .collect::<Vec<_>>(),
+        )?;
+        serializer.end()
+    }
+}
+
+struct Phase3 {
+    hashed_idents: Vec<(BigUint, BigUint)>,
+    exponents_a: Vec<Scalar>,
+    dh_output: Vec<(BigUint, BigUint)>,
+    hidden_tags: Vec<(BigUint, BigUint)>,
+    hidden_data: Vec<HiddenData>,
+}
+
+impl Serialize for Phase3 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut serializer = serializer.serialize_map(Some(5))?;
+        serializer.serialize_entry(
+            "hashed_idents",
+            &self
+                .hashed_idents
+                .iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>(),
+        )?;
+        serializer.serialize_entry("inv_phase1_exponents", &*exponent_bits(&self.exponents_a))?;
+        serializer.serialize_entry(
+            "phase2_dh_output",
+            &self
+                .dh_output
+                .iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>(),
+        )?;
+        serializer.serialize_entry(
+            "phase2_hidden_tags",
+            &self
+                .hidden_tags
+                .iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>(),
+        )?;
+
+        // Les données masquées sont des XOR sur 64 bits.
+        let to_bits = |value: &BigUint| to_bits(value, 64).map_err(ser::Error::custom);
+        let hidden_data = self
+            .hidden_data
+            .iter()
+            .map(|(a, b, c)| Ok((to_bits(a)?, to_bits(b)?, to_bits(c)?)))
+            .collect::<Result<Vec<_>, S::Error>>()?;
+        serializer.serialize_entry("phase2_hidden_data", &hidden_data)?;
+        serializer.end()
+    }
+}
+
+impl State {
+    fn phase1(&mut self) -> (Vec<Scalar>, Vec<Point>, Vec<Point>) {
+        let begin = Instant::now();
+        let map_size = self.circuit_state.squares.len();
+        let exponents = self.secrets.exponents(map_size);
+        let phase1 = Phase1 {
+            previous_state: self,
+            received_damage: &self.unencrypted_state.own_received_damage,
+            captures: self.unencrypted_state.adversary_captures.clone(),
+            exponents: exponent_bits(&exponents),
+        };
+        let phase1_input = Zeroizing::new(
+            serde_json::to_string(&phase1).expect("Échec de l'initialisation de la phase 1 !"),
+        );
+
+        let phase1_input_file = SecretFile::with_contents(phase1_input.as_bytes())
+            .expect("Impossible d'écrire dans le fichier les entrées de la phase 1");
+        let phase1_witness = SecretFile::new().expect("Impossible de créer un fichier temporaire");
+        self.metrics.time("phase1.serialize", begin);
+
+        // let circuit1 = self.initial_state.circuit_path().join("phase1/circuit");
+        let circuit1 = self
+            .initial_state
+            .circuit_path()
+            .join("phase1/circuit_cpp/circuit");
+
+        let begin = Instant::now();
+        let phase1_cmd = Command::new(circuit1)
+            .arg(phase1_input_file.path())
+            .arg(phase1_witness.path())
+            .output()
+            .expect("Le circuit de la phase 1 a échoué !");
+        assert!(phase1_cmd.status.success(), "{:?}", phase1_cmd);
+        let phase1_out: String =
+            String::from_utf8(phase1_cmd.stdout).expect("La phase 1 a donné du non-UTF-8 ??");
+        self.metrics.time("phase1.witness", begin);
+
+        let begin = Instant::now();
+
+        let output = Phase1Output::parse(&phase1_out, map_size)
+            .unwrap_or_else(|error| panic!("Sortie de la phase 1 invalide : {}", error));
+        assert_eq!(
+            output.vision,
+            self.visible_squares(),
+            "Le circuit de la phase 1 ne voit pas les mêmes cases que nous"
+        );
+        self.metrics.time("phase1.parse", begin);
+        (exponents, output.hashed_idents, output.diffie_hellman)
+    }
+
+    fn phase2(
+        &mut self,
+        diffie_hellmann_phase_1: Vec<Point>,
+    ) -> (Phase2Output, Exchange, Vec<AnnouncedAttack>, Vec<u64>) {
+        let begin = Instant::now();
+        let exponent = self.secrets.exponent();
+        let (width, height) = self.initial_state.size();
+        let map_size = width * height;
+        let own_exponents = self.secrets.exponents(map_size as usize);
+        let transactions = self
+            .plan
+            .take()
+            .map(|plan| plan.transactions().to_vec())
+            .unwrap_or_default();
+        let mut actions_captures = vec![0; self.initial_state.village_count() as usize];
+        for transaction in &transactions {
+            if let Transaction::CaptureVillage(village_id) = transaction {
+                actions_captures[*village_id as usize] = 1;
+            }
+        }
+
+        let phase2 = Phase2 {
+            state: self,
+            transactions: &transactions,
+            actions_captures: &actions_captures,
+            exponent,
+            own_exponents,
+            received_data: diffie_hellmann_phase_1,
+        };
+        let phase2_input = Zeroizing::new(
+            serde_json::to_string(&phase2).expect("Échec de l'initialisation de la phase 2 !"),
+        );
+
+        let phase2_input_file = SecretFile::with_contents(phase2_input.as_bytes())
+            .expect("Impossible d'écrire dans le fichier les entrées de la phase 2");
+        let phase2_witness = SecretFile::new().expect("Impossible de créer un fichier temporaire");
+        self.metrics.time("phase2.serialize", begin);
+
+        // let circuit2 = self.initial_state.circuit_path().join("phase2nova/circuit");
+        let circuit2 = self
+            .initial_state
+            .circuit_path()
+            .join("phase2nova/circuit_cpp/circuit");
+
+        let begin = Instant::now();
+        let phase2_cmd = Command::new(circuit2)
+            .arg(phase2_input_file.path())
+            .arg(phase2_witness.path())
+            .output()
+            .expect("Le circuit de la phase 2 a échoué !");
+        assert!(phase2_cmd.status.success(), "{:?}", phase2_cmd);
+        let phase2_out: String =
+            String::from_utf8(phase2_cmd.stdout).expect("La phase 2 a donné du non-UTF-8 ??");
+        self.metrics.time("phase2.witness", begin);
+
+        let begin = Instant::now();
+
+        let output = Phase2Output::parse(&phase2_out, map_size as usize)
+            .unwrap_or_else(|error| panic!("Sortie de la phase 2 invalide : {}", error));
+        self.metrics.time("phase2.parse", begin);
+
+        let begin = Instant::now();
+        let witness = load_witness_from_bin_file(phase2_witness.path());
+        self.metrics.witness("phase2", witness.len());
+        // Le témoin commence par la constante 1 puis par `step_out` : la chaîne
+        // sortante est l'entrée de la prochaine étape.
+        self.roll_hash = witness[2];
+        let circuit = CircomCircuit {
+            r1cs: self.r1cs.clone(),
+            witness: Some(witness),
+        };
+        self.metrics.time("phase2.load_witness", begin);
+
+        // Le repliement se fait en arrière-plan, la réponse n'en dépend pas. Le
+        // témoin est effacé une fois l'étape repliée.
+        self.prover
+            .push_step(self.metrics.turn(), SecretCircuit(circuit));
+        self.proven_steps += 1;
+
+        // Le circuit a appliqué le début de tour puis les actions à l'état
+        // précédent, on fait de même.
+        let received_damage = std::mem::replace(
+            &mut self.unencrypted_state.own_received_damage,
+            vec![0; map_size as usize],
+        );
+        let captures = std::mem::replace(
+            &mut self.unencrypted_state.adversary_captures,
+            vec![0; self.initial_state.village_count() as usize],
+        );
+        self.circuit_state
+            .start_turn(&received_damage, &captures, &self.initial_state);
+
+        let attacks = transactions
+            .iter()
+            .filter_map(|transaction| match transaction {
+                Transaction::Attack(attack) => Some(*attack),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for transaction in transactions {
+            self.circuit_state
+                .apply(transaction, &self.initial_state)
+                .expect("Les actions ont été vérifiées à leur ajout");
+        }
+
+        // Un attaquant ne bouge plus une fois son attaque prévue : les combats
+        // se jouent sur l'état après les actions, comme chez l'adversaire qui
+        // les rejoue avec ce qu'il en voit. Les ripostes ne seront appliquées
+        // qu'au début de notre prochain tour.
+        let seed = self.roll_seed.take();
+        let exchange = match &seed {
+            Some(seed) => Exchange::resolve(
+                &attacks,
+                &self.circuit_state.squares,
+                &self.initial_state,
+                seed,
+            )
+            .expect("Les attaques ont été vérifiées à leur ajout"),
+            None if attacks.is_empty() => Exchange::none(map_size as usize),
+            None => panic!("L'aléa du tour n'a pas été tiré avant d'attaquer"),
+        };
+        for (received, retaliation) in self
+            .unencrypted_state
+            .own_received_damage
+            .iter_mut()
+            .zip(&exchange.received)
+        {
+            *received += retaliation;
+        }
+
+        let announced = attacks.iter().map(AnnouncedAttack::from).collect();
+        (output, exchange, announced, actions_captures)
+    }
+
+    fn phase3(
+        &mut self,
+        exponents_a: Vec<Scalar>,
+        part3_stuff: Vec<(BigUint, BigUint)>,
+        dh_output: Vec<(BigUint, BigUint)>,
+        hidden_tags: Vec<(BigUint, BigUint)>,
+        hidden_data: Vec<HiddenData>,
+    ) -> Vec<VisibleEnemy> {
+        let map_size = hidden_tags.len();
+        let received_tags = hidden_tags.clone();
+        let inv_a = exponents_a.iter().map(Scalar::inverse).collect::<Vec<_>>();
+
+        let begin = Instant::now();
+        let phase3_input = Zeroizing::new(
+            serde_json::to_string(&Phase3 {
+                hashed_idents: part3_stuff,
+                exponents_a: inv_a,
+                dh_output,
+                hidden_tags,
+                hidden_data,
+            })
+            .expect("Impossible de convertir en JSON."),
+        );
+
+        let phase3_input_file = SecretFile::with_contents(phase3_input.as_bytes())
+            .expect("Impossible d'écrire dans le fichier les entrées de la phase 3");
+        let phase3_witness = SecretFile::new().expect("Impossible de créer un fichier temporaire");
+        self.metrics.time("phase3.serialize", begin);
+
+        // let circuit3 = self.initial_state.circuit_path().join("phase3/circuit");
+        let circuit3 = self
+            .initial_state
+            .circuit_path()
+            .join("phase3/circuit_cpp/circuit");
+
+        let begin = Instant::now();
+        let phase3_cmd = Command::new(circuit3)
+            .arg(phase3_input_file.path())
+            .arg(phase3_witness.path())
+            .output()
+            .expect("Le circuit de la phase 3 a échoué !");
+        assert!(phase3_cmd.status.success(), "{:?}", phase3_cmd);
+        let phase3_out: String =
+            String::from_utf8(phase3_cmd.stdout).expect("La phase 3 a donné du non-UTF-8 ??");
+        self.metrics.time("phase3.witness", begin);
+
+        let begin = Instant::now();
+
+        let output = Phase3Output::parse(&phase3_out, map_size)
+            .unwrap_or_else(|error| panic!("Sortie de la phase 3 invalide : {}", error));
+
+        // Les données sont masquées case par case : un candidat n'est bon que
+        // si notre tag recalculé est celui reçu pour la même case.
+        let grid = self.initial_state.grid();
+        let revealed = output
+            .own_tags
+            .into_iter()
+            .zip(output.candidates)
+            .zip(received_tags)
+            .enumerate()
+            .filter(|(_, ((own_tag, _), received_tag))| own_tag == received_tag)
+            .map(|(index, ((_, data), _))| (index, data));
+        let (visible_enemies, forged) = reveal_enemies(&grid, revealed);
+        if !forged.is_empty() {
+            println!("Données révélées impossibles sur les cases {:?}.", forged);
+            self.suspicious = true;
+        }
+        self.metrics.time("phase3.parse", begin);
+        visible_enemies
+    }
+}
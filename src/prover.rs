@@ -0,0 +1,419 @@
+//! Repliement des étapes Nova en arrière-plan.
+//!
+//! La réponse de la phase 2 ne dépend que du témoin : elle part dès qu'il est
+//! calculé, et le `prove_step` est confié au fil du joueur, qui replie les
+//! étapes dans l'ordre pendant que l'adversaire choisit ses actions. Une
+//! demande de preuve passe par la même file et n'attend donc que les étapes
+//! déjà envoyées.
+//!
+//! Une étape refusée par Nova rend la preuve récursive inutilisable : les
+//! étapes suivantes sont ignorées, et toute demande de preuve renvoie l'erreur.
+//!
+//! Les clés de la preuve compressée ne dépendent que des paramètres publics :
+//! le fil les génère à la première demande et s'en sert aussi pour vérifier les
+//! preuves de l'adversaire, sans jamais utiliser de clé venue de celui-ci.
+
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use halo2curves::bn256::Fr;
+use halo2curves::ff::Field;
+use nova_scotia::{C1, C2, S};
+use nova_snark::errors::NovaError;
+use nova_snark::provider::bn256_grumpkin::{bn256, grumpkin};
+use nova_snark::traits::circuit::TrivialTestCircuit;
+use nova_snark::{CompressedSNARK, ProverKey};
+
+use crate::secret::SecretCircuit;
+use crate::{CompressedProof, ProofVerifierKey, PublicParameters, Snark};
+
+/// Ce que le fil de preuve sait faire : replier, compresser et vérifier.
+/// Le fil s'occupe de l'ordre des travaux et des étapes refusées.
+pub trait Backend: Send + 'static {
+    type Circuit: Send + 'static;
+    type Proof: Send + 'static;
+
+    fn prove_step(&mut self, circuit: &Self::Circuit) -> Result<(), NovaError>;
+    fn compress(&mut self) -> Result<Compressed<Self::Proof>, ProofError>;
+    /// Sortie `z_n` de la preuve des `steps` premières étapes depuis `z0`.
+    fn verify(
+        &mut self,
+        proof: &Self::Proof,
+        steps: usize,
+        z0: Vec<Fr>,
+    ) -> Result<Vec<Fr>, ProofError>;
+}
+
+enum Job<B: Backend> {
+    /// Étape de la phase 2 du tour donné, témoin compris.
+    Step(u64, B::Circuit),
+    Compress(Sender<Result<Compressed<B::Proof>, ProofError>>),
+    /// Preuve adverse des `steps` premières étapes depuis `z0`, rendue avec la
+    /// sortie `z_n` qu'elle établit.
+    Verify {
+        proof: Box<B::Proof>,
+        steps: usize,
+        z0: Vec<Fr>,
+        reply: Sender<Verified<B::Proof>>,
+    },
+}
+
+type Verified<P = CompressedProof> = (Box<P>, Result<Vec<Fr>, ProofError>);
+
+type Keys = (
+    ProverKey<
+        bn256::Point,
+        grumpkin::Point,
+        C1<bn256::Point>,
+        C2<grumpkin::Point>,
+        S<bn256::Point>,
+        S<grumpkin::Point>,
+    >,
+    ProofVerifierKey,
+);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// Le repliement de l'étape du tour donné a échoué.
+    Step {
+        turn: u64,
+        error: NovaError,
+    },
+    Setup(NovaError),
+    Compress(NovaError),
+    /// La preuve vérifiée est refusée.
+    Rejected(NovaError),
+    /// Le fil de preuve s'est arrêté avant de répondre.
+    Stopped,
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofError::Step { turn, error } => {
+                write!(
+                    f,
+                    "l'étape du tour {} n'a pas pu être repliée ({})",
+                    turn, error
+                )
+            }
+            ProofError::Setup(error) => {
+                write!(
+                    f,
+                    "clés de la preuve compressée impossibles à générer ({})",
+                    error
+                )
+            }
+            ProofError::Compress(error) => write!(f, "compression refusée ({})", error),
+            ProofError::Rejected(error) => write!(f, "preuve refusée ({})", error),
+            ProofError::Stopped => write!(f, "le fil de preuve s'est arrêté"),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// Preuve compressée de toutes les étapes repliées avant la demande.
+pub struct Compressed<P = CompressedProof> {
+    pub proof: P,
+    /// Génération des clés, si cette preuve a été la première à en avoir besoin.
+    pub setup: Option<Duration>,
+    pub compress: Duration,
+}
+
+/// Preuve récursive d'un joueur avec ses paramètres publics. Les clés de la
+/// preuve compressée sont générées à la première demande.
+pub struct Nova {
+    public_params: PublicParameters,
+    snark: Snark,
+    initial_hash: Vec<Fr>,
+    keys: Option<Keys>,
+}
+
+impl Backend for Nova {
+    type Circuit = SecretCircuit;
+    type Proof = CompressedProof;
+
+    fn prove_step(&mut self, SecretCircuit(circuit): &SecretCircuit) -> Result<(), NovaError> {
+        let circuit_secondary = TrivialTestCircuit::default();
+        let z0_secondary =
+            vec![<halo2curves::grumpkin::G1 as halo2curves::group::Group>::Scalar::ZERO];
+        self.snark.prove_step(
+            &self.public_params,
+            circuit,
+            &circuit_secondary,
+            self.initial_hash.clone(),
+            z0_secondary,
+        )
+    }
+
+    fn compress(&mut self) -> Result<Compressed, ProofError> {
+        compress(&self.public_params, &self.snark, &mut self.keys)
+    }
+
+    fn verify(
+        &mut self,
+        proof: &CompressedProof,
+        steps: usize,
+        z0: Vec<Fr>,
+    ) -> Result<Vec<Fr>, ProofError> {
+        cached_keys(&self.public_params, &mut self.keys)
+            .and_then(|(_, vk)| verify(proof, vk, steps, z0))
+    }
+}
+
+/// File de travail du fil de preuve d'un joueur, qui possède les paramètres
+/// publics et la preuve récursive.
+pub struct Prover<B: Backend = Nova> {
+    jobs: Sender<Job<B>>,
+    /// Durée de chaque `prove_step` terminé, avec son tour.
+    steps: Receiver<(u64, Duration)>,
+}
+
+impl Prover {
+    pub fn spawn(public_params: PublicParameters, snark: Snark, initial_hash: Vec<Fr>) -> Prover {
+        Prover::start(Nova {
+            public_params,
+            snark,
+            initial_hash,
+            keys: None,
+        })
+    }
+}
+
+impl<B: Backend> Prover<B> {
+    fn start(mut backend: B) -> Prover<B> {
+        let (jobs, queue) = channel();
+        let (step_sender, steps) = channel();
+        thread::spawn(move || {
+            let mut failed = None;
+            for job in queue {
+                match job {
+                    Job::Step(_, _) if failed.is_some() => {}
+                    Job::Step(turn, circuit) => {
+                        let begin = Instant::now();
+                        match backend.prove_step(&circuit) {
+                            // Personne n'écoute plus une fois le joueur parti.
+                            Ok(()) => {
+                                let _ = step_sender.send((turn, begin.elapsed()));
+                            }
+                            Err(error) => failed = Some(ProofError::Step { turn, error }),
+                        }
+                    }
+                    Job::Compress(reply) => {
+                        let compressed = match &failed {
+                            Some(error) => Err(error.clone()),
+                            None => backend.compress(),
+                        };
+                        let _ = reply.send(compressed);
+                    }
+                    Job::Verify {
+                        proof,
+                        steps,
+                        z0,
+                        reply,
+                    } => {
+                        let z_n = backend.verify(&proof, steps, z0);
+                        let _ = reply.send((proof, z_n));
+                    }
+                }
+            }
+        });
+        Prover { jobs, steps }
+    }
+
+    /// Ajoute une étape à replier, sans attendre. Si le fil s'est arrêté,
+    /// l'étape est perdue et la prochaine demande de preuve le signale.
+    pub fn push_step(&self, turn: u64, circuit: B::Circuit) {
+        let _ = self.jobs.send(Job::Step(turn, circuit));
+    }
+
+    /// Attend que les étapes en file soient repliées, puis compresse la preuve.
+    pub fn compress(&self) -> Result<Compressed<B::Proof>, ProofError> {
+        let (reply, answer) = channel();
+        self.jobs
+            .send(Job::Compress(reply))
+            .map_err(|_| ProofError::Stopped)?;
+        answer.recv().map_err(|_| ProofError::Stopped)?
+    }
+
+    /// Vérifie une preuve adverse avec nos clés, une fois les étapes en file
+    /// repliées. La preuve est rendue avec sa sortie `z_n`.
+    pub fn verify(&self, proof: Box<B::Proof>, steps: usize, z0: Vec<Fr>) -> Verified<B::Proof> {
+        let (reply, answer) = channel();
+        self.jobs
+            .send(Job::Verify {
+                proof,
+                steps,
+                z0,
+                reply,
+            })
+            .expect("Le fil de preuve s'est arrêté");
+        answer
+            .recv()
+            .expect("Le fil de preuve s'est arrêté pendant la vérification")
+    }
+
+    /// Durées des étapes repliées depuis le dernier appel.
+    pub fn finished_steps(&self) -> impl Iterator<Item = (u64, Duration)> + '_ {
+        self.steps.try_iter()
+    }
+}
+
+fn setup(public_params: &PublicParameters) -> Result<Keys, ProofError> {
+    CompressedSNARK::<_, _, _, _, S<bn256::Point>, S<grumpkin::Point>>::setup(public_params)
+        .map_err(ProofError::Setup)
+}
+
+/// Clé de vérification des preuves faites avec `public_params`.
+pub fn verifier_key(public_params: &PublicParameters) -> Result<ProofVerifierKey, ProofError> {
+    setup(public_params).map(|(_, vk)| vk)
+}
+
+/// Sortie `z_n` de la preuve des `steps` premières étapes depuis `z0`.
+pub fn verify(
+    proof: &CompressedProof,
+    vk: &ProofVerifierKey,
+    steps: usize,
+    z0: Vec<Fr>,
+) -> Result<Vec<Fr>, ProofError> {
+    let z0_secondary = vec![<halo2curves::grumpkin::G1 as halo2curves::group::Group>::Scalar::ZERO];
+    proof
+        .verify(vk, steps, z0, z0_secondary)
+        .map(|(z_n, _)| z_n)
+        .map_err(ProofError::Rejected)
+}
+
+/// Clés déjà générées, ou générées maintenant.
+fn cached_keys<'a>(
+    public_params: &PublicParameters,
+    keys: &'a mut Option<Keys>,
+) -> Result<&'a Keys, ProofError> {
+    if keys.is_none() {
+        *keys = Some(setup(public_params)?);
+    }
+    Ok(keys.as_ref().unwrap())
+}
+
+fn compress(
+    public_params: &PublicParameters,
+    snark: &Snark,
+    keys: &mut Option<Keys>,
+) -> Result<Compressed, ProofError> {
+    let generated = keys.is_none();
+    let begin = Instant::now();
+    let (pk, _) = cached_keys(public_params, keys)?;
+    let setup = generated.then(|| begin.elapsed());
+
+    let begin = Instant::now();
+    let proof = CompressedSNARK::prove(public_params, pk, snark).map_err(ProofError::Compress)?;
+    Ok(Compressed {
+        proof,
+        setup,
+        compress: begin.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Étape de la doublure : repliée, refusée, ou fatale au fil.
+    enum Fold {
+        Ok,
+        Fail,
+        Panic,
+    }
+
+    /// Doublure de Nova dont la preuve est le nombre d'étapes repliées.
+    #[derive(Default)]
+    struct Counter {
+        folded: usize,
+    }
+
+    impl Backend for Counter {
+        type Circuit = Fold;
+        type Proof = usize;
+
+        fn prove_step(&mut self, circuit: &Fold) -> Result<(), NovaError> {
+            // Une étape lente : la demande de preuve doit bien l'attendre.
+            thread::sleep(Duration::from_millis(10));
+            match circuit {
+                Fold::Ok => {
+                    self.folded += 1;
+                    Ok(())
+                }
+                Fold::Fail => Err(NovaError::UnSat),
+                Fold::Panic => panic!("étape fatale"),
+            }
+        }
+
+        fn compress(&mut self) -> Result<Compressed<usize>, ProofError> {
+            Ok(Compressed {
+                proof: self.folded,
+                setup: None,
+                compress: Duration::ZERO,
+            })
+        }
+
+        fn verify(
+            &mut self,
+            proof: &usize,
+            steps: usize,
+            z0: Vec<Fr>,
+        ) -> Result<Vec<Fr>, ProofError> {
+            if *proof == steps {
+                Ok(z0)
+            } else {
+                Err(ProofError::Rejected(NovaError::InvalidNumSteps))
+            }
+        }
+    }
+
+    #[test]
+    fn proof_covers_the_steps_queued_before_it() {
+        let prover = Prover::start(Counter::default());
+        for turn in 1..=3 {
+            prover.push_step(turn, Fold::Ok);
+        }
+        assert_eq!(prover.compress().unwrap().proof, 3);
+        let turns: Vec<u64> = prover.finished_steps().map(|(turn, _)| turn).collect();
+        assert_eq!(turns, [1, 2, 3]);
+
+        prover.push_step(4, Fold::Ok);
+        let (proof, z_n) = prover.verify(Box::new(3), 3, vec![Fr::ONE]);
+        assert_eq!((*proof, z_n), (3, Ok(vec![Fr::ONE])));
+        assert_eq!(prover.compress().unwrap().proof, 4);
+        assert_eq!(prover.finished_steps().count(), 1);
+    }
+
+    #[test]
+    fn refused_step_is_returned_by_every_proof() {
+        let prover = Prover::start(Counter::default());
+        prover.push_step(1, Fold::Ok);
+        prover.push_step(2, Fold::Fail);
+        prover.push_step(3, Fold::Ok);
+        for _ in 0..2 {
+            assert_eq!(
+                prover.compress().err(),
+                Some(ProofError::Step {
+                    turn: 2,
+                    error: NovaError::UnSat
+                })
+            );
+        }
+        assert_eq!(prover.finished_steps().count(), 1);
+    }
+
+    #[test]
+    fn stopped_worker_is_an_error() {
+        let prover = Prover::start(Counter::default());
+        prover.push_step(1, Fold::Panic);
+        assert_eq!(prover.compress().err(), Some(ProofError::Stopped));
+        // Les étapes suivantes sont perdues sans faire paniquer le joueur.
+        prover.push_step(2, Fold::Ok);
+        assert_eq!(prover.compress().err(), Some(ProofError::Stopped));
+    }
+}
@@ -0,0 +1,280 @@
+//! Aléa partagé des combats, tiré à pile ou face à deux joueurs.
+//!
+//! Chaque joueur s'engage sur un nonce en n'envoyant que son hash, puis les deux
+//! nonces sont révélés une fois les deux engagements reçus : aucun joueur ne
+//! peut choisir les jets de l'autre. La graine du tour mélange les deux nonces
+//! avec l'identifiant de la partie et le numéro du tour, et chaque attaque en
+//! tire son propre flux, que les deux joueurs peuvent recalculer.
+
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+/// Identifiant d'une partie, tiré des hashs initiaux des deux joueurs.
+pub type GameId = [u8; 32];
+
+pub type Nonce = [u8; 32];
+pub type Commitment = [u8; 32];
+
+/// Hash chaîné de tout ce qui a été échangé hors circuit.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Transcript([u8; 32]);
+
+impl Transcript {
+    pub fn absorb(&mut self, label: &str, data: &[u8]) {
+        self.0 = Sha256::new()
+            .chain_update(self.0)
+            .chain_update((label.len() as u64).to_le_bytes())
+            .chain_update(label)
+            .chain_update((data.len() as u64).to_le_bytes())
+            .chain_update(data)
+            .finalize()
+            .into();
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+pub fn game_id(initial_hashes: [&[u8]; 2]) -> GameId {
+    Sha256::new()
+        .chain_update("wesnoth-zkpsi/partie")
+        .chain_update(initial_hashes[0])
+        .chain_update(initial_hashes[1])
+        .finalize()
+        .into()
+}
+
+/// Engagement d'un joueur, lié à la partie, au tour et à son côté pour qu'il
+/// ne puisse pas être rejoué ailleurs.
+pub fn commitment(game_id: &GameId, turn: u64, side: u8, nonce: &Nonce) -> Commitment {
+    Sha256::new()
+        .chain_update("wesnoth-zkpsi/engagement")
+        .chain_update(game_id)
+        .chain_update(turn.to_le_bytes())
+        .chain_update([side])
+        .chain_update(nonce)
+        .finalize()
+        .into()
+}
+
+/// Vérifie qu'un nonce révélé pour le tour `turn` ouvre l'engagement reçu du
+/// joueur `side` pour le tour `expected`.
+pub fn verify_opening(
+    game_id: &GameId,
+    expected: u64,
+    turn: u64,
+    side: u8,
+    nonce: &Nonce,
+    engaged: &Commitment,
+) -> Result<(), RandomnessError> {
+    if turn != expected {
+        return Err(RandomnessError::WrongTurn {
+            expected,
+            received: turn,
+        });
+    }
+    if commitment(game_id, turn, side, nonce) != *engaged {
+        return Err(RandomnessError::InvalidOpening { side });
+    }
+    Ok(())
+}
+
+/// Engagement coupé en deux entiers de 128 bits, poids forts en premier, pour
+/// tenir dans le corps des circuits.
+pub fn commitment_limbs(commitment: &Commitment) -> [u128; 2] {
+    let (high, low) = commitment.split_at(16);
+    [
+        u128::from_be_bytes(high.try_into().unwrap()),
+        u128::from_be_bytes(low.try_into().unwrap()),
+    ]
+}
+
+/// Générateur des secrets d'un joueur : exposants de la PSI et nonces.
+/// ChaCha20 plutôt que `StdRng`, dont l'algorithme peut changer d'une version
+/// de `rand` à l'autre.
+pub type SecretRng = ChaCha20Rng;
+
+/// Générateur des secrets du joueur `side`. Avec une graine, la partie se
+/// rejoue à l'identique, chaque côté ayant son propre flux ; sans, il est
+/// initialisé par le système.
+pub fn secret_rng(seed: Option<u64>, side: u8) -> SecretRng {
+    match seed {
+        Some(seed) => SecretRng::from_seed(
+            Sha256::new()
+                .chain_update("wesnoth-zkpsi/secrets")
+                .chain_update(seed.to_le_bytes())
+                .chain_update([side])
+                .finalize()
+                .into(),
+        ),
+        None => SecretRng::from_entropy(),
+    }
+}
+
+pub fn random_nonce(rng: &mut SecretRng) -> Nonce {
+    rng.gen()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RandomnessError {
+    WrongTurn { expected: u64, received: u64 },
+    InvalidOpening { side: u8 },
+}
+
+impl fmt::Display for RandomnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RandomnessError::WrongTurn { expected, received } => write!(
+                f,
+                "nonce révélé pour le tour {} au lieu du tour {}",
+                received, expected
+            ),
+            RandomnessError::InvalidOpening { side } => write!(
+                f,
+                "le nonce révélé par le joueur {} ne correspond pas à son engagement",
+                side
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RandomnessError {}
+
+/// Graine commune d'un tour.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SharedSeed([u8; 32]);
+
+impl SharedSeed {
+    /// Mélange les nonces des deux côtés, dans l'ordre des côtés.
+    pub fn derive(game_id: &GameId, turn: u64, nonces: [&Nonce; 2]) -> SharedSeed {
+        SharedSeed(
+            Sha256::new()
+                .chain_update("wesnoth-zkpsi/graine")
+                .chain_update(game_id)
+                .chain_update(turn.to_le_bytes())
+                .chain_update(nonces[0])
+                .chain_update(nonces[1])
+                .finalize()
+                .into(),
+        )
+    }
+
+    /// Flux des jets d'une attaque, selon son rang dans le tour.
+    pub fn attack_rng(&self, attack: u64) -> ChaCha20Rng {
+        let seed: [u8; 32] = Sha256::new()
+            .chain_update("wesnoth-zkpsi/attaque")
+            .chain_update(self.0)
+            .chain_update(attack.to_le_bytes())
+            .finalize()
+            .into();
+        ChaCha20Rng::from_seed(seed)
+    }
+
+    /// Tirage entre 0 et 99 qui décide d'un audit à la fin du tour.
+    pub fn audit_roll(&self) -> u64 {
+        let seed: [u8; 32] = Sha256::new()
+            .chain_update("wesnoth-zkpsi/audit")
+            .chain_update(self.0)
+            .finalize()
+            .into();
+        ChaCha20Rng::from_seed(seed).gen_range(0..100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+
+    const GAME: GameId = [7; 32];
+
+    #[test]
+    fn secret_rng_streams_depend_on_seed_and_side() {
+        let stream = |seed, side| {
+            let mut rng = secret_rng(seed, side);
+            (random_nonce(&mut rng), random_nonce(&mut rng))
+        };
+        assert_eq!(stream(Some(42), 0), stream(Some(42), 0));
+        assert_ne!(stream(Some(42), 0), stream(Some(42), 1));
+        assert_ne!(stream(Some(42), 0), stream(Some(43), 0));
+        assert_ne!(stream(None, 0), stream(None, 0));
+    }
+
+    #[test]
+    fn opening_checks_nonce_turn_and_side() {
+        let nonce = [1; 32];
+        let engaged = commitment(&GAME, 3, 0, &nonce);
+        assert_eq!(verify_opening(&GAME, 3, 3, 0, &nonce, &engaged), Ok(()));
+
+        assert_eq!(
+            verify_opening(&GAME, 3, 3, 0, &[2; 32], &engaged),
+            Err(RandomnessError::InvalidOpening { side: 0 })
+        );
+        assert_eq!(
+            verify_opening(&GAME, 3, 4, 0, &nonce, &engaged),
+            Err(RandomnessError::WrongTurn {
+                expected: 3,
+                received: 4
+            })
+        );
+        // Un engagement ne s'ouvre ni pour l'autre côté, ni dans une autre
+        // partie, ni à un autre tour.
+        assert_eq!(
+            verify_opening(&GAME, 3, 3, 1, &nonce, &engaged),
+            Err(RandomnessError::InvalidOpening { side: 1 })
+        );
+        assert_eq!(
+            verify_opening(&[8; 32], 3, 3, 0, &nonce, &engaged),
+            Err(RandomnessError::InvalidOpening { side: 0 })
+        );
+        let later = commitment(&GAME, 4, 0, &nonce);
+        assert_eq!(
+            verify_opening(&GAME, 3, 3, 0, &nonce, &later),
+            Err(RandomnessError::InvalidOpening { side: 0 })
+        );
+    }
+
+    #[test]
+    fn shared_seed_is_deterministic() {
+        let (a, b) = ([1; 32], [2; 32]);
+        let seed = SharedSeed::derive(&GAME, 5, [&a, &b]);
+        assert_eq!(seed, SharedSeed::derive(&GAME, 5, [&a, &b]));
+        assert_ne!(seed, SharedSeed::derive(&GAME, 5, [&b, &a]));
+        assert_ne!(seed, SharedSeed::derive(&GAME, 6, [&a, &b]));
+        assert_ne!(seed, SharedSeed::derive(&[8; 32], 5, [&a, &b]));
+        assert_ne!(seed, SharedSeed::derive(&GAME, 5, [&a, &[3; 32]]));
+
+        let again = SharedSeed::derive(&GAME, 5, [&a, &b]);
+        for attack in 0..3 {
+            let rolls = |seed: &SharedSeed| {
+                let mut rng = seed.attack_rng(attack);
+                (0..8).map(|_| rng.next_u64()).collect::<Vec<_>>()
+            };
+            assert_eq!(rolls(&seed), rolls(&again));
+            assert_ne!(rolls(&seed), {
+                let mut rng = seed.attack_rng(attack + 1);
+                (0..8).map(|_| rng.next_u64()).collect::<Vec<_>>()
+            });
+        }
+        assert_eq!(seed.audit_roll(), again.audit_roll());
+        assert!(seed.audit_roll() < 100);
+    }
+
+    #[test]
+    fn commitment_limbs_cover_all_bytes() {
+        let mut engaged = [0; 32];
+        engaged[0] = 0x80;
+        engaged[15] = 1;
+        engaged[16] = 2;
+        engaged[31] = 3;
+        assert_eq!(
+            commitment_limbs(&engaged),
+            [(0x80 << 120) | 1, (2 << 120) | 3]
+        );
+    }
+}
@@ -0,0 +1,1247 @@
+//! Moteur de règles natif, qui reproduit le template `Regles` de
+//! `phase2nova/regles.circom` action par action, pour que l'état local reste
+//! identique à celui que calcule le circuit.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::combat::Attack;
+use crate::hex::distance;
+use crate::pathfinding::{reachable, Path};
+use crate::unit::Unit;
+use crate::vision::VisibleEnemy;
+use crate::{CircuitState, InitialState, Position, Square, Transaction, MAX_ACTION_COUNT};
+
+/// Règle enfreinte par une action, avec de quoi expliquer pourquoi.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleViolation {
+    TooManyActions,
+    OutOfMap(Position),
+    EmptyOrigin(Position),
+    OccupiedDestination(Position),
+    TooFar { distance: u64, move_credits: u64 },
+    NotAdjacent(Position, Position),
+    BlockedByEnemy(Position),
+    ZoneOfControl(Position),
+    TooExpensive { cost: u64, move_credits: u64 },
+    EmptyPath,
+    Unreachable(Position),
+    UnknownVillage(u64),
+    NoUnitOnVillage(u64),
+    VillageAlreadyOwned(u64),
+    UnknownCastle(u64),
+    CommanderNotOnKeep(u64),
+    OccupiedCastle(u64),
+    NotRecruitable(Unit),
+    NotEnoughGold { cost: u64, gold: u64 },
+    AlreadyAttacked(Position),
+    UnknownWeapon(Unit, usize),
+    NoTarget(Position),
+    AttackingOwnUnit(Position),
+}
+
+impl fmt::Display for RuleViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleViolation::TooManyActions => {
+                write!(f, "pas plus de {} actions par tour", MAX_ACTION_COUNT)
+            }
+            RuleViolation::OutOfMap(position) => {
+                write!(f, "la case {:?} est hors de la carte", position)
+            }
+            RuleViolation::EmptyOrigin(position) => {
+                write!(f, "aucune unité à déplacer en {:?}", position)
+            }
+            RuleViolation::OccupiedDestination(position) => {
+                write!(f, "la case d'arrivée {:?} est déjà occupée", position)
+            }
+            RuleViolation::TooFar {
+                distance,
+                move_credits,
+            } => write!(
+                f,
+                "déplacement de {} cases avec seulement {} points de mouvement restants",
+                distance, move_credits
+            ),
+            RuleViolation::NotAdjacent(from, to) => {
+                write!(f, "le chemin saute de {:?} à {:?}", from, to)
+            }
+            RuleViolation::BlockedByEnemy(position) => {
+                write!(f, "le chemin traverse l'ennemi en {:?}", position)
+            }
+            RuleViolation::ZoneOfControl(position) => write!(
+                f,
+                "le chemin continue après la zone de contrôle ennemie en {:?}",
+                position
+            ),
+            RuleViolation::TooExpensive { cost, move_credits } => write!(
+                f,
+                "le chemin coûte {} points de mouvement, il n'en reste que {}",
+                cost, move_credits
+            ),
+            RuleViolation::EmptyPath => write!(f, "le chemin n'a aucune case"),
+            RuleViolation::Unreachable(position) => {
+                write!(f, "aucun chemin assez court ne mène en {:?}", position)
+            }
+            RuleViolation::UnknownVillage(village_id) => {
+                write!(f, "le village {} n'existe pas sur cette carte", village_id)
+            }
+            RuleViolation::NoUnitOnVillage(village_id) => write!(
+                f,
+                "aucune de nos unités n'est sur le village {} pour le capturer",
+                village_id
+            ),
+            RuleViolation::VillageAlreadyOwned(village_id) => {
+                write!(f, "le village {} est déjà à nous", village_id)
+            }
+            RuleViolation::UnknownCastle(castle_id) => {
+                write!(f, "le château {} n'existe pas sur cette carte", castle_id)
+            }
+            RuleViolation::CommanderNotOnKeep(castle_id) => write!(
+                f,
+                "le commandant doit être sur le donjon du château {} pour recruter",
+                castle_id
+            ),
+            RuleViolation::OccupiedCastle(castle_id) => {
+                write!(f, "le château {} est déjà occupé", castle_id)
+            }
+            RuleViolation::NotRecruitable(unit) => {
+                write!(f, "{:?} ne peut pas être recruté", unit)
+            }
+            RuleViolation::NotEnoughGold { cost, gold } => write!(
+                f,
+                "le recrutement coûte {} pièces d'or, il n'en reste que {}",
+                cost, gold
+            ),
+            RuleViolation::AlreadyAttacked(position) => {
+                write!(f, "l'unité en {:?} a déjà attaqué ce tour", position)
+            }
+            RuleViolation::UnknownWeapon(unit, weapon) => {
+                write!(f, "{:?} n'a pas d'arme numéro {}", unit, weapon)
+            }
+            RuleViolation::NoTarget(position) => {
+                write!(f, "aucune unité adverse connue en {:?}", position)
+            }
+            RuleViolation::AttackingOwnUnit(position) => {
+                write!(f, "l'unité en {:?} est à nous", position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleViolation {}
+
+impl CircuitState {
+    /// Vérifie qu'une action est légale dans cet état. Les contraintes de
+    /// `Regles` y sont toutes, ainsi que celles du jeu que le circuit ne vérifie
+    /// pas (unité à déplacer, village déjà possédé).
+    pub fn check(&self, transaction: Transaction, map: &InitialState) -> Result<(), RuleViolation> {
+        let grid = map.grid();
+        let in_map = |position: Position| {
+            if grid.contains(position) {
+                Ok(map.index(position))
+            } else {
+                Err(RuleViolation::OutOfMap(position))
+            }
+        };
+
+        match transaction {
+            Transaction::None => {}
+            Transaction::MoveUnit(origin, destination) => {
+                let (from, to) = (in_map(origin)?, in_map(destination)?);
+                if self.squares[from].unit == Unit::None {
+                    return Err(RuleViolation::EmptyOrigin(origin));
+                }
+                if self.squares[to].unit != Unit::None {
+                    return Err(RuleViolation::OccupiedDestination(destination));
+                }
+                let distance = distance(origin, destination);
+                let move_credits = self.squares[from].move_credits;
+                if distance > move_credits {
+                    return Err(RuleViolation::TooFar {
+                        distance,
+                        move_credits,
+                    });
+                }
+            }
+            Transaction::CaptureVillage(village_id) => {
+                let position = *map
+                    .village_positions()
+                    .get(village_id as usize)
+                    .ok_or(RuleViolation::UnknownVillage(village_id))?;
+                if self.squares[position].unit == Unit::None {
+                    return Err(RuleViolation::NoUnitOnVillage(village_id));
+                }
+                if self.squares[position].captured {
+                    return Err(RuleViolation::VillageAlreadyOwned(village_id));
+                }
+            }
+            Transaction::PurchaseUnit(castle_id, unit) => {
+                let (keep, castle) = *map
+                    .castles()
+                    .get(castle_id as usize)
+                    .ok_or(RuleViolation::UnknownCastle(castle_id))?;
+                if !self.squares[map.keeps()[keep]].unit.is_commander() {
+                    return Err(RuleViolation::CommanderNotOnKeep(castle_id));
+                }
+                if self.squares[castle].unit != Unit::None {
+                    return Err(RuleViolation::OccupiedCastle(castle_id));
+                }
+                let cost = unit.cost().ok_or(RuleViolation::NotRecruitable(unit))?;
+                if cost > self.gold_amount {
+                    return Err(RuleViolation::NotEnoughGold {
+                        cost,
+                        gold: self.gold_amount,
+                    });
+                }
+            }
+            Transaction::Attack(attack) => {
+                let Attack {
+                    from,
+                    to,
+                    defender,
+                    defender_health,
+                    ..
+                } = attack;
+                let (from_index, to_index) = (in_map(from)?, in_map(to)?);
+                let attacker = self.squares[from_index].unit;
+                if attacker == Unit::None {
+                    return Err(RuleViolation::EmptyOrigin(from));
+                }
+                if distance(from, to) != 1 {
+                    return Err(RuleViolation::NotAdjacent(from, to));
+                }
+                if self.squares[to_index].unit != Unit::None {
+                    return Err(RuleViolation::AttackingOwnUnit(to));
+                }
+                if defender == Unit::None || defender_health == 0 {
+                    return Err(RuleViolation::NoTarget(to));
+                }
+                // Mêmes conditions que `Attack::resolve`, qui ne peut donc plus
+                // échouer une fois l'attaque admise.
+                attack.weapon(attacker)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applique une action comme une étape de `Regles`, après l'avoir vérifiée.
+    /// L'état n'est pas modifié si l'action est illégale.
+    pub fn apply(
+        &mut self,
+        transaction: Transaction,
+        map: &InitialState,
+    ) -> Result<(), RuleViolation> {
+        self.check(transaction, map)?;
+
+        match transaction {
+            // Le circuit ne voit pas les attaques, leurs dégâts passent par
+            // `degats` au début du tour suivant.
+            Transaction::None | Transaction::Attack(_) => {}
+            Transaction::MoveUnit(origin, destination) => {
+                let (from, to) = (map.index(origin), map.index(destination));
+                let moved = self.squares[from];
+
+                // L'état des villages reste attaché aux cases.
+                self.squares[to] = Square {
+                    captured: self.squares[to].captured,
+                    move_credits: moved.move_credits - distance(origin, destination),
+                    ..moved
+                };
+                self.squares[from] = Square {
+                    captured: self.squares[from].captured,
+                    ..Unit::None.default_square()
+                };
+            }
+            Transaction::CaptureVillage(village_id) => {
+                let position = map.village_positions()[village_id as usize];
+                self.squares[position].captured = true;
+                self.captured_village_count += 1;
+            }
+            Transaction::PurchaseUnit(castle_id, unit) => {
+                let (_, castle) = map.castles()[castle_id as usize];
+
+                // La recrue ne peut pas bouger avant le prochain tour.
+                self.squares[castle].unit = unit;
+                self.squares[castle].health_points = unit.max_health();
+                self.gold_amount -= unit.cost().unwrap();
+                // Aucune recrue n'est loyale, elles paient toutes l'upkeep.
+                self.current_upkeep_costs += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applique les dégâts reçus depuis notre tour précédent, comme au début de
+    /// `Final` : une unité meurt si les dégâts atteignent ses points de vie, et
+    /// l'upkeep des recrues mortes n'est plus dû.
+    pub fn receive_damage(&mut self, damages: &[u64]) {
+        for (square, &damage) in self.squares.iter_mut().zip(damages) {
+            if square.unit == Unit::None || damage == 0 {
+                continue;
+            }
+            if damage < square.health_points {
+                square.health_points -= damage;
+            } else {
+                // Seul le commandant est loyal.
+                if !square.unit.is_commander() {
+                    self.current_upkeep_costs -= 1;
+                }
+                *square = Square {
+                    captured: square.captured,
+                    ..Unit::None.default_square()
+                };
+            }
+        }
+    }
+
+    /// Début de tour, comme le prétraitement de `Final` : dégâts reçus, pertes
+    /// des villages capturés par l'adversaire, soins, revenu et upkeep, puis
+    /// points de mouvement remis à neuf.
+    pub fn start_turn(&mut self, damages: &[u64], captures: &[u64], map: &InitialState) {
+        self.receive_damage(damages);
+
+        // L'adversaire annonce toutes ses captures : seuls comptent les villages
+        // qui étaient à nous.
+        let mut lost = 0;
+        for (&position, &captured) in map.village_positions().iter().zip(captures) {
+            if captured != 0 && self.squares[position].captured {
+                self.squares[position].captured = false;
+                lost += 1;
+            }
+        }
+        self.captured_village_count = self.captured_village_count.saturating_sub(lost);
+
+        let economy = map.economy();
+        for (index, square) in self.squares.iter_mut().enumerate() {
+            // Une unité qui n'a pas bougé a encore tous ses points de mouvement.
+            let rested = square.move_credits == square.unit.movement();
+            let mut heal = if rested { economy.rest_heal } else { 0 };
+            if map.village_positions().contains(&index) {
+                heal += economy.village_heal;
+            }
+            square.health_points = (square.health_points + heal).min(square.unit.max_health());
+            square.move_credits = square.unit.movement();
+        }
+
+        let supported = economy.village_support * self.captured_village_count;
+        let upkeep = self.current_upkeep_costs.saturating_sub(supported);
+        let income = economy.base_income + economy.village_gold * self.captured_village_count;
+        // Pas de dettes : l'or s'arrête à zéro.
+        self.gold_amount = (self.gold_amount + income).saturating_sub(upkeep);
+    }
+}
+
+/// Action d'un tour scripté, telle qu'un replay de Wesnoth la décrit.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Scripted {
+    /// Déplacement le long du chemin suivi, dont on paie le terrain.
+    Move(Path),
+    /// Attaque de l'unité adverse en `to`, dont le type et les points de vie
+    /// sont ceux que révèle la vision au moment de jouer.
+    Attack {
+        from: Position,
+        to: Position,
+        weapon: usize,
+    },
+    /// Recrutement ou capture.
+    Transaction(Transaction),
+}
+
+/// Tour en préparation. Les actions sont vérifiées une à une dans l'état que
+/// `Final` obtient au début du tour, avant d'appliquer les actions.
+///
+/// Le circuit ne retire que la distance d'un déplacement. Ici, les points de
+/// mouvement suivent le coût réel des chemins, pour qu'une suite de petits
+/// déplacements ne contourne ni le terrain ni les zones de contrôle.
+pub struct Plan {
+    projected: CircuitState,
+    /// Unités adverses connues, qui bloquent les chemins.
+    enemies: Vec<Position>,
+    transactions: Vec<Transaction>,
+}
+
+impl Plan {
+    /// Tour qui commence depuis `state`, l'état à la fin de notre tour
+    /// précédent, avec les dégâts reçus et les villages perdus depuis.
+    pub fn new(
+        state: &CircuitState,
+        damages: &[u64],
+        captures: &[u64],
+        enemies: Vec<Position>,
+        map: &InitialState,
+    ) -> Plan {
+        let mut projected = state.clone();
+        projected.start_turn(damages, captures, map);
+        Plan {
+            projected,
+            enemies,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// État obtenu en appliquant les actions déjà prévues.
+    #[cfg(test)]
+    pub fn projected(&self) -> &CircuitState {
+        &self.projected
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Refuse une action de plus pour une unité qui a attaqué, ou une action du
+    /// circuit de trop.
+    fn admit(&self, transaction: Transaction) -> Result<(), RuleViolation> {
+        let circuit_actions = self
+            .transactions
+            .iter()
+            .filter(|pending| !matches!(pending, Transaction::Attack(_)))
+            .count();
+        // Une unité qui a attaqué ne bouge plus et n'attaque plus de ce tour.
+        let attacked = |position: Position| {
+            self.transactions.iter().any(
+                |pending| matches!(pending, Transaction::Attack(attack) if attack.from == position),
+            )
+        };
+        match transaction {
+            Transaction::Attack(Attack { from, .. }) | Transaction::MoveUnit(from, _)
+                if attacked(from) =>
+            {
+                Err(RuleViolation::AlreadyAttacked(from))
+            }
+            Transaction::Attack(_) => Ok(()),
+            _ if circuit_actions >= MAX_ACTION_COUNT => Err(RuleViolation::TooManyActions),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ajoute une action au tour, après avoir vérifié qu'elle est légale une
+    /// fois appliquées les actions déjà prévues. Un déplacement suit le chemin
+    /// le moins cher.
+    pub fn push(
+        &mut self,
+        transaction: Transaction,
+        map: &InitialState,
+    ) -> Result<(), RuleViolation> {
+        if let Transaction::MoveUnit(origin, destination) = transaction {
+            self.admit(transaction)?;
+            self.projected.check(transaction, map)?;
+            let path = reachable(map, &self.projected, origin, &self.enemies)
+                .path_to(destination)
+                .ok_or(RuleViolation::Unreachable(destination))?;
+            return self.push_path(&path, map);
+        }
+        self.admit(transaction)?;
+        self.projected.apply(transaction, map)?;
+        self.transactions.push(transaction);
+        Ok(())
+    }
+
+    /// Ajoute une action scriptée. Le défenseur d'une attaque est l'unité de
+    /// `visible` sur la case visée, avec ses points de vie.
+    pub fn push_scripted(
+        &mut self,
+        action: &Scripted,
+        visible: &[VisibleEnemy],
+        map: &InitialState,
+    ) -> Result<(), RuleViolation> {
+        match *action {
+            Scripted::Move(ref path) => self.push_path(path, map),
+            Scripted::Attack { from, to, weapon } => {
+                let enemy = visible
+                    .iter()
+                    .find(|enemy| enemy.position == to)
+                    .ok_or(RuleViolation::NoTarget(to))?;
+                let attack = Attack {
+                    from,
+                    to,
+                    weapon,
+                    defender: enemy.unit,
+                    defender_health: enemy.health_points,
+                };
+                self.push(Transaction::Attack(attack), map)
+            }
+            Scripted::Transaction(transaction) => self.push(transaction, map),
+        }
+    }
+
+    /// Ajoute un déplacement le long d'un chemin, vérifié avec le terrain et
+    /// les zones de contrôle des ennemis connus.
+    pub fn push_path(&mut self, path: &Path, map: &InitialState) -> Result<(), RuleViolation> {
+        let transaction = path.as_move()?;
+        self.admit(transaction)?;
+        let spent = self.projected.check_path(path, map, &self.enemies)?;
+        let Transaction::MoveUnit(origin, destination) = transaction else {
+            unreachable!("Un chemin se joue en un seul déplacement");
+        };
+        let move_credits = self.projected.squares[map.index(origin)].move_credits;
+        self.projected.apply(transaction, map)?;
+        self.projected.squares[map.index(destination)].move_credits = move_credits - spent;
+        self.transactions.push(transaction);
+        Ok(())
+    }
+}
+
+/// Paramètres économiques d'une partie, passés aux circuits après `chateaux`
+/// (`base_income`, `village_gold`, `village_support`, `heal_village` et
+/// `heal_rest`).
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Economy {
+    pub base_income: u64,
+    pub village_gold: u64,
+    /// Upkeep pris en charge par chaque village.
+    pub village_support: u64,
+    pub village_heal: u64,
+    pub rest_heal: u64,
+}
+
+impl Default for Economy {
+    /// Valeurs par défaut de Wesnoth.
+    fn default() -> Self {
+        Economy {
+            base_income: 2,
+            village_gold: 2,
+            village_support: 1,
+            village_heal: 8,
+            rest_heal: 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::hex::tests::circuit;
+    use crate::unit::Commander;
+
+    const MAP: InitialState = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+
+    const REGLES: &str = include_str!("../wesnoth-zkpsi/phase2/regles.circom");
+    const REGLES_NOVA: &str = include_str!("../wesnoth-zkpsi/phase2nova/regles.circom");
+    const FINAL_NOVA: &str = include_str!("../wesnoth-zkpsi/phase2nova/circuit.circom");
+    const FINAL: &str = include_str!("../wesnoth-zkpsi/phase2/circuit.circom");
+
+    /// Commandant sur le premier donjon, rien d'autre sur la carte.
+    fn start() -> CircuitState {
+        let mut squares = vec![Unit::None.default_square(); 100];
+        squares[0] = Unit::OrcCommander.default_square();
+        CircuitState {
+            squares,
+            gold_amount: 100,
+            captured_village_count: 0,
+            current_upkeep_costs: 0,
+        }
+    }
+
+    fn map_index(position: Position) -> usize {
+        MAP.index(position)
+    }
+
+    fn rows(state: &CircuitState) -> Vec<[i64; 4]> {
+        state
+            .squares
+            .iter()
+            .map(|square| {
+                [
+                    u64::from(&square.unit) as i64,
+                    square.health_points as i64,
+                    square.captured as i64,
+                    square.move_credits as i64,
+                ]
+            })
+            .collect()
+    }
+
+    /// Transcription de `next_state` dans `Regles` pour un déplacement
+    /// (`action[0] = 1`, ni capture ni recrutement).
+    fn regles_move(prev_state: &[[i64; 4]], action: [i64; 8], state_height: i64) -> Vec<[i64; 4]> {
+        let position_depart = action[1] * state_height + action[2];
+        let position_arrivee = action[3] * state_height + action[4];
+        let unite_action = prev_state[position_depart as usize];
+        let unite_action = [unite_action[0], unite_action[1], unite_action[3]];
+        let dist = circuit::distance([action[1], action[2]], [action[3], action[4]]);
+        assert!(dist <= unite_action[2], "contrainte_move non satisfaite");
+
+        (0..prev_state.len() as i64)
+            .map(|i| {
+                let prev = prev_state[i as usize];
+                let est_depart = (i == position_depart) as i64;
+                let est_destination = (i == position_arrivee) as i64;
+                assert_eq!(est_destination * prev[0], 0);
+                let not_depart = [
+                    prev[0] + est_destination * (unite_action[0] - prev[0]),
+                    prev[1] + est_destination * (unite_action[1] - prev[1]),
+                    prev[3] + est_destination * (unite_action[2] - dist - prev[3]),
+                ];
+                [
+                    (1 - est_depart) * not_depart[0],
+                    (1 - est_depart) * not_depart[1],
+                    prev[2],
+                    (1 - est_depart) * not_depart[2],
+                ]
+            })
+            .collect()
+    }
+
+    /// Liste entière d'un paramètre de `Final`, entre crochets.
+    fn final_parameter(source: &str, index: usize) -> Vec<i64> {
+        let main = source
+            .lines()
+            .find(|line| line.starts_with("component main"))
+            .expect("Pas de component main");
+        main.split('[')
+            .filter(|part| !part.is_empty() && part.as_bytes()[0].is_ascii_digit())
+            .map(|part| {
+                part.split(']')
+                    .next()
+                    .unwrap()
+                    .split(',')
+                    .map(|n| n.trim().parse().unwrap())
+                    .collect()
+            })
+            .nth(index)
+            .unwrap()
+    }
+
+    /// Paramètres entiers de `Final` après ses listes : `base_income`,
+    /// `village_gold`, `village_support`, `heal_village` et `heal_rest`.
+    fn final_scalars(source: &str) -> Vec<i64> {
+        let main = source
+            .lines()
+            .find(|line| line.starts_with("component main"))
+            .expect("Pas de component main");
+        let tail = &main[main.rfind(']').unwrap() + 1..];
+        tail.trim_end_matches(");")
+            .split(',')
+            .filter(|n| !n.trim().is_empty())
+            .map(|n| n.trim().parse().unwrap())
+            .collect()
+    }
+
+    /// Transcription du prétraitement de `Final`, jusqu'aux entrées de
+    /// `application0` : dégâts, captures, soins, monnaie et déplacements.
+    fn final_preprocessing(
+        prev_state: &[[i64; 4]],
+        prev_misc_state: [i64; 3],
+        degats: &[i64],
+        captures: &[i64],
+        source: &str,
+    ) -> (Vec<[i64; 4]>, [i64; 3]) {
+        let pos_villages = final_parameter(source, 0);
+        let hp_troupes = final_parameter(source, 1);
+        let range_troupes = final_parameter(source, 2);
+        let [base_income, village_gold, village_support, heal_village, heal_rest] =
+            final_scalars(source)[..]
+        else {
+            panic!("Paramètres entiers de Final inattendus");
+        };
+        let indexof = |i: usize| pos_villages.iter().position(|&v| v == i as i64);
+
+        let mut degats_state = vec![[0; 4]; prev_state.len()];
+        let mut degats_state_upkeep = prev_misc_state[2];
+        for (i, prev) in prev_state.iter().enumerate() {
+            let nokill = (prev[1] > degats[i]) as i64;
+            degats_state[i][0] = prev[0] * nokill;
+            degats_state[i][1] = nokill * (prev[1] - degats[i]);
+            degats_state[i][2] = match indexof(i) {
+                Some(ind) => (1 - captures[ind]) * prev[2],
+                None => 0,
+            };
+            degats_state[i][3] = nokill * prev[3];
+            let loyal = (prev[0] * (1 - prev[0]) == 0) as i64;
+            degats_state_upkeep = degats_state_upkeep - (1 - loyal) + nokill * (1 - loyal);
+        }
+        let pertes_villages: i64 = pos_villages
+            .iter()
+            .zip(captures)
+            .map(|(&pos, &capture)| capture * prev_state[pos as usize][2])
+            .sum();
+
+        let range_unit = |i: usize| range_troupes[degats_state[i][0] as usize];
+        let final_state = (0..prev_state.len())
+            .map(|i| {
+                let est_village = indexof(i).is_some() as i64;
+                let au_repos = (degats_state[i][3] == range_unit(i)) as i64;
+                let max_health = hp_troupes[degats_state[i][0] as usize];
+                let soigne = degats_state[i][1] + est_village * heal_village + au_repos * heal_rest;
+                let full_heal = (soigne < max_health) as i64;
+                max_health + full_heal * (soigne - max_health)
+            })
+            .collect::<Vec<_>>();
+
+        let villages = prev_misc_state[1] - pertes_villages;
+        let pay_upkeep = (village_support * villages < degats_state_upkeep) as i64;
+        let dette = pay_upkeep * (degats_state_upkeep - village_support * villages);
+        let revenu = prev_misc_state[0] + base_income + village_gold * villages;
+        let floor_zero = (dette < revenu) as i64;
+        let final_money = floor_zero * (revenu - dette);
+
+        let state = (0..prev_state.len())
+            .map(|i| {
+                [
+                    degats_state[i][0],
+                    final_state[i],
+                    degats_state[i][2],
+                    range_unit(i),
+                ]
+            })
+            .collect();
+        (state, [final_money, villages, degats_state_upkeep])
+    }
+
+    #[test]
+    fn regles_sources_are_pinned() {
+        // Les deux copies de `Regles` doivent rester identiques, et contenir les
+        // corrections dont dépend le moteur natif.
+        assert_eq!(REGLES, REGLES_NOVA);
+        for line in [
+            "component contrainte_move = LessEqThan(64);",
+            "action_type1.out * (1 - contrainte_move.out) === 0;",
+            "(unite_action.out[1] - prev_state[i][1])",
+            "(unite_action.out[2] - dist.out - prev_state[i][3])",
+            "rdiff.in1 <== in1[1] + div2.quotient;",
+            "sumdiff.in1 <== in1[0] + in1[1] + div2.quotient;",
+        ] {
+            assert!(REGLES.contains(line), "{} absent de regles.circom", line);
+        }
+    }
+
+    #[test]
+    fn unit_catalog_matches_circuit_parameters() {
+        // `pos_villages`, `hp_troupes`, `range_troupes`, `prix_troupes`, `donjons`.
+        let villages = final_parameter(FINAL_NOVA, 0);
+        let hp = final_parameter(FINAL_NOVA, 1);
+        let range = final_parameter(FINAL_NOVA, 2);
+        let prix = final_parameter(FINAL_NOVA, 3);
+        let keeps = final_parameter(FINAL_NOVA, 4);
+        assert_eq!(hp.len(), Unit::ALL.len());
+        for (i, unit) in Unit::ALL.iter().enumerate() {
+            assert_eq!(unit.max_health() as i64, hp[i], "{:?}", unit);
+            assert_eq!(unit.movement() as i64, range[i], "{:?}", unit);
+            assert_eq!(
+                unit.cost().map(|cost| cost as i64),
+                Some(prix[i]).filter(|&prix| prix > 0),
+                "{:?}",
+                unit
+            );
+        }
+        let positions = |list: &[usize]| list.iter().map(|&i| i as i64).collect::<Vec<_>>();
+        assert_eq!(villages, positions(MAP.village_positions()));
+        assert_eq!(keeps, positions(MAP.keeps()));
+    }
+
+    #[test]
+    fn move_matches_regles() {
+        let mut state = start();
+        state.squares[0].health_points = 20;
+        state.squares[0].move_credits = 4;
+        state.squares[map_index((2, 1))].captured = true;
+        let prev = rows(&state);
+
+        let transaction = Transaction::MoveUnit((0, 0), (2, 1));
+        state.apply(transaction, &MAP).unwrap();
+        assert_eq!(
+            rows(&state),
+            regles_move(&prev, transaction.to_action().unwrap(), 10)
+        );
+
+        let moved = state.squares[map_index((2, 1))];
+        assert_eq!(moved.unit, Unit::OrcCommander);
+        assert_eq!(moved.health_points, 20);
+        assert_eq!(moved.move_credits, 2);
+        assert!(moved.captured);
+        assert_eq!(state.squares[0].unit, Unit::None);
+    }
+
+    #[test]
+    fn move_uses_every_credit() {
+        // `LessEqThan` : on peut aller exactement aussi loin que ses points de
+        // mouvement.
+        let mut state = start();
+        state
+            .apply(Transaction::MoveUnit((0, 0), (0, 5)), &MAP)
+            .unwrap();
+        assert_eq!(state.squares[map_index((0, 5))].move_credits, 0);
+        assert_eq!(
+            state.check(Transaction::MoveUnit((0, 5), (0, 6)), &MAP),
+            Err(RuleViolation::TooFar {
+                distance: 1,
+                move_credits: 0
+            })
+        );
+    }
+
+    fn attack(from: Position, to: Position, weapon: usize, defender: Unit) -> Transaction {
+        Transaction::Attack(Attack {
+            from,
+            to,
+            weapon,
+            defender,
+            defender_health: defender.max_health(),
+        })
+    }
+
+    #[test]
+    fn check_rejects_each_violation() {
+        let mut state = start();
+        state.squares[map_index((1, 1))] = Unit::OrcishGrunt.default_square();
+        // Village 0 en (5, 0), déjà à nous.
+        state.squares[50] = Unit::WolfRider.default_square();
+        state.squares[50].captured = true;
+        // Château 0 en (0, 1), occupé.
+        state.squares[1] = Unit::GoblinSpearman.default_square();
+
+        let cases = [
+            (
+                Transaction::MoveUnit((0, 0), (10, 0)),
+                RuleViolation::OutOfMap((10, 0)),
+            ),
+            (
+                Transaction::MoveUnit((3, 3), (3, 4)),
+                RuleViolation::EmptyOrigin((3, 3)),
+            ),
+            (
+                Transaction::MoveUnit((0, 0), (1, 1)),
+                RuleViolation::OccupiedDestination((1, 1)),
+            ),
+            (
+                Transaction::MoveUnit((0, 0), (6, 3)),
+                RuleViolation::TooFar {
+                    distance: 6,
+                    move_credits: 5,
+                },
+            ),
+            (
+                Transaction::CaptureVillage(8),
+                RuleViolation::UnknownVillage(8),
+            ),
+            (
+                Transaction::CaptureVillage(1),
+                RuleViolation::NoUnitOnVillage(1),
+            ),
+            (
+                Transaction::CaptureVillage(0),
+                RuleViolation::VillageAlreadyOwned(0),
+            ),
+            (
+                Transaction::PurchaseUnit(6, Unit::OrcishGrunt),
+                RuleViolation::UnknownCastle(6),
+            ),
+            (
+                Transaction::PurchaseUnit(3, Unit::OrcishGrunt),
+                RuleViolation::CommanderNotOnKeep(3),
+            ),
+            (
+                Transaction::PurchaseUnit(1, Unit::OrcCommander),
+                RuleViolation::NotRecruitable(Unit::OrcCommander),
+            ),
+            (
+                attack((0, 0), (1, 0), 0, Unit::None),
+                RuleViolation::NoTarget((1, 0)),
+            ),
+            (
+                attack((2, 2), (2, 3), 0, Unit::OrcishGrunt),
+                RuleViolation::EmptyOrigin((2, 2)),
+            ),
+            (
+                attack((0, 0), (0, 2), 0, Unit::OrcishGrunt),
+                RuleViolation::NotAdjacent((0, 0), (0, 2)),
+            ),
+            (
+                attack((0, 0), (0, 1), 0, Unit::OrcishGrunt),
+                RuleViolation::AttackingOwnUnit((0, 1)),
+            ),
+            (
+                Transaction::PurchaseUnit(0, Unit::OrcishGrunt),
+                RuleViolation::OccupiedCastle(0),
+            ),
+            (
+                attack((0, 0), (1, 0), 2, Unit::OrcishGrunt),
+                RuleViolation::UnknownWeapon(Unit::OrcCommander, 2),
+            ),
+            (
+                attack((0, 0), (0, 10), 0, Unit::OrcishGrunt),
+                RuleViolation::OutOfMap((0, 10)),
+            ),
+        ];
+        for (transaction, violation) in cases {
+            let before = rows(&state);
+            assert_eq!(state.check(transaction, &MAP), Err(violation.clone()));
+            assert_eq!(state.apply(transaction, &MAP), Err(violation));
+            assert_eq!(rows(&state), before, "{:?}", transaction);
+        }
+    }
+
+    #[test]
+    fn purchase_needs_gold() {
+        let mut state = start();
+        state.gold_amount = 11;
+        assert_eq!(
+            state.apply(Transaction::PurchaseUnit(0, Unit::OrcishGrunt), &MAP),
+            Err(RuleViolation::NotEnoughGold { cost: 12, gold: 11 })
+        );
+        state.gold_amount = 12;
+        state
+            .apply(Transaction::PurchaseUnit(0, Unit::OrcishGrunt), &MAP)
+            .unwrap();
+        assert_eq!(state.gold_amount, 0);
+        assert_eq!(state.current_upkeep_costs, 1);
+        let recruit = state.squares[1];
+        assert_eq!(recruit.unit, Unit::OrcishGrunt);
+        assert_eq!(recruit.health_points, Unit::OrcishGrunt.max_health());
+    }
+
+    #[test]
+    fn capture_marks_village() {
+        let mut state = start();
+        state
+            .apply(Transaction::MoveUnit((0, 0), (0, 5)), &MAP)
+            .unwrap();
+        // Village 2 en (0, 5).
+        state.apply(Transaction::CaptureVillage(2), &MAP).unwrap();
+        assert!(state.squares[5].captured);
+        assert_eq!(state.captured_village_count, 1);
+        // Le village reste à nous quand l'unité repart.
+        state.squares[5].move_credits = 1;
+        state
+            .apply(Transaction::MoveUnit((0, 5), (0, 4)), &MAP)
+            .unwrap();
+        assert!(state.squares[5].captured);
+        assert_eq!(
+            state.check(Transaction::CaptureVillage(2), &MAP),
+            Err(RuleViolation::NoUnitOnVillage(2))
+        );
+    }
+
+    #[test]
+    fn attacks_leave_the_state_alone() {
+        let mut state = start();
+        let before = rows(&state);
+        state
+            .apply(attack((0, 0), (1, 0), 0, Unit::OrcishGrunt), &MAP)
+            .unwrap();
+        assert_eq!(rows(&state), before);
+    }
+
+    fn no_damage() -> Vec<u64> {
+        vec![0; 100]
+    }
+
+    fn no_capture() -> Vec<u64> {
+        vec![0; MAP.village_count() as usize]
+    }
+
+    #[test]
+    fn plan_moves_again_on_the_next_turn() {
+        let mut plan = Plan::new(&start(), &no_damage(), &no_capture(), vec![], &MAP);
+        plan.push(Transaction::MoveUnit((0, 0), (0, 5)), &MAP)
+            .unwrap();
+        assert_eq!(
+            plan.push(Transaction::MoveUnit((0, 5), (0, 6)), &MAP),
+            Err(RuleViolation::TooFar {
+                distance: 1,
+                move_credits: 0
+            })
+        );
+
+        // Fin du tour : le circuit a appliqué les actions à l'état précédent.
+        let mut end = start();
+        for &transaction in plan.transactions() {
+            end.apply(transaction, &MAP).unwrap();
+        }
+        let mut next = Plan::new(&end, &no_damage(), &no_capture(), vec![], &MAP);
+        next.push(Transaction::MoveUnit((0, 5), (0, 9)), &MAP)
+            .unwrap();
+        assert_eq!(next.projected().squares[9].move_credits, 1);
+    }
+
+    #[test]
+    fn plan_counts_income() {
+        let mut state = start();
+        state.gold_amount = 10;
+        // Le revenu de base arrive avant les actions.
+        let mut plan = Plan::new(&state, &no_damage(), &no_capture(), vec![], &MAP);
+        assert_eq!(plan.projected().gold_amount, 12);
+        plan.push(Transaction::PurchaseUnit(0, Unit::OrcishGrunt), &MAP)
+            .unwrap();
+        assert_eq!(plan.projected().gold_amount, 0);
+
+        // Une recrue paie l'upkeep, que deux villages prennent en charge.
+        let mut state = start();
+        state.gold_amount = 10;
+        state.current_upkeep_costs = 3;
+        state.captured_village_count = 2;
+        let plan = Plan::new(&state, &no_damage(), &no_capture(), vec![], &MAP);
+        assert_eq!(plan.projected().gold_amount, 10 + 2 + 2 * 2 - 1);
+    }
+
+    #[test]
+    fn plan_forgets_dead_units() {
+        let mut state = start();
+        state.squares[map_index((1, 0))] = Unit::OrcishGrunt.default_square();
+        state.current_upkeep_costs = 1;
+        let mut damages = no_damage();
+        damages[map_index((1, 0))] = Unit::OrcishGrunt.max_health();
+        damages[0] = 8;
+
+        let mut plan = Plan::new(&state, &damages, &no_capture(), vec![], &MAP);
+        assert_eq!(
+            plan.push(Transaction::MoveUnit((1, 0), (2, 0)), &MAP),
+            Err(RuleViolation::EmptyOrigin((1, 0)))
+        );
+        assert_eq!(
+            plan.push(attack((1, 0), (2, 0), 0, Unit::OrcishGrunt), &MAP),
+            Err(RuleViolation::EmptyOrigin((1, 0)))
+        );
+        // Le commandant blessé a récupéré en se reposant.
+        assert_eq!(
+            plan.projected().squares[0].health_points,
+            Unit::OrcCommander.max_health() - 8 + 2
+        );
+        assert_eq!(plan.projected().current_upkeep_costs, 0);
+        assert!(plan.transactions().is_empty());
+    }
+
+    #[test]
+    fn plan_loses_captured_villages() {
+        let mut state = start();
+        state.squares[50] = Unit::OrcishGrunt.default_square();
+        state.squares[50].captured = true;
+        state.captured_village_count = 1;
+        let mut captures = no_capture();
+        captures[0] = 1;
+        let mut plan = Plan::new(&state, &no_damage(), &captures, vec![], &MAP);
+        plan.push(Transaction::CaptureVillage(0), &MAP).unwrap();
+        assert_eq!(plan.projected().captured_village_count, 1);
+    }
+
+    #[test]
+    fn start_turn_matches_final() {
+        assert!(FINAL_NOVA.contains("loyal[i].in <== prev_state[i][0] * (1 - prev_state[i][0]);"));
+        assert!(FINAL_NOVA.contains(
+            "pertes_villages[vill - 1] + captures[vill] * prev_state[pos_villages[vill]][2];"
+        ));
+        let economy = MAP.economy();
+        assert_eq!(
+            final_scalars(FINAL_NOVA),
+            [
+                economy.base_income,
+                economy.village_gold,
+                economy.village_support,
+                economy.village_heal,
+                economy.rest_heal
+            ]
+            .map(|value| value as i64)
+        );
+
+        let mut rng = StdRng::seed_from_u64(36);
+        let villages = MAP.village_positions();
+        for _ in 0..200 {
+            let mut state = start();
+            state.squares[0].health_points = rng.gen_range(1..=Unit::OrcCommander.max_health());
+            for _ in 0..rng.gen_range(0..12) {
+                let index = rng.gen_range(1..100);
+                let unit = Unit::ALL[rng.gen_range(2..Unit::ALL.len())];
+                state.squares[index] = Square {
+                    unit,
+                    health_points: rng.gen_range(1..=unit.max_health()),
+                    captured: false,
+                    move_credits: rng.gen_range(0..=unit.movement()),
+                };
+            }
+            state.squares[0].move_credits = rng.gen_range(0..=Unit::OrcCommander.movement());
+            // Villages possédés, avec ou sans unité dessus.
+            for &village in villages {
+                state.squares[village].captured = rng.gen_bool(0.5);
+            }
+            state.captured_village_count = villages
+                .iter()
+                .filter(|&&v| state.squares[v].captured)
+                .count() as u64;
+            state.current_upkeep_costs = state
+                .squares
+                .iter()
+                .filter(|square| square.unit != Unit::None && !square.unit.is_commander())
+                .count() as u64;
+            state.gold_amount = rng.gen_range(0..40);
+
+            // Dégâts sur quelques cases, mortels ou non, parfois sur une case vide
+            // que l'adversaire croit occupée.
+            let mut damages = no_damage();
+            for _ in 0..rng.gen_range(0..6) {
+                let index = rng.gen_range(0..100);
+                let health_points = state.squares[index].health_points;
+                damages[index] = match rng.gen_range(0..3) {
+                    0 => health_points,
+                    1 => rng.gen_range(0..=health_points),
+                    _ => health_points + rng.gen_range(1..10),
+                };
+            }
+            // Les captures annoncées touchent aussi des villages qui n'étaient
+            // pas à nous.
+            let captures = villages
+                .iter()
+                .map(|_| rng.gen_bool(0.3) as u64)
+                .collect::<Vec<_>>();
+
+            let (expected_state, expected_misc) = final_preprocessing(
+                &rows(&state),
+                [
+                    state.gold_amount as i64,
+                    state.captured_village_count as i64,
+                    state.current_upkeep_costs as i64,
+                ],
+                &damages.iter().map(|&d| d as i64).collect::<Vec<_>>(),
+                &captures.iter().map(|&c| c as i64).collect::<Vec<_>>(),
+                FINAL_NOVA,
+            );
+            state.start_turn(&damages, &captures, &MAP);
+            assert_eq!(rows(&state), expected_state);
+            assert_eq!(
+                [
+                    state.gold_amount as i64,
+                    state.captured_village_count as i64,
+                    state.current_upkeep_costs as i64,
+                ],
+                expected_misc
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_final_has_the_same_economy() {
+        // Début de tour du circuit sans Nova, toujours compilé par
+        // `base_compil.sh` : mêmes pertes de villages, soins et monnaie.
+        let start_of_turn = |source: &'static str| {
+            let begin = source
+                .find("  // Pertes de villages dans le compte")
+                .unwrap();
+            let end = source
+                .find("final_money <== floor_zero.out * degats_money;")
+                .unwrap();
+            source[begin..end]
+                .lines()
+                .filter(|line| !line.contains("range_unit") && !line.trim().is_empty())
+                .collect::<Vec<_>>()
+        };
+        let (legacy, nova) = (start_of_turn(FINAL), start_of_turn(FINAL_NOVA));
+        for line in nova.iter().filter(|line| line.contains("<==")) {
+            assert!(
+                legacy.contains(line),
+                "Absent du circuit sans Nova : {}",
+                line
+            );
+        }
+        assert!(FINAL
+            .contains("base_income, village_gold, village_support, heal_village, heal_rest) {"));
+        assert!(FINAL.contains("  2, 2, 1, 8, 2); //"));
+    }
+
+    #[test]
+    fn plan_limits_actions() {
+        let mut plan = Plan::new(&start(), &no_damage(), &no_capture(), vec![], &MAP);
+        plan.push(attack((0, 0), (1, 0), 0, Unit::OrcishGrunt), &MAP)
+            .unwrap();
+        assert_eq!(
+            plan.push(Transaction::MoveUnit((0, 0), (0, 1)), &MAP),
+            Err(RuleViolation::AlreadyAttacked((0, 0)))
+        );
+        assert_eq!(
+            plan.push(attack((0, 0), (1, 0), 0, Unit::OrcishGrunt), &MAP),
+            Err(RuleViolation::AlreadyAttacked((0, 0)))
+        );
+
+        let mut plan = Plan::new(&start(), &no_damage(), &no_capture(), vec![], &MAP);
+        for _ in 0..MAX_ACTION_COUNT {
+            plan.push(Transaction::None, &MAP).unwrap();
+        }
+        assert_eq!(
+            plan.push(Transaction::None, &MAP),
+            Err(RuleViolation::TooManyActions)
+        );
+        // Les attaques ne sont pas des actions du circuit.
+        plan.push(attack((0, 0), (1, 0), 0, Unit::OrcishGrunt), &MAP)
+            .unwrap();
+    }
+
+    #[test]
+    fn plan_spends_the_real_path_cost() {
+        let mut state = start();
+        state.squares[0] = Unit::None.default_square();
+        state.squares[map_index((3, 4))] = Unit::OrcishGrunt.default_square();
+        let mut plan = Plan::new(&state, &no_damage(), &no_capture(), vec![], &MAP);
+        // La forêt en (3, 3) coûte 2 : le circuit n'en retirerait que 1.
+        plan.push(Transaction::MoveUnit((3, 4), (3, 3)), &MAP)
+            .unwrap();
+        assert_eq!(plan.projected().squares[map_index((3, 3))].move_credits, 3);
+        // Le marais en (5, 3) est à 3 cases, mais coûte 3 à lui seul.
+        assert_eq!(
+            plan.push(Transaction::MoveUnit((3, 3), (5, 3)), &MAP),
+            Err(RuleViolation::Unreachable((5, 3)))
+        );
+        // L'eau en (4, 4) coûte 3, une deuxième fois serait de trop.
+        plan.push_path(
+            &Path {
+                steps: vec![(3, 3), (4, 4)],
+            },
+            &MAP,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.push(Transaction::MoveUnit((4, 4), (5, 4)), &MAP),
+            Err(RuleViolation::TooFar {
+                distance: 1,
+                move_credits: 0
+            })
+        );
+        assert_eq!(
+            plan.push_path(
+                &Path {
+                    steps: vec![(4, 4), (4, 5)]
+                },
+                &MAP
+            ),
+            Err(RuleViolation::TooFar {
+                distance: 1,
+                move_credits: 0
+            })
+        );
+        assert_eq!(
+            plan.push_path(&Path { steps: vec![] }, &MAP),
+            Err(RuleViolation::EmptyPath)
+        );
+        assert_eq!(plan.transactions().len(), 2);
+    }
+
+    #[test]
+    fn plan_stops_in_zones_of_control() {
+        let mut plan = Plan::new(&start(), &no_damage(), &no_capture(), vec![(2, 1)], &MAP);
+        // (1, 0) touche l'ennemi en (2, 1).
+        plan.push(Transaction::MoveUnit((0, 0), (1, 0)), &MAP)
+            .unwrap();
+        assert_eq!(plan.projected().squares[map_index((1, 0))].move_credits, 0);
+        assert_eq!(
+            plan.push(Transaction::MoveUnit((1, 0), (0, 1)), &MAP),
+            Err(RuleViolation::TooFar {
+                distance: 1,
+                move_credits: 0
+            })
+        );
+        // Pas de passage à travers la zone de contrôle.
+        let mut plan = Plan::new(&start(), &no_damage(), &no_capture(), vec![(2, 1)], &MAP);
+        assert_eq!(
+            plan.push_path(
+                &Path {
+                    steps: vec![(0, 0), (1, 0), (1, 1), (1, 2)]
+                },
+                &MAP
+            ),
+            Err(RuleViolation::ZoneOfControl((1, 0)))
+        );
+    }
+}
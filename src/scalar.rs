@@ -0,0 +1,137 @@
+//! Scalaires du sous-groupe d'ordre premier de Baby Jubjub, exposants de la PSI.
+//!
+//! Les points échangés sont des hashs de Pedersen, qui tombent dans le
+//! sous-groupe d'ordre premier `l`, l'ordre de la courbe valant `8 l`. Les
+//! exposants vivent donc modulo `l` : tirés uniformément parmi les non nuls, ils
+//! sont tous inversibles, et la phase 3 retire le sien avec son inverse modulo
+//! `l`.
+
+use std::str::FromStr;
+
+use num_bigint::{BigUint, RandBigInt};
+use rand::Rng;
+use zeroize::Zeroizing;
+
+use crate::convert::to_bits;
+
+/// Ordre `l` du sous-groupe premier de Baby Jubjub.
+pub const SUBGROUP_ORDER: &str =
+    "2736030358979909402780800718157159386076813972158567259200215660948447373041";
+
+/// Nombre de bits des exposants en entrée des circuits (`EscalarMulAny(254)`).
+pub const SCALAR_BITS: usize = 254;
+
+fn subgroup_order() -> BigUint {
+    BigUint::from_str(SUBGROUP_ORDER).unwrap()
+}
+
+/// Écrase les chiffres d'un `BigUint` dans son propre tampon. `BigUint`
+/// n'implémente pas `Zeroize`, mais réaffecter autant de chiffres nuls qu'il en
+/// a réutilise son allocation.
+fn wipe(value: &mut BigUint) {
+    let digits = value.iter_u32_digits().count();
+    value.assign_from_slice(&vec![0; digits]);
+}
+
+/// Scalaire non nul modulo `l`. C'est un secret : il est effacé à sa
+/// destruction et n'a ni `Debug` ni `Display`.
+pub struct Scalar(BigUint);
+
+impl Scalar {
+    /// Tirage uniforme parmi les scalaires non nuls.
+    pub fn random(rng: &mut impl Rng) -> Scalar {
+        Scalar(rng.gen_biguint_range(&BigUint::from(1u8), &subgroup_order()))
+    }
+
+    /// Inverse modulo `l`, qui existe toujours puisque `l` est premier et le
+    /// scalaire non nul.
+    pub fn inverse(&self) -> Scalar {
+        let order = subgroup_order();
+        let exponent = &order - BigUint::from(2u8);
+        Scalar(self.0.modpow(&exponent, &order))
+    }
+
+    /// Bits petit-boutistes sur `SCALAR_BITS` chiffres, pour l'entrée d'un
+    /// circuit.
+    pub fn to_bits(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(to_bits(&self.0, SCALAR_BITS).expect("Un scalaire tient sur 251 bits"))
+    }
+}
+
+impl Drop for Scalar {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// Bits de chaque exposant, effacés avec le vecteur.
+pub fn exponent_bits(exponents: &[Scalar]) -> Zeroizing<Vec<Vec<u8>>> {
+    Zeroizing::new(
+        exponents
+            .iter()
+            .map(|exponent| exponent.to_bits().to_vec())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn sampling_stays_in_the_non_zero_residues() {
+        let order = subgroup_order();
+        let mut rng = StdRng::seed_from_u64(47);
+        for _ in 0..200 {
+            let scalar = Scalar::random(&mut rng);
+            assert!(scalar.0 > BigUint::ZERO);
+            assert!(scalar.0 < order);
+        }
+        // Un générateur qui ne tire que des zéros donne le plus petit non nul.
+        assert_eq!(
+            Scalar::random(&mut StepRng::new(0, 0)).0,
+            BigUint::from(1u8)
+        );
+    }
+
+    #[test]
+    fn inverse_is_modulo_the_subgroup_order() {
+        let order = subgroup_order();
+        let mut rng = StdRng::seed_from_u64(47);
+        let extremes = [
+            Scalar(BigUint::from(1u8)),
+            Scalar(&order - BigUint::from(1u8)),
+        ];
+        let random = (0..50).map(|_| Scalar::random(&mut rng));
+        for scalar in extremes.into_iter().chain(random) {
+            let inverse = scalar.inverse();
+            assert!(inverse.0 < order);
+            assert_eq!((&scalar.0 * &inverse.0) % &order, BigUint::from(1u8));
+            assert_eq!(inverse.inverse().0, scalar.0);
+        }
+    }
+
+    #[test]
+    fn bits_are_little_endian_on_254_digits() {
+        let scalar = Scalar(BigUint::from(6u8));
+        let bits = scalar.to_bits();
+        assert_eq!(bits.len(), SCALAR_BITS);
+        assert_eq!(&bits[..4], [0, 1, 1, 0]);
+        assert!(bits[4..].iter().all(|&bit| bit == 0));
+
+        let largest = Scalar(subgroup_order() - BigUint::from(1u8));
+        let bits = largest.to_bits();
+        assert!(bits[251..].iter().all(|&bit| bit == 0));
+    }
+
+    #[test]
+    fn wipe_zeroes_the_digits() {
+        let mut value = subgroup_order();
+        wipe(&mut value);
+        assert_eq!(value, BigUint::ZERO);
+    }
+}
@@ -0,0 +1,164 @@
+//! Secrets d'un joueur : aléa des exposants de la PSI et fichiers qui les
+//! contiennent.
+//!
+//! Un exposant (voir `scalar`) ne sort en clair que vers l'entrée d'un circuit,
+//! écrite dans un fichier lisible par le seul joueur, écrasé de zéros avant
+//! d'être supprimé. Le témoin calculé à partir de cette entrée contient aussi
+//! les exposants et suit le même sort, sur le disque comme en mémoire.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use halo2curves::bn256::Fr;
+use halo2curves::ff::Field;
+use nova_scotia::circom::circuit::CircomCircuit;
+use tempfile::NamedTempFile;
+
+use crate::randomness::{random_nonce, Nonce, SecretRng};
+use crate::scalar::Scalar;
+
+/// Ce que le joueur ne partage jamais, à l'écart du reste de son état.
+pub struct Secrets {
+    rng: SecretRng,
+}
+
+impl Secrets {
+    pub fn new(rng: SecretRng) -> Secrets {
+        Secrets { rng }
+    }
+
+    /// Exposant uniforme parmi les scalaires non nuls.
+    pub fn exponent(&mut self) -> Scalar {
+        Scalar::random(&mut self.rng)
+    }
+
+    /// Exposants de la PSI, un par case.
+    pub fn exponents(&mut self, count: usize) -> Vec<Scalar> {
+        (0..count).map(|_| self.exponent()).collect()
+    }
+
+    pub fn nonce(&mut self) -> Nonce {
+        random_nonce(&mut self.rng)
+    }
+}
+
+/// Fichier d'entrée ou de témoin d'un circuit, lisible par le seul joueur et
+/// écrasé de zéros avant d'être supprimé.
+pub struct SecretFile(NamedTempFile);
+
+impl SecretFile {
+    /// `NamedTempFile` crée déjà le fichier en mode 0600.
+    pub fn new() -> io::Result<SecretFile> {
+        NamedTempFile::new().map(SecretFile)
+    }
+
+    pub fn with_contents(contents: &[u8]) -> io::Result<SecretFile> {
+        let mut file = SecretFile::new()?;
+        file.0.write_all(contents)?;
+        file.0.flush()?;
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    /// Écrase le fichier tel qu'il est sur le disque : le circuit a pu le
+    /// réécrire par son chemin depuis sa création.
+    fn overwrite(&self) -> io::Result<()> {
+        let mut file = File::options().write(true).open(self.path())?;
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut io::repeat(0).take(len), &mut file)?;
+        file.sync_all()
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        if let Err(error) = self.overwrite() {
+            eprintln!(
+                "Impossible d'effacer le fichier secret {:?} : {}",
+                self.path(),
+                error
+            );
+        }
+        // `NamedTempFile` le supprime ensuite.
+    }
+}
+
+/// Étape de la phase 2 confiée au fil de preuve. Son témoin est écrasé de
+/// zéros à sa destruction, que l'étape ait été repliée ou non ; les copies que
+/// Nova en fait pendant le repliement nous échappent. `Fr` n'implémente pas
+/// `Zeroize` : on réaffecte zéro à chaque valeur.
+pub struct SecretCircuit(pub CircomCircuit<Fr>);
+
+impl Drop for SecretCircuit {
+    fn drop(&mut self) {
+        if let Some(witness) = &mut self.0.witness {
+            witness.fill(Fr::ZERO);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::panic;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Second nom du fichier, qui permet de relire son contenu après sa
+    /// suppression.
+    fn link(file: &SecretFile, dir: &Path) -> PathBuf {
+        let link = dir.join("lien");
+        fs::hard_link(file.path(), &link).unwrap();
+        link
+    }
+
+    #[test]
+    fn file_is_wiped_and_removed_after_use() {
+        let dir = tempfile::tempdir_in(std::env::temp_dir()).unwrap();
+        let file = SecretFile::with_contents(b"exposants").unwrap();
+        let path = file.path().to_path_buf();
+        let link = link(&file, dir.path());
+        assert_eq!(fs::read(&path).unwrap(), b"exposants");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Le circuit réécrit le fichier par son chemin, plus long qu'avant.
+        fs::write(&path, b"temoin plus long").unwrap();
+        drop(file);
+        assert!(!path.exists());
+        assert_eq!(fs::read(&link).unwrap(), [0; 16]);
+    }
+
+    #[test]
+    fn file_is_wiped_and_removed_on_error() {
+        let dir = tempfile::tempdir_in(std::env::temp_dir()).unwrap();
+        let result = panic::catch_unwind(|| {
+            let file = SecretFile::with_contents(b"exposants").unwrap();
+            let path = file.path().to_path_buf();
+            let link = link(&file, dir.path());
+            // Erreur pendant que le fichier est encore ouvert.
+            panic::resume_unwind(Box::new((path, link)));
+        });
+        let (path, link) = *result
+            .unwrap_err()
+            .downcast::<(PathBuf, PathBuf)>()
+            .unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read(&link).unwrap(), [0; 9]);
+
+        // Un fichier déjà supprimé ne fait pas échouer l'effacement.
+        let file = SecretFile::new().unwrap();
+        fs::remove_file(file.path()).unwrap();
+        drop(file);
+    }
+}
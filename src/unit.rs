@@ -1,41 +1,115 @@
+use crate::pathfinding::MovementType;
 use crate::Square;
 use serde::{Serialize, Serializer};
 
-#[derive(Copy, Clone)]
+/// Types de troupes, dans l'ordre des paramètres `hp_troupes`, `range_troupes`
+/// et `prix_troupes` des circuits : le commandant (guerrier orc), puis les
+/// recrues des nordiques dans l'ordre proposé par le jeu.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Unit {
     None,
     OrcCommander,
-    OrcSoldier,
+    OrcishArcher,
+    OrcishAssassin,
+    NagaFighter,
+    OrcishGrunt,
+    TrollWhelp,
+    WolfRider,
+    GoblinSpearman,
 }
 
 impl Unit {
+    pub const ALL: [Unit; 9] = [
+        Unit::None,
+        Unit::OrcCommander,
+        Unit::OrcishArcher,
+        Unit::OrcishAssassin,
+        Unit::NagaFighter,
+        Unit::OrcishGrunt,
+        Unit::TrollWhelp,
+        Unit::WolfRider,
+        Unit::GoblinSpearman,
+    ];
+
     pub fn is_commander(&self) -> bool {
+        matches!(self, Unit::OrcCommander)
+    }
+
+    /// Points de vie d'une unité fraîchement recrutée (`hp_troupes`).
+    pub fn max_health(&self) -> u64 {
         match self {
-            Unit::OrcCommander => true,
-            _ => false,
+            Unit::None => 0,
+            Unit::OrcCommander => 58,
+            Unit::OrcishArcher => 32,
+            Unit::OrcishAssassin => 26,
+            Unit::NagaFighter => 33,
+            Unit::OrcishGrunt => 38,
+            Unit::TrollWhelp => 42,
+            Unit::WolfRider => 32,
+            Unit::GoblinSpearman => 18,
         }
     }
 
-    pub fn default_square(&self) -> Square {
+    /// Points de mouvement en début de tour, qui servent aussi de portée de
+    /// vision (`range_troupes`).
+    pub fn movement(&self) -> u64 {
+        match self {
+            Unit::None => 0,
+            Unit::OrcCommander => 5,
+            Unit::OrcishArcher => 5,
+            Unit::OrcishAssassin => 6,
+            Unit::NagaFighter => 7,
+            Unit::OrcishGrunt => 5,
+            Unit::TrollWhelp => 4,
+            Unit::WolfRider => 8,
+            Unit::GoblinSpearman => 5,
+        }
+    }
+
+    pub fn movement_type(&self) -> MovementType {
         match self {
-            Unit::None => Square {
-                unit: Unit::None,
-                health_points: 0,
-                captured: false,
-                move_credits: 0,
-            },
-            Unit::OrcCommander => Square {
-                unit: Unit::OrcCommander,
-                health_points: 58,
-                captured: false,
-                move_credits: 5,
-            },
-            Unit::OrcSoldier => Square {
-                unit: Unit::OrcSoldier,
-                health_points: 12,
-                captured: false,
-                move_credits: 5,
-            },
+            Unit::NagaFighter => MovementType::Naga,
+            Unit::TrollWhelp => MovementType::LargeFoot,
+            _ => MovementType::OrcishFoot,
+        }
+    }
+
+    /// Prix de recrutement (`prix_troupes`), `None` pour ce qui ne se recrute pas.
+    pub fn cost(&self) -> Option<u64> {
+        match self {
+            Unit::None | Unit::OrcCommander => None,
+            Unit::OrcishArcher => Some(14),
+            Unit::OrcishAssassin => Some(17),
+            Unit::NagaFighter => Some(14),
+            Unit::OrcishGrunt => Some(12),
+            Unit::TrollWhelp => Some(13),
+            Unit::WolfRider => Some(17),
+            Unit::GoblinSpearman => Some(9),
+        }
+    }
+
+    /// Unité dont l'identifiant est `id` dans Wesnoth, attribut `type` des
+    /// sauvegardes.
+    pub fn from_wesnoth_id(id: &str) -> Option<Unit> {
+        match id {
+            "Orcish Warrior" => Some(Unit::OrcCommander),
+            "Orcish Archer" => Some(Unit::OrcishArcher),
+            "Orcish Assassin" => Some(Unit::OrcishAssassin),
+            "Naga Fighter" => Some(Unit::NagaFighter),
+            "Orcish Grunt" => Some(Unit::OrcishGrunt),
+            "Troll Whelp" => Some(Unit::TrollWhelp),
+            "Wolf Rider" => Some(Unit::WolfRider),
+            "Goblin Spearman" => Some(Unit::GoblinSpearman),
+            _ => None,
+        }
+    }
+
+    pub fn default_square(&self) -> Square {
+        Square {
+            unit: *self,
+            health_points: self.max_health(),
+            captured: false,
+            move_credits: self.movement(),
         }
     }
 }
@@ -44,12 +118,10 @@ impl TryFrom<u64> for Unit {
     type Error = String;
 
     fn try_from(value: u64) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(Unit::None),
-            1 => Ok(Unit::OrcCommander),
-            2 => Ok(Unit::OrcSoldier),
-            _ => Err("Invalid unit id.".to_string()),
-        }
+        Unit::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or_else(|| "Invalid unit id.".to_string())
     }
 }
 
@@ -64,11 +136,7 @@ impl Serialize for Unit {
 
 impl From<&Unit> for u64 {
     fn from(value: &Unit) -> Self {
-        match value {
-            Unit::None => 0,
-            Unit::OrcCommander => 1,
-            Unit::OrcSoldier => 2,
-        }
+        *value as u64
     }
 }
 
@@ -0,0 +1,214 @@
+//! Brouillard de guerre, calculé comme le template `Vision` des circuits.
+//!
+//! Une case est visible si une de nos unités est à une distance comprise entre
+//! 1 et sa portée, qui est son déplacement maximal. Comme dans le circuit, la
+//! case d'une unité n'est pas vue par l'unité elle-même.
+
+use crate::hex::Grid;
+use crate::unit::Unit;
+use crate::{CircuitState, HiddenData, InitialState, Position};
+
+/// Unité adverse révélée par la PSI à la fin de la phase 3.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct VisibleEnemy {
+    pub position: Position,
+    pub unit: Unit,
+    pub health_points: u64,
+    /// L'adversaire possède le village sous l'unité.
+    pub captured: bool,
+}
+
+/// Décode les données révélées par la phase 3, pour les cases dont le tag
+/// correspond. Renvoie les unités adverses vues et les cases dont les données
+/// ne décrivent aucune unité possible, que seul un adversaire tricheur a pu
+/// envoyer.
+pub fn reveal_enemies(
+    grid: &Grid,
+    revealed: impl IntoIterator<Item = (usize, HiddenData)>,
+) -> (Vec<VisibleEnemy>, Vec<Position>) {
+    let mut enemies = Vec::new();
+    let mut forged = Vec::new();
+    for (index, (unit, health_points, captured)) in revealed {
+        let position = grid.position(index);
+        let decoded = u64::try_from(unit).ok().and_then(|unit| {
+            Some((
+                Unit::try_from(unit).ok()?,
+                u64::try_from(health_points).ok()?,
+                match u64::try_from(captured).ok()? {
+                    0 => false,
+                    1 => true,
+                    _ => return None,
+                },
+            ))
+        });
+        match decoded {
+            Some((Unit::None, _, _)) => {}
+            Some((unit, health_points, captured)) => enemies.push(VisibleEnemy {
+                position,
+                unit,
+                health_points,
+                captured,
+            }),
+            None => forged.push(position),
+        }
+    }
+    (enemies, forged)
+}
+
+impl CircuitState {
+    /// Cases visibles, dans l'ordre des indices des cases : c'est la sortie de
+    /// `Vision`, qui décide des identifiants envoyés en clair dans la PSI.
+    pub fn vision(&self, map: &InitialState) -> Vec<bool> {
+        let grid = map.grid();
+        let mut visible = vec![false; grid.len()];
+        for (index, square) in self.squares.iter().enumerate() {
+            if square.unit == Unit::None {
+                continue;
+            }
+            let seer = grid.position(index);
+            for radius in 1..=square.unit.movement() {
+                for position in grid.ring(seer, radius) {
+                    visible[grid.index(position)] = true;
+                }
+            }
+        }
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use num_bigint::BigUint;
+
+    use super::{reveal_enemies, VisibleEnemy};
+    use crate::hex::tests::circuit::neighbour_rect;
+    use crate::unit::{Commander, Unit};
+    use crate::{CircuitState, HiddenData, InitialState};
+
+    /// Reprise du template `Vision`, avec ses indices et son parcours des
+    /// anneaux.
+    fn circuit_vision(
+        state_height: i64,
+        state_width: i64,
+        max_radius: i64,
+        sightrange: &[u64],
+    ) -> Vec<bool> {
+        let mut out = vec![false; (state_height * state_width) as usize];
+        for x in 0..state_height {
+            for y in 0..state_width {
+                let mut interm_or = false;
+                for rayon in 1..=max_radius {
+                    let mut cur_coord_rect = [x, y + rayon];
+                    for orientation in 0..6 {
+                        for _ in 0..rayon {
+                            if cur_coord_rect[0] < state_height
+                                && 0 <= cur_coord_rect[0]
+                                && cur_coord_rect[1] < state_width
+                                && 0 <= cur_coord_rect[1]
+                            {
+                                let seer = cur_coord_rect[0] * state_height + cur_coord_rect[1];
+                                interm_or |= rayon as u64 <= sightrange[seer as usize];
+                            }
+                            cur_coord_rect = neighbour_rect(cur_coord_rect, orientation);
+                        }
+                    }
+                }
+                out[(x * state_width + y) as usize] = interm_or;
+            }
+        }
+        out
+    }
+
+    fn random_state(rng: &mut StdRng, unit_count: usize) -> CircuitState {
+        let mut squares = vec![Unit::None.default_square(); 100];
+        for _ in 0..unit_count {
+            let unit = Unit::ALL[rng.gen_range(1..Unit::ALL.len())];
+            squares[rng.gen_range(0..100)] = unit.default_square();
+        }
+        CircuitState {
+            squares,
+            gold_amount: 0,
+            captured_village_count: 0,
+            current_upkeep_costs: 0,
+        }
+    }
+
+    #[test]
+    fn vision_matches_circuit() {
+        let map = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+        let max_radius = Unit::ALL.iter().map(Unit::movement).max().unwrap() as i64;
+        let mut rng = StdRng::seed_from_u64(34);
+        for unit_count in [0, 1, 1, 2, 3, 5, 8, 13] {
+            let state = random_state(&mut rng, unit_count);
+            let sightrange: Vec<u64> = state.squares.iter().map(|s| s.unit.movement()).collect();
+            assert_eq!(
+                state.vision(&map),
+                circuit_vision(10, 10, max_radius, &sightrange)
+            );
+        }
+    }
+
+    #[test]
+    fn unit_does_not_see_its_own_square() {
+        let map = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = random_state(&mut rng, 0);
+        state.squares[0] = Unit::OrcCommander.default_square();
+        let vision = state.vision(&map);
+        assert!(!vision[0]);
+        // Portée de 5 depuis le coin.
+        assert!(vision[map.index((5, 0))]);
+        assert!(!vision[map.index((6, 0))]);
+    }
+
+    fn data(unit: u64, health_points: u64, captured: u64) -> HiddenData {
+        (unit.into(), health_points.into(), captured.into())
+    }
+
+    #[test]
+    fn reveal_decodes_units_and_skips_empty_squares() {
+        let map = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+        let grid = map.grid();
+        let grunt = Unit::ALL
+            .iter()
+            .position(|&unit| unit == Unit::OrcishGrunt)
+            .unwrap();
+        let (enemies, forged) = reveal_enemies(
+            &grid,
+            [(12, data(grunt as u64, 20, 1)), (13, data(0, 0, 0))],
+        );
+        assert_eq!(
+            enemies,
+            [VisibleEnemy {
+                position: (1, 2),
+                unit: Unit::OrcishGrunt,
+                health_points: 20,
+                captured: true,
+            }]
+        );
+        assert!(forged.is_empty());
+    }
+
+    #[test]
+    fn reveal_reports_forged_data_without_panicking() {
+        let map = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+        let grid = map.grid();
+        let unknown = Unit::ALL.len() as u64;
+        let (enemies, forged) = reveal_enemies(
+            &grid,
+            [
+                (12, data(unknown, 20, 0)),
+                (13, data(u64::MAX, 20, 0)),
+                (14, (BigUint::from(1u8) << 64, 20u8.into(), 0u8.into())),
+                (15, data(1, 20, 2)),
+                (16, data(1, 20, 0)),
+            ],
+        );
+        assert_eq!(forged, [(1, 2), (1, 3), (1, 4), (1, 5)]);
+        assert_eq!(enemies.len(), 1);
+        assert_eq!(enemies[0].position, (1, 6));
+    }
+}
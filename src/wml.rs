@@ -0,0 +1,942 @@
+//! Import des replays de Wesnoth comme tours scriptés.
+//!
+//! Une sauvegarde ou un replay non compressé est un document WML : des balises
+//! `[nom]`…`[/nom]` et des attributs `clé=valeur`. Les actions sont les
+//! `[command]` des `[replay]`, dans l'ordre où elles ont été jouées. On en garde
+//! les déplacements, recrutements et attaques, et `[init_side]` et `[end_turn]`
+//! découpent les tours de chaque côté. Le reste (discussion, tirages, contrôles
+//! de synchronisation) est ignoré.
+//!
+//! Un déplacement garde le chemin suivi dans Wesnoth, dont on paie le terrain.
+//! Une attaque ne garde que les cases et l'arme : le défenseur et ses points de
+//! vie sont ceux que révèle la vision quand le tour est joué.
+//!
+//! Wesnoth ne note pas les captures : un déplacement qui finit sur un village
+//! qui n'est pas encore à nous le capture, et on ajoute l'action
+//! `CaptureVillage` correspondante.
+//!
+//! Les coordonnées de Wesnoth commencent à 1, sans la bordure de la carte, et
+//! suivent déjà la disposition odd-q de `hex` : la case `(x, y)` de Wesnoth est
+//! notre position `(x - 1, y - 1)`. Les messages d'erreur gardent celles de
+//! Wesnoth.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::pathfinding::Path;
+use crate::rules::Scripted;
+use crate::unit::Unit;
+use crate::{InitialState, Position, Transaction, Turn};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WmlError {
+    Syntax {
+        line: usize,
+        message: &'static str,
+    },
+    UnexpectedClose {
+        line: usize,
+        tag: String,
+    },
+    Unclosed(String),
+    NoReplay,
+    Missing {
+        tag: &'static str,
+        key: &'static str,
+    },
+    BadValue {
+        key: &'static str,
+        value: String,
+    },
+    UnknownSide(String),
+    UnknownUnit(String),
+    /// Les rappels n'existent pas dans nos règles.
+    Recall,
+    OutOfMap(Position),
+    NotACastle(Position),
+    UnknownDefender(Position),
+    MapSize {
+        expected: (u64, u64),
+        found: (u64, u64),
+    },
+    /// Villages ou donjons ailleurs que sur notre carte.
+    MapFeatures,
+}
+
+/// Position telle que l'affiche Wesnoth.
+fn wesnoth((x, y): Position) -> String {
+    format!("{},{}", x.wrapping_add(1), y.wrapping_add(1))
+}
+
+impl fmt::Display for WmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WmlError::Syntax { line, message } => write!(f, "ligne {} : {}", line, message),
+            WmlError::UnexpectedClose { line, tag } => {
+                write!(
+                    f,
+                    "ligne {} : [/{}] ne ferme aucune balise ouverte",
+                    line, tag
+                )
+            }
+            WmlError::Unclosed(tag) => write!(f, "la balise [{}] n'est jamais fermée", tag),
+            WmlError::NoReplay => write!(f, "aucune action [replay] dans le fichier"),
+            WmlError::Missing { tag, key } => {
+                write!(f, "il manque {} dans [{}]", key, tag)
+            }
+            WmlError::BadValue { key, value } => {
+                write!(f, "valeur {:?} invalide pour {}", value, key)
+            }
+            WmlError::UnknownSide(side) => {
+                write!(f, "le côté {} n'existe pas, seuls 1 et 2 jouent", side)
+            }
+            WmlError::UnknownUnit(id) => write!(f, "l'unité {:?} n'est pas jouable ici", id),
+            WmlError::Recall => write!(f, "les rappels d'unités ne sont pas gérés"),
+            WmlError::OutOfMap(position) => {
+                write!(f, "la case {} est hors de la carte", wesnoth(*position))
+            }
+            WmlError::NotACastle(position) => write!(
+                f,
+                "recrutement en {}, qui n'est pas un château du donjon",
+                wesnoth(*position)
+            ),
+            WmlError::UnknownDefender(position) => {
+                write!(f, "aucune unité adverse connue en {}", wesnoth(*position))
+            }
+            WmlError::MapSize { expected, found } => write!(
+                f,
+                "carte de {}x{} cases au lieu de {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            WmlError::MapFeatures => write!(
+                f,
+                "les villages ou les donjons ne sont pas ceux de notre carte"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WmlError {}
+
+/// Balise WML, avec ses attributs dans l'ordre du document et ses enfants.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    /// Valeur d'un attribut. Comme dans Wesnoth, la dernière affectation
+    /// l'emporte.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .rev()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Premier attribut `key` de l'arbre, en profondeur.
+    fn find(&self, key: &str) -> Option<&str> {
+        self.get(key)
+            .or_else(|| self.children.iter().find_map(|child| child.find(key)))
+    }
+
+    /// Balises `name` de l'arbre dans l'ordre du document, sans chercher à
+    /// l'intérieur de celles trouvées.
+    fn collect<'a>(&'a self, name: &str, found: &mut Vec<&'a Node>) {
+        for child in &self.children {
+            if child.name == name {
+                found.push(child);
+            } else {
+                child.collect(name, found);
+            }
+        }
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    offset: usize,
+    line: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.offset).copied()
+    }
+
+    fn starts_with(&self, pattern: &str) -> bool {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(shift, c)| self.chars.get(self.offset + shift) == Some(&c))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn syntax(&self, message: &'static str) -> WmlError {
+        WmlError::Syntax {
+            line: self.line,
+            message,
+        }
+    }
+
+    /// Saute les blancs de la ligne, sans passer à la suivante.
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\r')) {
+            self.next();
+        }
+    }
+
+    /// Saute les blancs, les lignes vides et les commentaires.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                }
+                Some('#') => while self.next().is_some_and(|c| c != '\n') {},
+                _ => return,
+            }
+        }
+    }
+
+    /// Texte jusqu'à `end`, sur la ligne en cours.
+    fn until(&mut self, end: char, message: &'static str) -> Result<String, WmlError> {
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                Some(c) if c == end => {
+                    self.next();
+                    return Ok(text);
+                }
+                None | Some('\n') => return Err(self.syntax(message)),
+                Some(c) => {
+                    self.next();
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    /// Chaîne entre guillemets, éventuellement sur plusieurs lignes, où `""`
+    /// est un guillemet.
+    fn quoted(&mut self) -> Result<String, WmlError> {
+        let start = self.syntax("chaîne jamais terminée");
+        self.next();
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                None => return Err(start),
+                Some('"') if self.peek() == Some('"') => {
+                    self.next();
+                    text.push('"');
+                }
+                Some('"') => return Ok(text),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// Valeur d'un attribut : un texte brut jusqu'à la fin de la ligne, ou des
+    /// chaînes `"…"` et `<<…>>`, traduisibles avec `_`, concaténées par `+`.
+    fn value(&mut self) -> Result<String, WmlError> {
+        let mut value = String::new();
+        loop {
+            self.skip_spaces();
+            if self.starts_with("_ \"") || self.starts_with("_\"") {
+                self.next();
+                self.skip_spaces();
+            }
+            if self.peek() == Some('"') {
+                value.push_str(&self.quoted()?);
+            } else if self.starts_with("<<") {
+                let start = self.syntax("chaîne << jamais terminée");
+                self.offset += 2;
+                while !self.starts_with(">>") {
+                    value.push(self.next().ok_or(start.clone())?);
+                }
+                self.offset += 2;
+            } else {
+                let mut text = String::new();
+                while let Some(c) = self.peek().filter(|&c| c != '\n') {
+                    self.next();
+                    text.push(c);
+                }
+                value.push_str(text.trim());
+                return Ok(value);
+            }
+            self.skip_spaces();
+            if self.peek() != Some('+') {
+                break;
+            }
+            self.next();
+            self.skip_blank();
+        }
+        if self.peek() == Some('#') {
+            while self.peek().is_some_and(|c| c != '\n') {
+                self.next();
+            }
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(value),
+            Some(_) => Err(self.syntax("texte après la valeur")),
+        }
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Lit un document WML déjà passé par le préprocesseur, comme une sauvegarde.
+/// La racine renvoyée n'a pas de nom.
+pub fn parse(text: &str) -> Result<Node, WmlError> {
+    let mut reader = Reader {
+        chars: text.chars().collect(),
+        offset: 0,
+        line: 1,
+    };
+    let mut stack = vec![Node::default()];
+    loop {
+        reader.skip_blank();
+        let Some(c) = reader.peek() else {
+            break;
+        };
+        if c == '[' {
+            let line = reader.line;
+            reader.next();
+            let tag = reader.until(']', "balise jamais fermée par ]")?;
+            if let Some(name) = tag.strip_prefix('/') {
+                if stack.len() == 1 || stack.last().unwrap().name != name {
+                    return Err(WmlError::UnexpectedClose {
+                        line,
+                        tag: name.to_string(),
+                    });
+                }
+                let node = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(node);
+                continue;
+            }
+            let (amend, name) = match tag.strip_prefix('+') {
+                Some(name) => (true, name),
+                None => (false, tag.as_str()),
+            };
+            if !valid_name(name) {
+                return Err(reader.syntax("nom de balise invalide"));
+            }
+            // `[+nom]` rouvre la dernière balise `nom` pour la compléter.
+            let parent = stack.last_mut().unwrap();
+            let reopened = amend
+                .then(|| parent.children.iter().rposition(|child| child.name == name))
+                .flatten()
+                .map(|index| parent.children.remove(index));
+            stack.push(reopened.unwrap_or(Node {
+                name: name.to_string(),
+                ..Node::default()
+            }));
+        } else {
+            let keys = reader.until('=', "ligne qui n'est ni une balise ni un attribut")?;
+            let keys: Vec<&str> = keys.split(',').map(str::trim).collect();
+            if !keys.iter().all(|key| valid_name(key)) {
+                return Err(reader.syntax("nom d'attribut invalide"));
+            }
+            let value = reader.value()?;
+            // `x,y=1,2` affecte chaque clé, la dernière gardant le reste.
+            let mut values = value.splitn(keys.len(), ',');
+            let node = stack.last_mut().unwrap();
+            for key in keys.iter() {
+                let value = if keys.len() == 1 {
+                    value.clone()
+                } else {
+                    values.next().unwrap_or_default().to_string()
+                };
+                node.attributes.push((key.to_string(), value));
+            }
+        }
+    }
+    if stack.len() > 1 {
+        return Err(WmlError::Unclosed(stack.pop().unwrap().name));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+/// Vérifie que `map_data` décrit notre carte : même taille une fois retirée
+/// la bordure d'une case, villages et donjons aux mêmes endroits.
+fn check_map(map_data: &str, map: &InitialState) -> Result<(), WmlError> {
+    let rows: Vec<Vec<&str>> = map_data
+        .lines()
+        .map(str::trim)
+        // Les anciennes cartes commencent par un en-tête `clé=valeur`.
+        .filter(|line| !line.is_empty() && !line.contains('='))
+        .map(|line| line.split(',').map(str::trim).collect())
+        .collect();
+    let width = rows.first().map_or(0, Vec::len);
+    let found = (
+        width.saturating_sub(2) as u64,
+        rows.len().saturating_sub(2) as u64,
+    );
+    if found != map.size() || rows.iter().any(|row| row.len() != width) {
+        return Err(WmlError::MapSize {
+            expected: map.size(),
+            found,
+        });
+    }
+
+    let (mut villages, mut keeps) = (Vec::new(), Vec::new());
+    for (y, row) in rows[1..rows.len() - 1].iter().enumerate() {
+        for (x, code) in row[1..width - 1].iter().enumerate() {
+            // Un départ de côté précède le terrain : `1 Kh`.
+            let terrain = code.rsplit(' ').next().unwrap();
+            let index = map.index((x as u64, y as u64));
+            if terrain.contains("^V") {
+                villages.push(index);
+            }
+            if terrain.starts_with('K') {
+                keeps.push(index);
+            }
+        }
+    }
+    let sorted = |positions: &[usize]| {
+        let mut positions = positions.to_vec();
+        positions.sort_unstable();
+        positions
+    };
+    if sorted(&villages) != sorted(map.village_positions()) || sorted(&keeps) != sorted(map.keeps())
+    {
+        return Err(WmlError::MapFeatures);
+    }
+    Ok(())
+}
+
+/// Suit la partie de Wesnoth commande après commande pour écrire les tours de
+/// chaque côté.
+struct Importer<'a> {
+    map: &'a InitialState,
+    side: usize,
+    current: Turn,
+    turns: [Vec<Turn>; 2],
+    /// Dernière unité vue sur chaque case, avec son côté. Les morts n'y sont
+    /// pas retirés, le replay ne disant pas qui gagne les combats.
+    units: HashMap<Position, (usize, Unit)>,
+    /// Côté qui possède chaque village.
+    owners: Vec<Option<usize>>,
+}
+
+fn required<'a>(node: &'a Node, tag: &'static str, key: &'static str) -> Result<&'a str, WmlError> {
+    node.get(key).ok_or(WmlError::Missing { tag, key })
+}
+
+fn number(key: &'static str, value: &str) -> Result<u64, WmlError> {
+    value.trim().parse().map_err(|_| WmlError::BadValue {
+        key,
+        value: value.to_string(),
+    })
+}
+
+impl Importer<'_> {
+    fn position(&self, x: u64, y: u64) -> Result<Position, WmlError> {
+        let (width, height) = self.map.size();
+        if x == 0 || y == 0 || x > width || y > height {
+            // Hors carte, `wesnoth` rajoute 1 à chaque coordonnée.
+            return Err(WmlError::OutOfMap((x.wrapping_sub(1), y.wrapping_sub(1))));
+        }
+        Ok((x - 1, y - 1))
+    }
+
+    /// Case donnée par les attributs `x` et `y` d'une balise.
+    fn square(&self, node: &Node, tag: &'static str) -> Result<Position, WmlError> {
+        let x = number("x", required(node, tag, "x")?)?;
+        let y = number("y", required(node, tag, "y")?)?;
+        self.position(x, y)
+    }
+
+    fn command(&mut self, command: &Node) -> Result<(), WmlError> {
+        for action in &command.children {
+            match action.name.as_str() {
+                "init_side" => {
+                    let side = required(action, "init_side", "side_number")?;
+                    self.side = match side.trim() {
+                        "1" => 0,
+                        "2" => 1,
+                        _ => return Err(WmlError::UnknownSide(side.to_string())),
+                    };
+                }
+                "end_turn" => {
+                    let turn = std::mem::take(&mut self.current);
+                    self.turns[self.side].push(turn);
+                    self.side = 1 - self.side;
+                }
+                "move" => self.move_unit(action)?,
+                "recruit" => self.recruit(action)?,
+                "attack" => self.attack(action)?,
+                "recall" => return Err(WmlError::Recall),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn move_unit(&mut self, action: &Node) -> Result<(), WmlError> {
+        let coordinates = |key| {
+            required(action, "move", key)?
+                .split(',')
+                .map(|value| number(key, value))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let (xs, ys) = (coordinates("x")?, coordinates("y")?);
+        if xs.len() != ys.len() {
+            return Err(WmlError::BadValue {
+                key: "y",
+                value: required(action, "move", "y")?.to_string(),
+            });
+        }
+        let path = xs
+            .into_iter()
+            .zip(ys)
+            .map(|(x, y)| self.position(x, y))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (Some(&from), Some(&to)) = (path.first(), path.last()) else {
+            return Ok(());
+        };
+        if from == to {
+            return Ok(());
+        }
+        self.current.push(Scripted::Move(Path { steps: path }));
+        if let Some(unit) = self.units.remove(&from) {
+            self.units.insert(to, unit);
+        }
+
+        let index = self.map.index(to);
+        if let Some(village) = self
+            .map
+            .village_positions()
+            .iter()
+            .position(|&village| village == index)
+        {
+            if self.owners[village] != Some(self.side) {
+                self.owners[village] = Some(self.side);
+                self.current
+                    .push(Scripted::Transaction(Transaction::CaptureVillage(
+                        village as u64,
+                    )));
+            }
+        }
+        Ok(())
+    }
+
+    fn recruit(&mut self, action: &Node) -> Result<(), WmlError> {
+        let id = required(action, "recruit", "type")?;
+        let unit = Unit::from_wesnoth_id(id)
+            .filter(|unit| unit.cost().is_some())
+            .ok_or_else(|| WmlError::UnknownUnit(id.to_string()))?;
+        let position = self.square(action, "recruit")?;
+        let keep = match action.child("from") {
+            Some(from) => Some(self.map.index(self.square(from, "from")?)),
+            None => None,
+        };
+        let index = self.map.index(position);
+        let castle = self
+            .map
+            .castles()
+            .iter()
+            .position(|&(castle_keep, castle)| {
+                castle == index && keep.is_none_or(|keep| self.map.keeps()[castle_keep] == keep)
+            })
+            .ok_or(WmlError::NotACastle(position))?;
+        self.current
+            .push(Scripted::Transaction(Transaction::PurchaseUnit(
+                castle as u64,
+                unit,
+            )));
+        self.units.insert(position, (self.side, unit));
+        Ok(())
+    }
+
+    fn attack(&mut self, action: &Node) -> Result<(), WmlError> {
+        let source = action.child("source").ok_or(WmlError::Missing {
+            tag: "attack",
+            key: "source",
+        })?;
+        let destination = action.child("destination").ok_or(WmlError::Missing {
+            tag: "attack",
+            key: "destination",
+        })?;
+        let from = self.square(source, "source")?;
+        let to = self.square(destination, "destination")?;
+        let weapon = number("weapon", required(action, "attack", "weapon")?)? as usize;
+        if !matches!(self.units.get(&to), Some(&(side, _)) if side != self.side) {
+            return Err(WmlError::UnknownDefender(to));
+        }
+        self.current.push(Scripted::Attack { from, to, weapon });
+        Ok(())
+    }
+}
+
+/// Tours de chaque côté d'un replay de Wesnoth joué sur `map`, le côté 1 de
+/// Wesnoth étant le premier. Si le fichier contient la carte (`map_data`),
+/// elle doit être la nôtre.
+pub fn import_replay(text: &str, map: &InitialState) -> Result<[Vec<Turn>; 2], WmlError> {
+    let document = parse(text)?;
+    if let Some(map_data) = document.find("map_data") {
+        check_map(map_data, map)?;
+    }
+    let mut replays = Vec::new();
+    document.collect("replay", &mut replays);
+    if replays.is_empty() {
+        return Err(WmlError::NoReplay);
+    }
+
+    let grid = map.grid();
+    let commanders = match map {
+        InitialState::Nordic(_, a, b) => [Unit::from(*a), Unit::from(*b)],
+    };
+    let mut importer = Importer {
+        map,
+        side: 0,
+        current: Turn::new(),
+        turns: [Vec::new(), Vec::new()],
+        units: map
+            .keeps()
+            .iter()
+            .zip(commanders)
+            .enumerate()
+            .map(|(side, (&keep, commander))| (grid.position(keep), (side, commander)))
+            .collect(),
+        owners: vec![None; map.village_positions().len()],
+    };
+    for replay in replays {
+        for command in replay.children("command") {
+            importer.command(command)?;
+        }
+    }
+    if !importer.current.is_empty() {
+        let turn = std::mem::take(&mut importer.current);
+        importer.turns[importer.side].push(turn);
+    }
+    Ok(importer.turns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::Attack;
+    use crate::rules::Plan;
+    use crate::unit::Commander;
+    use crate::vision::VisibleEnemy;
+    use crate::CircuitState;
+
+    const MAP: InitialState = InitialState::Nordic((10, 10), Commander::Orc, Commander::Orc);
+
+    const REPLAY: &str = r#"
+#textdomain wesnoth
+version="1.16.0"
+[replay]
+    [command]
+        [init_side]
+            side_number=1
+        [/init_side]
+    [/command]
+    [command]
+        [recruit]
+            type="Orcish Grunt"
+            x,y=1,2
+            [from]
+                x=1
+                y=1
+            [/from]
+        [/recruit]
+    [/command]
+    [command]
+        [move]
+            x="1,1,1,1,1"
+            y="2,3,4,5,6"
+        [/move]
+    [/command]
+    [command]
+        [end_turn]
+        [/end_turn]
+    [/command]
+    [command]
+        [init_side]
+            side_number=2
+        [/init_side]
+    [/command]
+    [command]
+        [speak]
+            message= _ "bonne " +
+                "chance"
+        [/speak]
+        [recruit]
+            type="Wolf Rider"
+            x=10
+            y=9
+        [/recruit]
+    [/command]
+    [command]
+        [attack]
+            weapon=0
+            [source]
+                x,y=10,9
+            [/source]
+            [destination]
+                x,y=1,6
+            [/destination]
+        [/attack]
+    [/command]
+[/replay]
+"#;
+
+    #[test]
+    fn parse_values() {
+        let document = parse("[a]\n  k=\"un \"\"mot\"\"\" + _ \"et\n deux\" # fin\n  x,y=3,4\n[/a]\n[+a]\nk=<<brut>>\n[/a]").unwrap();
+        let a = document.child("a").unwrap();
+        assert_eq!(document.children.len(), 1);
+        assert_eq!(a.get("k"), Some("brut"));
+        assert_eq!(a.attributes[0].1, "un \"mot\"et\n deux");
+        assert_eq!((a.get("x"), a.get("y")), (Some("3"), Some("4")));
+
+        assert_eq!(
+            parse("[a]\n[b]\n[/a]"),
+            Err(WmlError::UnexpectedClose {
+                line: 3,
+                tag: "a".to_string()
+            })
+        );
+        assert_eq!(parse("[a]\n"), Err(WmlError::Unclosed("a".to_string())));
+        assert!(matches!(
+            parse("k=\"ouverte\n"),
+            Err(WmlError::Syntax { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn replay_turns() {
+        let [a, b] = import_replay(REPLAY, &MAP).unwrap();
+        assert_eq!(
+            a,
+            [vec![
+                Scripted::Transaction(Transaction::PurchaseUnit(0, Unit::OrcishGrunt)),
+                Scripted::Move(Path {
+                    steps: vec![(0, 1), (0, 2), (0, 3), (0, 4), (0, 5)]
+                }),
+                Scripted::Transaction(Transaction::CaptureVillage(2)),
+            ]]
+        );
+        assert_eq!(
+            b,
+            [vec![
+                Scripted::Transaction(Transaction::PurchaseUnit(4, Unit::WolfRider)),
+                Scripted::Attack {
+                    from: (9, 8),
+                    to: (0, 5),
+                    weapon: 0,
+                },
+            ]]
+        );
+    }
+
+    #[test]
+    fn replay_errors() {
+        let unknown = REPLAY.replace("Wolf Rider", "Elvish Archer");
+        assert_eq!(
+            import_replay(&unknown, &MAP),
+            Err(WmlError::UnknownUnit("Elvish Archer".to_string()))
+        );
+        let outside = REPLAY.replace("y=\"2,3,4,5,6\"", "y=\"2,3,4,5,11\"");
+        assert_eq!(
+            import_replay(&outside, &MAP).unwrap_err().to_string(),
+            "la case 1,11 est hors de la carte"
+        );
+        assert_eq!(
+            import_replay("[scenario]\n[/scenario]", &MAP),
+            Err(WmlError::NoReplay)
+        );
+    }
+
+    #[test]
+    fn map_must_match() {
+        // Bordure comprise, villages et donjons de `Nordic`.
+        let mut rows = vec![vec!["Gg"; 12]; 12];
+        for &index in MAP.village_positions() {
+            let (x, y) = MAP.grid().position(index);
+            rows[y as usize + 1][x as usize + 1] = "Gg^Vo";
+        }
+        rows[1][1] = "1 Kh";
+        rows[10][10] = "2 Kh";
+        let map_data = |rows: &[Vec<&str>]| {
+            rows.iter()
+                .map(|row| row.join(", "))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(check_map(&map_data(&rows), &MAP), Ok(()));
+
+        rows[2][2] = "Gg^Vo";
+        assert_eq!(
+            check_map(&map_data(&rows), &MAP),
+            Err(WmlError::MapFeatures)
+        );
+        assert_eq!(
+            check_map(&map_data(&rows[1..]), &MAP),
+            Err(WmlError::MapSize {
+                expected: (10, 10),
+                found: (10, 9),
+            })
+        );
+    }
+
+    /// Deux tours de chaque côté : les recrues, puis un détour du grunt vers un
+    /// village et le loup qui vient l'attaquer.
+    const SCRIPTED: &str = r#"
+[replay]
+    [command]
+        [init_side]
+            side_number=1
+        [/init_side]
+    [/command]
+    [command]
+        [recruit]
+            type="Orcish Grunt"
+            x,y=1,2
+        [/recruit]
+    [/command]
+    [command]
+        [end_turn]
+        [/end_turn]
+    [/command]
+    [command]
+        [recruit]
+            type="Wolf Rider"
+            x,y=10,9
+        [/recruit]
+    [/command]
+    [command]
+        [end_turn]
+        [/end_turn]
+    [/command]
+    [command]
+        [move]
+            x="1,2,2,1,1,1"
+            y="2,2,3,4,5,6"
+        [/move]
+    [/command]
+    [command]
+        [end_turn]
+        [/end_turn]
+    [/command]
+    [command]
+        [move]
+            x="10,9,8,7,6,5,4,3,2"
+            y="9,9,8,8,7,8,7,7,6"
+        [/move]
+    [/command]
+    [command]
+        [attack]
+            weapon=0
+            [source]
+                x,y=2,6
+            [/source]
+            [destination]
+                x,y=1,6
+            [/destination]
+        [/attack]
+    [/command]
+    [command]
+        [end_turn]
+        [/end_turn]
+    [/command]
+[/replay]
+"#;
+
+    /// Unités de `state`, telles que la vision les révèle à l'adversaire.
+    fn visible(state: &CircuitState) -> Vec<VisibleEnemy> {
+        state
+            .squares
+            .iter()
+            .enumerate()
+            .filter(|(_, square)| square.unit != Unit::None)
+            .map(|(index, square)| VisibleEnemy {
+                position: MAP.grid().position(index),
+                unit: square.unit,
+                health_points: square.health_points,
+                captured: square.captured,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn imported_turns_are_scripted_along_wesnoth_paths() {
+        let [a, b] = import_replay(SCRIPTED, &MAP).unwrap();
+        assert_eq!((a.len(), b.len()), (2, 2));
+
+        let mut states = [0, 99].map(|keep| {
+            let mut squares = vec![Unit::None.default_square(); 100];
+            squares[keep] = Unit::OrcCommander.default_square();
+            CircuitState {
+                squares,
+                gold_amount: 100,
+                captured_village_count: 0,
+                current_upkeep_costs: 0,
+            }
+        });
+        let captures = vec![0; MAP.village_count() as usize];
+        let mut played = Vec::new();
+        for (turn, round) in a.iter().zip(&b).enumerate() {
+            for (side, actions) in <[_; 2]>::from(round).into_iter().enumerate() {
+                let visible = visible(&states[1 - side]);
+                let enemies = visible.iter().map(|enemy| enemy.position).collect();
+                let mut damages = vec![0; 100];
+                if (turn, side) == (1, 0) {
+                    // Le grunt a été blessé : l'attaque doit voir ses points de
+                    // vie réels, pas son maximum.
+                    damages[MAP.index((0, 1))] = 10;
+                }
+                let mut plan = Plan::new(&states[side], &damages, &captures, enemies, &MAP);
+                for action in actions {
+                    plan.push_scripted(action, &visible, &MAP).unwrap();
+                }
+                played.push(plan.transactions().to_vec());
+                states[side] = plan.projected().clone();
+            }
+        }
+
+        // Le détour par (1, 1) et (1, 2) coûte 5, le plus court chemin 4.
+        let grunt = states[0].squares[MAP.index((0, 5))];
+        assert_eq!((grunt.unit, grunt.move_credits), (Unit::OrcishGrunt, 0));
+        assert_eq!(states[0].captured_village_count, 1);
+        let wolf = states[1].squares[MAP.index((1, 5))];
+        assert_eq!((wolf.unit, wolf.move_credits), (Unit::WolfRider, 0));
+
+        assert_eq!(
+            played[3],
+            [
+                Transaction::MoveUnit((9, 8), (1, 5)),
+                Transaction::Attack(Attack {
+                    from: (1, 5),
+                    to: (0, 5),
+                    weapon: 0,
+                    defender: Unit::OrcishGrunt,
+                    defender_health: 28,
+                }),
+            ]
+        );
+    }
+}
@@ -11,7 +11,7 @@ template Main(n) {
   for (var i = 0; i < n; i++) {
     hash.in[i] <== to_hash[i];
   }
-  log(hash.out);
+  log("hash", 0, hash.out);
 }
 
 component main = Main((10 * 10) * 4 + 3);
@@ -7,7 +7,8 @@ include "exchange.circom";
 
 template Final(state_size, state_height, state_width, actions_size, enum_tag, enum_data, mimc_hash_size,
   nb_villages, pos_villages, nb_troupes, hp_troupes, range_troupes, prix_troupes,
-  nb_donjons, donjons, nb_chateaux, chateaux) {
+  nb_donjons, donjons, nb_chateaux, chateaux,
+  base_income, village_gold, village_support, heal_village, heal_rest) {
   assert(state_size == state_height * state_width);
   var max_radius = tabmax(nb_troupes, range_troupes);
   // La troupe 1 correspond au chef
@@ -40,7 +41,7 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
     nokill[i].in[0] <-- prev_state[i][1];
     nokill[i].in[1] <-- degats[i];
     degats_state[i][0] <-- prev_state[i][0] * nokill[i].out;
-    degats_state[i][1] <-- nokill[i].out * (prev_state[i][0] - degats[i]);
+    degats_state[i][1] <-- nokill[i].out * (prev_state[i][1] - degats[i]);
     var ind = indexof(i, nb_villages, pos_villages);
     if (ind != -1) {
       degats_state[i][2] <-- (1 - captures[ind]) * prev_state[i][2];
@@ -66,11 +67,12 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
     }
   }
 
-  // Pertes de villages dans le compte
+  // Pertes de villages dans le compte : l'adversaire annonce toutes ses
+  // captures, seules comptent celles des villages que nous possédions
   signal pertes_villages[nb_villages];
-  pertes_villages[0] <-- captures[0];
+  pertes_villages[0] <-- captures[0] * prev_state[pos_villages[0]][2];
   for (var vill = 1; vill < nb_villages; vill++) {
-    pertes_villages[vill] <-- pertes_villages[vill - 1] + captures[vill];
+    pertes_villages[vill] <-- pertes_villages[vill - 1] + captures[vill] * prev_state[pos_villages[vill]][2];
   }
 
   /* Obtention dès maintenant du déplacement de chaque case */
@@ -82,9 +84,9 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
 
   /* Soin dans les villages en début de tour
   Une unité est soignée passivement de :
-  - 8 points de vie en début de tour si elle est dans un village
-  - 2 points de vie en début de tour si elle n'a pas bougé pendant le dernier
-    tour
+  - heal_village points de vie en début de tour si elle est dans un village
+  - heal_rest points de vie en début de tour si elle n'a pas bougé pendant le
+    dernier tour
   */
   signal final_state[state_size];
   component au_repos[state_size];
@@ -103,17 +105,17 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
     max_health[i] = VillagePos(nb_troupes, hp_troupes);
     max_health[i].index <-- degats_state[i][0];
     if (est_village) {
-      full_heal[i].in[0] <-- degats_state[i][1] + 8 + au_repos[i].out * 2;
+      full_heal[i].in[0] <-- degats_state[i][1] + heal_village + au_repos[i].out * heal_rest;
       full_heal[i].in[1] <-- max_health[i].out;
 
       final_state[i] <-- max_health[i].out +
-        full_heal[i].out * (degats_state[i][1] + 8 + (au_repos[i].out * 2) - max_health[i].out);
+        full_heal[i].out * (degats_state[i][1] + heal_village + (au_repos[i].out * heal_rest) - max_health[i].out);
     } else {
-      full_heal[i].in[0] <-- degats_state[i][1] + au_repos[i].out * 2;
+      full_heal[i].in[0] <-- degats_state[i][1] + au_repos[i].out * heal_rest;
       full_heal[i].in[1] <-- max_health[i].out;
 
       final_state[i] <-- max_health[i].out +
-        full_heal[i].out * (degats_state[i][1] +  (au_repos[i].out * 2) - max_health[i].out);
+        full_heal[i].out * (degats_state[i][1] + (au_repos[i].out * heal_rest) - max_health[i].out);
     }
   }
 
@@ -127,19 +129,21 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
      If the upkeep cost is greater than the number of villages+2 then the side
      starts losing gold, if it is equal, no income is gained or lost.
      """
+     Les 2, le gain par village et l'upkeep supporté par chaque village sont
+     les paramètres base_income, village_gold et village_support.
   */
   signal final_money;
   signal degats_money;
   component pay_upkeep = LessThan(64); // 1 si on paie l'upkeep
-  pay_upkeep.in[0] <-- prev_misc_state[1] - pertes_villages[nb_villages - 1];
+  pay_upkeep.in[0] <-- village_support * (prev_misc_state[1] - pertes_villages[nb_villages - 1]);
   pay_upkeep.in[1] <-- degats_state_upkeep[state_size - 1];
   // TODO : ON PEUT AVOIR DES DETTES, CETTE VERSION TE FERA MOURIR SI TU PAIES
   // PAS TES DETTES POUR L'INSTANT ET C'EST TOUT
   // Gérer avec un flag ?
   component floor_zero = LessThan(64); // 1 si on peut payer sans tomber dans le rouge
-  floor_zero.in[0] <-- pay_upkeep.out * (degats_state_upkeep[state_size - 1] - (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
-  floor_zero.in[1] <-- prev_misc_state[0] + 2 + prev_misc_state[1] - pertes_villages[nb_villages - 1];
-  degats_money <-- prev_misc_state[0] + 2 + prev_misc_state[1] - pertes_villages[nb_villages - 1] - pay_upkeep.out * (degats_state_upkeep[state_size - 1] - (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
+  floor_zero.in[0] <-- pay_upkeep.out * (degats_state_upkeep[state_size - 1] - village_support * (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
+  floor_zero.in[1] <-- prev_misc_state[0] + base_income + village_gold * (prev_misc_state[1] - pertes_villages[nb_villages - 1]);
+  degats_money <-- prev_misc_state[0] + base_income + village_gold * (prev_misc_state[1] - pertes_villages[nb_villages - 1]) - pay_upkeep.out * (degats_state_upkeep[state_size - 1] - village_support * (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
   final_money <-- floor_zero.out * degats_money;
 
   /* Hachage des cases
@@ -171,4 +175,4 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
 //   nb_villages, pos_villages, nb_troupes, hp_troupes, range_troupes, prix_troupes,
 //   nb_donjons, donjons, nb_chateaux, chateaux) {
 
-component main {public [degats, captures]} = Final(10 * 10, 10, 10 , 10, 0, 1, 64, 8, [50,90,5,45,54,94,9,49], 9, [0,58,32,26,33,38,42,32,18], [0,5,5,6,7,5,4,8,5], [0,-1,14,17,14,12,13,17,9], 2, [0, 99], 6, [[0,1], [0,10], [0,20], [1,89], [1,98], [1,79]]);
+component main {public [degats, captures]} = Final(10 * 10, 10, 10, 10, 0, 1, 64, 8, [50,90,5,45,54,94,9,49], 9, [0,58,32,26,33,38,42,32,18], [0,5,5,6,7,5,4,8,5], [0,-1,14,17,14,12,13,17,9], 2, [0,99], 6, [[0,1],[0,10],[0,20],[1,89],[1,98],[1,79]], 2, 2, 1, 8, 2);
@@ -94,11 +94,13 @@ template Vision(state_size, state_height, state_width, max_radius) {
 
 
 /* Cette version imprime les hashed_idents (pour usage personnel) puis les outputs de la phase 1
-On imprime seulement, à chaque ligne, d'abord les hashed_idents dans l'ordre,
-puis l'output de la phase 1, avec les deux coordonnées séparées par un espace,
-sur state_size lignes à chaque fois.
+Chaque ligne commence par son groupe et l'indice de la case, suivis des
+valeurs séparées par des espaces : d'abord les hashed_idents (groupe ident),
+puis l'output de la phase 1 (groupe dh), avec les deux coordonnées.
 Par exemple:
-12151 223154
+ident 3 12151 223154
+Viennent enfin les cases visibles (groupe vision), un booléen par ligne, pour
+que le client vérifie sa propre vision.
 */
 template Phase1(state_size, state_height, state_width, max_radius) {
   signal input sightrange[state_size];
@@ -134,8 +136,11 @@ template Phase1(state_size, state_height, state_width, max_radius) {
     choose_phase1[i].sel <-- can_see.out[i];
     phase1_output[i] <-- choose_phase1[i].out;
   }
+  // Chaque ligne de sortie est « groupe indice valeurs... », voir output.rs
   // Hash de chaque case
-  for (var i = 0; i < state_size; i++) { log(hashed_idents[i][0],hashed_idents[i][1]); }
+  for (var i = 0; i < state_size; i++) { log("ident", i, hashed_idents[i][0], hashed_idents[i][1]); }
   // Sortie
-  for (var i = 0; i < state_size; i++) { log(phase1_output[i][0],phase1_output[i][1]); }
+  for (var i = 0; i < state_size; i++) { log("dh", i, phase1_output[i][0], phase1_output[i][1]); }
+  // Vision
+  for (var i = 0; i < state_size; i++) { log("vision", i, can_see.out[i]); }
 }
@@ -106,7 +106,8 @@ peut pas bouger.
 
 template Final(state_size, state_height, state_width, actions_size, enum_tag, enum_data, mimc_hash_size,
   nb_villages, pos_villages, nb_troupes, hp_troupes, range_troupes, prix_troupes,
-  nb_donjons, donjons, nb_chateaux, chateaux) {
+  nb_donjons, donjons, nb_chateaux, chateaux,
+  base_income, village_gold, village_support, heal_village, heal_rest) {
   assert(state_size == state_height * state_width);
   var max_radius = tabmax(nb_troupes, range_troupes);
   // La troupe 1 correspond au chef
@@ -198,22 +199,24 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
     // TODO: Niveau des unités
     if (i == 0) {
       loyal[0] = IsZero();
-      // 1 si la troupe est loyale ou s'il n'y a pas de troupes
-      loyal[0].in <== degats_state[0][0] * (1 - degats_state[0][0]);
+      // 1 si la troupe est loyale ou s'il n'y avait pas de troupe avant les
+      // dégâts : une troupe tuée est déjà vide dans degats_state
+      loyal[0].in <== prev_state[0][0] * (1 - prev_state[0][0]);
       // degats_state_upkeep[0] <== prev_misc_state[2] - (1 - nokill[0]) * (1 - loyal[0].out);
       degats_state_upkeep[0] <== prev_misc_state[2] - (1 - loyal[0].out) + nokill[0].out * (1 - loyal[0].out);
     } else {
       loyal[i] = IsZero();
-      loyal[i].in <== degats_state[i][0] * (1 - degats_state[i][0]);
+      loyal[i].in <== prev_state[i][0] * (1 - prev_state[i][0]);
       degats_state_upkeep[i] <== degats_state_upkeep[i - 1] - (1 - loyal[i].out) + nokill[i].out * (1 - loyal[i].out);
     }
   }
 
-  // Pertes de villages dans le compte
+  // Pertes de villages dans le compte : l'adversaire annonce toutes ses
+  // captures, seules comptent celles des villages que nous possédions
   signal pertes_villages[nb_villages];
-  pertes_villages[0] <== captures[0];
+  pertes_villages[0] <== captures[0] * prev_state[pos_villages[0]][2];
   for (var vill = 1; vill < nb_villages; vill++) {
-    pertes_villages[vill] <== pertes_villages[vill - 1] + captures[vill];
+    pertes_villages[vill] <== pertes_villages[vill - 1] + captures[vill] * prev_state[pos_villages[vill]][2];
   }
 
   /* Obtention dès maintenant du déplacement de chaque case */
@@ -225,9 +228,9 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
 
   /* Soin dans les villages en début de tour
   Une unité est soignée passivement de :
-  - 8 points de vie en début de tour si elle est dans un village
-  - 2 points de vie en début de tour si elle n'a pas bougé pendant le dernier
-    tour
+  - heal_village points de vie en début de tour si elle est dans un village
+  - heal_rest points de vie en début de tour si elle n'a pas bougé pendant le
+    dernier tour
   */
   signal final_state[state_size];
   component au_repos[state_size];
@@ -246,17 +249,17 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
     max_health[i] = VillagePos(nb_troupes, hp_troupes);
     max_health[i].index <== degats_state[i][0];
     if (est_village) {
-      full_heal[i].in[0] <== degats_state[i][1] + 8 + au_repos[i].out * 2;
+      full_heal[i].in[0] <== degats_state[i][1] + heal_village + au_repos[i].out * heal_rest;
       full_heal[i].in[1] <== max_health[i].out;
 
       final_state[i] <== max_health[i].out +
-        full_heal[i].out * (degats_state[i][1] + 8 + (au_repos[i].out * 2) - max_health[i].out);
+        full_heal[i].out * (degats_state[i][1] + heal_village + (au_repos[i].out * heal_rest) - max_health[i].out);
     } else {
-      full_heal[i].in[0] <== degats_state[i][1] + au_repos[i].out * 2;
+      full_heal[i].in[0] <== degats_state[i][1] + au_repos[i].out * heal_rest;
       full_heal[i].in[1] <== max_health[i].out;
 
       final_state[i] <== max_health[i].out +
-        full_heal[i].out * (degats_state[i][1] +  (au_repos[i].out * 2) - max_health[i].out);
+        full_heal[i].out * (degats_state[i][1] + (au_repos[i].out * heal_rest) - max_health[i].out);
     }
   }
 
@@ -270,19 +273,21 @@ template Final(state_size, state_height, state_width, actions_size, enum_tag, en
      If the upkeep cost is greater than the number of villages+2 then the side
      starts losing gold, if it is equal, no income is gained or lost.
      """
+     Les 2, le gain par village et l'upkeep supporté par chaque village sont
+     les paramètres base_income, village_gold et village_support.
   */
   signal final_money;
   signal degats_money;
   component pay_upkeep = LessThan(64); // 1 si on paie l'upkeep
-  pay_upkeep.in[0] <== prev_misc_state[1] - pertes_villages[nb_villages - 1];
+  pay_upkeep.in[0] <== village_support * (prev_misc_state[1] - pertes_villages[nb_villages - 1]);
   pay_upkeep.in[1] <== degats_state_upkeep[state_size - 1];
   // TODO : ON PEUT AVOIR DES DETTES, CETTE VERSION TE FERA MOURIR SI TU PAIES
   // PAS TES DETTES POUR L'INSTANT ET C'EST TOUT
   // Gérer avec un flag ?
   component floor_zero = LessThan(64); // 1 si on peut payer sans tomber dans le rouge
-  floor_zero.in[0] <== pay_upkeep.out * (degats_state_upkeep[state_size - 1] - (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
-  floor_zero.in[1] <== prev_misc_state[0] + 2 + prev_misc_state[1] - pertes_villages[nb_villages - 1];
-  degats_money <== prev_misc_state[0] + 2 + prev_misc_state[1] - pertes_villages[nb_villages - 1] - pay_upkeep.out * (degats_state_upkeep[state_size - 1] - (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
+  floor_zero.in[0] <== pay_upkeep.out * (degats_state_upkeep[state_size - 1] - village_support * (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
+  floor_zero.in[1] <== prev_misc_state[0] + base_income + village_gold * (prev_misc_state[1] - pertes_villages[nb_villages - 1]);
+  degats_money <== prev_misc_state[0] + base_income + village_gold * (prev_misc_state[1] - pertes_villages[nb_villages - 1]) - pay_upkeep.out * (degats_state_upkeep[state_size - 1] - village_support * (prev_misc_state[1] - pertes_villages[nb_villages - 1]));
   final_money <== floor_zero.out * degats_money;
 
   /* Hachage des cases
@@ -401,7 +406,9 @@ component main {public [phase1_received, degats, captures, actions_captures]} =
     [0,5,5,6,7,5,4,8,5], // Range
     [0,-1,14,17,14,12,13,17,9], // Prix
   2, [0, 99], // Donjons
-  6, [[0,1], [0,10], [0,20], [1,89], [1,98], [1,79]]); // Chateaux
+  6, [[0,1], [0,10], [0,20], [1,89], [1,98], [1,79]], // Chateaux
+  2, 2, 1, 8, 2); // Revenu, or et upkeep par village, soins au village et au repos
 // template Final(state_size, state_height, state_width, actions_size, enum_tag, enum_data, mimc_hash_size,
 //   nb_villages, pos_villages, nb_troupes, hp_troupes, range_troupes, prix_troupes,
-//   nb_donjons, donjons, nb_chateaux, chateaux) {
+//   nb_donjons, donjons, nb_chateaux, chateaux,
+//   base_income, village_gold, village_support, heal_village, heal_rest) {
@@ -231,10 +231,12 @@ template Phase2(state_size, enum_tag, enum_data) {
       }
     }
   }
-  for (var i = 0; i < state_size; i++) { log(phase2_dh_output[i][0],phase2_dh_output[i][1]); }
-  for (var i = 0; i < state_size; i++) { log(phase2_hidden_tags[i][0],phase2_hidden_tags[i][1]); }
+  // Chaque ligne de sortie est « groupe indice valeurs... », voir output.rs
+  for (var i = 0; i < state_size; i++) { log("dh", i, phase2_dh_output[i][0], phase2_dh_output[i][1]); }
+  for (var i = 0; i < state_size; i++) { log("tag", i, phase2_hidden_tags[i][0], phase2_hidden_tags[i][1]); }
   for (var i = 0; i < state_size; i++) {
     log(
+      "data", i,
       bits_to_number(phase2_hidden_data[i][0]),
       bits_to_number(phase2_hidden_data[i][1]),
       bits_to_number(phase2_hidden_data[i][2])
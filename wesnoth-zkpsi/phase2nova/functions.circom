@@ -10,8 +10,8 @@ pragma circom 2.1.8;
 function max(x,y) { return x > y ? x : y; }
 function min(x,y) { return x > y ? y : x; }
 function abs(x) { return x > 0 ? x : -x; }
-function ax_to_rect(c) { return [c[0], (c[1] + (c[0] - (c[0] % 2))) \ 2]; }
-function rect_to_ax(c) { return [c[0], (c[1] - (c[0] - (c[0] % 2))) \ 2]; }
+function ax_to_rect(c) { return [c[0], c[1] + (c[0] - (c[0] % 2)) \ 2]; }
+function rect_to_ax(c) { return [c[0], c[1] - (c[0] - (c[0] % 2)) \ 2]; }
 function dist_ax(one,two) {
   var qdiff = (one[0] - two[0]);
   var rdiff = (one[1] - two[1]);
@@ -31,14 +31,17 @@ function neighbour_rect(coord, dir){
       // odd cols
       [[1,0], [0,-1], [-1,0], [-1,1], [0,1], [1,1]]
   ];
-  var is_odd = coord[0] % 2;
+  // Les colonnes négatives sont traversées en faisant le tour des bords, or
+  // un négatif est un grand élément du corps dont la parité est inversée
+  var is_odd = abs(coord[0]) % 2;
   return [coord[0] + rect_dirs[is_odd][dir][0], coord[1] + rect_dirs[is_odd][dir][1]];
 }
 
 function nb_neighbours(state_height, state_width, x, y, radius) {
-  if (min(x, state_height - x) <= radius && min(y, state_width - y) <= radius) {
-    // Formule pour un hexagone complet
-    return 1 + (3 * radius * (radius + 1));
+  // Voisins sans la case elle-même
+  if (radius <= x && x + radius < state_height && radius <= y && y + radius < state_width) {
+    // Formule pour un hexagone complet, loin des bords
+    return 3 * radius * (radius + 1);
   } else {
     // Sinon, il faut bourriner ...
     var count = 0;
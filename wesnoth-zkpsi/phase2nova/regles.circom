@@ -43,26 +43,26 @@ template Distance() {
   signal input in2[2];
   signal output out;
 
+  // Les colonnes impaires sont descendues (odd-q) : en axial, q = x et
+  // r = y - x \ 2
   component div1 = Div2();
   div1.in <== in1[0];
   component div2 = Div2();
   div2.in <== in2[0];
 
-  // Les secondes coordonnées
-  component ax1 = Div2();
-  ax1.in <== in1[1] + 2 * div1.quotient;
-  component ax2 = Div2();
-  ax2.in <== in2[1] + 2 * div2.quotient;
-
+  // Les différences sont réarrangées pour ne comparer que des positifs
+  // |q1 - q2|
   component qdiff = Diff();
   qdiff.in1 <== in1[0];
   qdiff.in2 <== in2[0];
+  // |r1 - r2| = |(y1 + x2 \ 2) - (y2 + x1 \ 2)|
   component rdiff = Diff();
-  rdiff.in1 <== ax1.quotient;
-  rdiff.in2 <== ax2.quotient;
+  rdiff.in1 <== in1[1] + div2.quotient;
+  rdiff.in2 <== in2[1] + div1.quotient;
+  // |(q1 + r1) - (q2 + r2)|
   component sumdiff = Diff();
-  sumdiff.in1 <== in1[0] + ax1.quotient;
-  sumdiff.in2 <== in2[0] + ax2.quotient;
+  sumdiff.in1 <== in1[0] + in1[1] + div2.quotient;
+  sumdiff.in2 <== in2[0] + in2[1] + div1.quotient;
 
   component dist = Div2();
   dist.in <== qdiff.out + sumdiff.out + rdiff.out;
@@ -234,7 +234,7 @@ template Regles(state_size, state_height, state_width, nb_villages, pos_villages
 
   // Le paramètre est le nombre de bits des arguments
   // 64 devrait être OK pour ce qui est à l'intérieur des cases de l'état
-  component contrainte_move = LessThan(64);
+  component contrainte_move = LessEqThan(64);
   // Vérification de la distance de mouvement
   contrainte_move.in[0] <== dist.out;
   contrainte_move.in[1] <== unite_action.out[2]; // Déplacement restant
@@ -248,6 +248,8 @@ template Regles(state_size, state_height, state_width, nb_villages, pos_villages
   action_type2.in[1] <== action[0];
   // Accrochage de la bound sur les villages
   action_type2.out * (1 - bounds_capture.out) === 0;
+  // Accrochage de la distance de mouvement
+  action_type1.out * (1 - contrainte_move.out) === 0;
 
   /* Changements si on n'appelle pas une nouvelle unité */
   signal next_state_if_not_depart[state_size][3];
@@ -288,7 +290,7 @@ template Regles(state_size, state_height, state_width, nb_villages, pos_villages
 
     // HP de la case, changé uniquement par un mouvement
     next_state_if_not_depart[i][1] <==
-      prev_state[i][1] + est_destination[i].out * (unite_action.out[0] - prev_state[i][1]);
+      prev_state[i][1] + est_destination[i].out * (unite_action.out[1] - prev_state[i][1]);
     next_state_if_no_summon[i][1] <== (1 - est_depart[i].out) * next_state_if_not_depart[i][1];
 
     // État du village sur la case : à changer selon le village pris s'il y en a un
@@ -301,10 +303,10 @@ template Regles(state_size, state_height, state_width, nb_villages, pos_villages
     // points de fatigue
     /*
       (1 - est_destination[i].out) * prev_state[i][2]
-      + est_destination[i].out * (unite_action.out[0] - dist.out);
+      + est_destination[i].out * (unite_action.out[2] - dist.out);
     */
     next_state_if_not_depart[i][2] <==
-      prev_state[i][3] + est_destination[i].out * (unite_action.out[0] - dist.out - prev_state[i][3]);
+      prev_state[i][3] + est_destination[i].out * (unite_action.out[2] - dist.out - prev_state[i][3]);
     next_state[i][3] <== (1 - est_depart[i].out) * next_state_if_not_depart[i][2];
   }
 
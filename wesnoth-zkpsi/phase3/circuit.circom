@@ -93,10 +93,12 @@ template Final(state_size, enum_tag, enum_data) {
     }
   }
   // Hash des tags, à comparer pour voir ceux égaux aux reçus
-  for (var i = 0; i < state_size; i++) { log(own_hidden_tags[i].out[0],own_hidden_tags[i].out[1]); }
+  // Chaque ligne de sortie est « groupe indice valeurs... », voir output.rs
+  for (var i = 0; i < state_size; i++) { log("tag", i, own_hidden_tags[i].out[0], own_hidden_tags[i].out[1]); }
   // Candidats de sortie
   for (var i = 0; i < state_size; i++) {
     log(
+      "data", i,
       bits_to_number(own_hidden_data[i][0]),
       bits_to_number(own_hidden_data[i][1]),
       bits_to_number(own_hidden_data[i][2])
@@ -104,4 +106,4 @@ template Final(state_size, enum_tag, enum_data) {
   }
 }
 
-component main = Final(10 * 10,0,1);
+component main = Final(10 * 10, 0, 1);